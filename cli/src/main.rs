@@ -44,7 +44,7 @@ Usage:
     leaf-examples --version
 
 Options:
-    <model-name>            Which MNIST model to use. Valid values: [linear, mlp]
+    <model-name>            Which MNIST model to use. Valid values: [linear, mlp, conv]
 
     -h --help               Show this screen.
     --version               Show version.
@@ -148,12 +148,27 @@ fn run_mnist(model_name: String, batch_size: usize, learning_rate: f32, momentum
 
     match model_name.as_ref() {
         "conv" => {
-            unimplemented!()
+            net_cfg.add_layer(LayerConfig::new("reshape",
+                LayerType::Reshape(ReshapeConfig::of_shape(&[batch_size, 1, LEN, LEN]))));
+            net_cfg.add_layer(LayerConfig::new("conv",
+                LayerType::Convolution(ConvolutionConfig {
+                    num_output: 20, filter_shape: vec![5], padding: vec![0], stride: vec![1] })));
+            net_cfg.add_layer(LayerConfig::new("pooling",
+                LayerType::Pooling(PoolingConfig {
+                    mode: PoolingMode::Max, filter_shape: vec![2], padding: vec![0], stride: vec![2] })));
+            net_cfg.add_layer(LayerConfig::new("flatten",
+                LayerType::Flatten(FlattenConfig::default())));
+            net_cfg.add_layer(LayerConfig::new("linear1",
+                LayerType::Linear(LinearConfig { output_size: 500 })));
+            net_cfg.add_layer(LayerConfig::new("sigmoid",
+                LayerType::Sigmoid));
+            net_cfg.add_layer(LayerConfig::new("linear2",
+                LayerType::Linear(LinearConfig { output_size: 10 })));
         }
 
         "mlp" => {
-            net_cfg.add_layer(LayerConfig::new("reshape",
-                LayerType::Reshape(ReshapeConfig::of_shape(&[batch_size, LEN * LEN]))));
+            net_cfg.add_layer(LayerConfig::new("flatten",
+                LayerType::Flatten(FlattenConfig::default())));
             net_cfg.add_layer(LayerConfig::new("linear1",
                 LayerType::Linear(LinearConfig { output_size: 1568 })));
             net_cfg.add_layer(LayerConfig::new("sigmoid",
@@ -172,15 +187,13 @@ fn run_mnist(model_name: String, batch_size: usize, learning_rate: f32, momentum
         }
     }
 
-    net_cfg.add_layer(LayerConfig::new("log_softmax", LayerType::LogSoftmax));
-
     let mut classifier_cfg = SequentialConfig::default();
     classifier_cfg.add_input("network_out", &[batch_size, 10]);
     classifier_cfg.add_input("label", &[batch_size, 1]);
-    // set up nll loss
-    let nll_layer_cfg = NegativeLogLikelihoodConfig { num_classes: 10 };
-    let nll_cfg = LayerConfig::new("nll", LayerType::NegativeLogLikelihood(nll_layer_cfg));
-    classifier_cfg.add_layer(nll_cfg);
+    // set up softmax cross-entropy loss
+    let loss_layer_cfg = SoftmaxCrossEntropyConfig::new(10);
+    let loss_cfg = LayerConfig::new("loss", LayerType::SoftmaxCrossEntropy(loss_layer_cfg));
+    classifier_cfg.add_layer(loss_cfg);
 
     // set up backends
     
@@ -0,0 +1,358 @@
+//! Provides a finite-difference gradient checker for [Layers][layers].
+//!
+//! The checker verifies the backward pass of a layer numerically: it projects all outputs of
+//! the layer onto fixed random directions `r`, which gives the scalar objective `L = Σ r · y`,
+//! and compares the gradients computed by [backward_input][backward_input] and
+//! [backward_parameters][backward_parameters] with the central differences
+//! `(L(x + h) - L(x - h)) / 2h` of every input and weight value.
+//!
+//! Loss layers ignore the gradient of their output, so for outputs that have a
+//! [loss weight][loss_weight] the direction is always `1`.
+//!
+//! Layers that support it can also be checked while computing [in-place][in_place], where
+//! the backward pass has to recover what it needs from the output.
+//!
+//! [layers]: ../layers/index.html
+//! [backward_input]: ../layers/core/struct.Layer.html#method.backward_input
+//! [backward_parameters]: ../layers/core/struct.Layer.html#method.backward_parameters
+//! [loss_weight]: ../layers/core/trait.LayerWorker.html#method.loss_weight
+//! [in_place]: ../layers/core/trait.LayerWorker.html#method.compute_in_place
+
+use crate::layers::{Layer, LayerConfig, LayerType, Mode};
+use crate::typedefs::{ArcLockTensor, ArcLockTensorBlob, LeafBackend, WeightArcLockTensorBlob};
+
+use parenchyma::prelude::SharedTensor;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::{error, fmt};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone)]
+/// Describes an input tensor of the layer that is checked.
+pub struct CheckedInput {
+    /// The shape of the input.
+    pub shape: Vec<usize>,
+    /// The values of the input.
+    ///
+    /// If `None`, random values with an absolute value between `0.1` and `1` are used,
+    /// which keeps the values away from the kinks of e.g. ReLU.
+    pub data: Option<Vec<f32>>,
+    /// Whether the gradient with respect to the input is checked.
+    ///
+    /// Inputs like labels, targets, indices or masks are not differentiable.
+    pub differentiable: bool,
+}
+
+impl CheckedInput {
+    /// A differentiable input of `shape` that is filled with random values.
+    pub fn random(shape: &[usize]) -> CheckedInput {
+        CheckedInput {
+            shape: shape.to_vec(),
+            data: None,
+            differentiable: true,
+        }
+    }
+
+    /// A differentiable input of `shape` that holds the given values.
+    ///
+    /// Useful for layers that are only differentiable for some values,
+    /// e.g. max pooling needs distinct values.
+    pub fn values(shape: &[usize], data: &[f32]) -> CheckedInput {
+        CheckedInput {
+            shape: shape.to_vec(),
+            data: Some(data.to_vec()),
+            differentiable: true,
+        }
+    }
+
+    /// A non-differentiable input of `shape` that holds the given values,
+    /// e.g. the labels of a loss layer.
+    pub fn constant(shape: &[usize], data: &[f32]) -> CheckedInput {
+        CheckedInput {
+            shape: shape.to_vec(),
+            data: Some(data.to_vec()),
+            differentiable: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A gradient value that does not match its central difference.
+pub struct GradientMismatch {
+    /// The name of the checked tensor, e.g. `input 0` or the name of a weight.
+    pub tensor: String,
+    /// The index of the value inside the tensor.
+    pub index: usize,
+    /// The gradient computed by the backward pass of the layer.
+    pub analytical: f32,
+    /// The gradient computed by central differences.
+    pub numerical: f32,
+}
+
+impl fmt::Display for GradientMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Gradient of {} at index {} is {} but the central difference is {}",
+               self.tensor, self.index, self.analytical, self.numerical)
+    }
+}
+
+impl error::Error for GradientMismatch {
+    fn description(&self) -> &str {
+        "The gradient does not match its central difference"
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Compares the backward pass of a layer with central differences.
+pub struct GradientChecker {
+    /// The distance `h` by which every value is moved in both directions.
+    ///
+    /// Default: 0.01
+    pub step: f32,
+    /// The allowed difference between the gradients, relative to the
+    /// magnitude of the gradients (but at least absolute).
+    ///
+    /// Default: 0.01
+    pub tolerance: f32,
+    /// The [Mode][1] the layer is checked in.
+    /// [1]: ../layers/core/enum.Mode.html
+    ///
+    /// Default: Train
+    pub mode: Mode,
+    /// The seed for the random inputs and output directions.
+    ///
+    /// Default: 0
+    pub seed: u64,
+    /// Whether the output of the layer gets the name of its first input,
+    /// so a layer that supports it computes in-place.
+    ///
+    /// Ignored for container layers.
+    ///
+    /// Default: false
+    pub in_place: bool,
+    /// Whether the layer is created anew for every forward pass, with the learnable
+    /// weights of the checked layer copied into it.
+    ///
+    /// Layers that draw random values from a seeded generator, like Dropout in
+    /// training mode, then draw the same values in every pass.
+    ///
+    /// Default: false
+    pub recreate_layer: bool,
+}
+
+impl Default for GradientChecker {
+    fn default() -> GradientChecker {
+        GradientChecker {
+            step: 0.01f32,
+            tolerance: 0.01f32,
+            mode: Mode::Train,
+            seed: 0,
+            in_place: false,
+            recreate_layer: false,
+        }
+    }
+}
+
+impl GradientChecker {
+    /// Checks the layer described by `config` with random, differentiable inputs of `input_shapes`.
+    pub fn check(&self, backend: Rc<LeafBackend>, config: &LayerConfig, input_shapes: &[&[usize]]) -> Result<(), GradientMismatch> {
+        let inputs = input_shapes.iter().map(|shape| CheckedInput::random(shape)).collect::<Vec<_>>();
+        self.check_inputs(backend, config, &inputs)
+    }
+
+    /// Checks the layer described by `config` with the described `inputs`.
+    ///
+    /// The gradients of all differentiable inputs and all learnable weights are checked.
+    /// Container layers receive the inputs in the order of their configured inputs, all
+    /// other layers are connected to the inputs in the given order.
+    pub fn check_inputs(&self, backend: Rc<LeafBackend>, config: &LayerConfig, inputs: &[CheckedInput]) -> Result<(), GradientMismatch> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let input_tensors = inputs.iter().map(|input| {
+            let data = match input.data {
+                Some(ref data) => data.clone(),
+                None => {
+                    let capacity = input.shape.iter().fold(1, |prod, i| prod * i);
+                    (0..capacity).map(|_| random_value(&mut rng)).collect::<Vec<_>>()
+                }
+            };
+            let mut tensor = SharedTensor::<f32>::from(&input.shape[..]);
+            tensor.write_slice(&data[..]).unwrap();
+            Arc::new(RwLock::new(tensor))
+        }).collect::<Vec<_>>();
+
+        let mut layer = self.create_layer(backend.clone(), config, &input_tensors);
+
+        // the analytical gradients
+        let outputs = self.forward(&mut layer, &input_tensors);
+        let directions = outputs.iter().enumerate().map(|(output_id, output)| {
+            let capacity = output.read().unwrap().shape().capacity();
+            if layer.worker.loss_weight(output_id).is_some() {
+                vec![1f32; capacity]
+            } else {
+                (0..capacity).map(|_| rng.gen_range(-1f32, 1f32)).collect::<Vec<_>>()
+            }
+        }).collect::<Vec<_>>();
+        let output_gradients = outputs.iter().enumerate().zip(&directions).map(|((output_id, output), direction)| {
+            // in-place the output gradient is the input gradient, which the layer transforms in place
+            let gradient = if layer.is_using_in_place() {
+                layer.output_blobs_gradient[output_id].clone()
+            } else {
+                Arc::new(RwLock::new(SharedTensor::<f32>::from(output.read().unwrap().shape().dimensions())))
+            };
+            gradient.write().unwrap().write_slice(&direction[..]).unwrap();
+            gradient
+        }).collect::<Vec<_>>();
+
+        layer.clear_weights_gradients();
+        let input_gradients = layer.backward_input(&output_gradients);
+        layer.backward_parameters();
+
+        let mut checked = Vec::new();
+        for (input_id, input) in inputs.iter().enumerate() {
+            if input.differentiable {
+                checked.push((format!("input {}", input_id), input_tensors[input_id].clone(), copy_values(&input_gradients[input_id])));
+            }
+        }
+        let weights = layer.learnable_weights_data();
+        let weights_gradients = layer.learnable_weights_gradients();
+        let weights_names = layer.learnable_weights_names();
+        for ((weight, gradient), name) in weights.into_iter().zip(&weights_gradients).zip(weights_names) {
+            checked.push((name, weight, copy_values(gradient)));
+        }
+
+        // the numerical gradients
+        for (name, tensor, analytical) in checked {
+            for (index, &analytical) in analytical.iter().enumerate() {
+                let numerical = self.central_difference(&backend, config, &mut layer, &input_tensors, &directions, &tensor, index);
+                let scale = 1f32.max(analytical.abs()).max(numerical.abs());
+                if (analytical - numerical).abs() > self.tolerance * scale {
+                    return Err(GradientMismatch {
+                        tensor: name,
+                        index: index,
+                        analytical: analytical,
+                        numerical: numerical,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the layer in the checked [Mode][1] and connects it to the `inputs`.
+    /// [1]: ../layers/core/enum.Mode.html
+    ///
+    /// Container layers create their own inputs from their config.
+    /// A layer that computes in-place gets its own copy of the first input,
+    /// since it overwrites it.
+    fn create_layer(&self, backend: Rc<LeafBackend>, config: &LayerConfig, inputs: &[ArcLockTensor]) -> Layer {
+        let mut layer = match config.layer_type {
+            LayerType::Sequential(_) | LayerType::Graph(_) => Layer::from_config(backend, config),
+            _ => {
+                let mut layer_config = config.clone();
+                layer_config.inputs = (0..inputs.len()).map(|input_id| format!("gradient_check_input_{}", input_id)).collect();
+                if self.in_place {
+                    layer_config.outputs = vec![layer_config.inputs[0].clone()];
+                } else if layer_config.outputs.is_empty() {
+                    layer_config.add_output("gradient_check_output");
+                }
+
+                let mut registry: HashMap<String, ArcLockTensorBlob> = HashMap::new();
+                for (input_id, (name, input)) in layer_config.inputs.iter().zip(inputs).enumerate() {
+                    let data = if self.in_place && input_id == 0 {
+                        let mut data = SharedTensor::<f32>::from(input.read().unwrap().shape().dimensions());
+                        data.write_slice(&copy_values(input)[..]).unwrap();
+                        Arc::new(RwLock::new(data))
+                    } else {
+                        input.clone()
+                    };
+                    let gradient = SharedTensor::<f32>::from(input.read().unwrap().shape().dimensions());
+                    registry.insert(name.clone(), (data, Arc::new(RwLock::new(gradient))));
+                }
+                let mut weight_registry: HashMap<String, WeightArcLockTensorBlob> = HashMap::new();
+
+                let mut layer = Layer::from_config(backend, &layer_config);
+                layer.connect(&mut registry, &mut weight_registry);
+                if self.in_place && !layer.is_using_in_place() {
+                    panic!("Layer {} can not be checked in-place, since it does not compute in-place.", layer.name);
+                }
+                layer
+            }
+        };
+        layer.set_mode(self.mode);
+        layer
+    }
+
+    /// Runs a forward pass of `layer` with the values of `inputs`.
+    ///
+    /// A layer that computes in-place overwrites its input, so the values are copied into it first.
+    fn forward(&self, layer: &mut Layer, inputs: &[ArcLockTensor]) -> Vec<ArcLockTensor> {
+        if layer.is_using_in_place() {
+            let connected_inputs = layer.input_blobs_data.clone();
+            for (connected_input, input) in connected_inputs.iter().zip(inputs) {
+                connected_input.write().unwrap().write_slice(&copy_values(input)[..]).unwrap();
+            }
+            layer.forward(&connected_inputs)
+        } else {
+            layer.forward(inputs)
+        }
+    }
+
+    /// Computes the objective `Σ r · y` of a forward pass.
+    fn evaluate(&self,
+                backend: &Rc<LeafBackend>,
+                config: &LayerConfig,
+                layer: &mut Layer,
+                inputs: &[ArcLockTensor],
+                directions: &[Vec<f32>]) -> f64 {
+        if self.recreate_layer {
+            let mut recreated = self.create_layer(backend.clone(), config, inputs);
+            for (recreated_weight, weight) in recreated.learnable_weights_data().iter().zip(&layer.learnable_weights_data()) {
+                recreated_weight.write().unwrap().write_slice(&copy_values(weight)[..]).unwrap();
+            }
+            objective(&self.forward(&mut recreated, inputs), directions)
+        } else {
+            objective(&self.forward(layer, inputs), directions)
+        }
+    }
+
+    /// Computes `(L(x + h) - L(x - h)) / 2h` for the value at `index` of `tensor`.
+    fn central_difference(&self,
+                          backend: &Rc<LeafBackend>,
+                          config: &LayerConfig,
+                          layer: &mut Layer,
+                          inputs: &[ArcLockTensor],
+                          directions: &[Vec<f32>],
+                          tensor: &ArcLockTensor,
+                          index: usize) -> f32 {
+        let original = tensor.read().unwrap().as_slice().unwrap()[index];
+
+        tensor.write().unwrap().as_mut_slice().unwrap()[index] = original + self.step;
+        let positive = self.evaluate(backend, config, layer, inputs, directions);
+        tensor.write().unwrap().as_mut_slice().unwrap()[index] = original - self.step;
+        let negative = self.evaluate(backend, config, layer, inputs, directions);
+        tensor.write().unwrap().as_mut_slice().unwrap()[index] = original;
+
+        ((positive - negative) / (2f64 * self.step as f64)) as f32
+    }
+}
+
+/// A random value with an absolute value between `0.1` and `1`.
+fn random_value<R: Rng>(rng: &mut R) -> f32 {
+    let value = rng.gen_range(0.1f32, 1f32);
+    if rng.gen() { value } else { -value }
+}
+
+/// The projection `Σ r · y` of the outputs onto the directions.
+fn objective(outputs: &[ArcLockTensor], directions: &[Vec<f32>]) -> f64 {
+    outputs.iter().zip(directions).fold(0f64, |sum, (output, direction)| {
+        let output = output.read().unwrap();
+        output.as_slice().unwrap().iter().zip(direction).fold(sum, |sum, (&y, &r)| sum + y as f64 * r as f64)
+    })
+}
+
+fn copy_values(tensor: &ArcLockTensor) -> Vec<f32> {
+    tensor.read().unwrap().as_slice().unwrap().to_vec()
+}
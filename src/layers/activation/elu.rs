@@ -0,0 +1,142 @@
+//! Applies the Exponential Linear Unit.
+//!
+//! Non-linearity activation function: y = x if x > 0, otherwise y = alpha * (exp(x) - 1)
+//!
+//! Like ReLU it is the identity for positive inputs, but it saturates smoothly to `-alpha`
+//! for negative inputs, which pushes the mean activation towards zero.
+//!
+//! Can be computed in-place as long as `alpha` is positive.
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::elu_config as capnp_config;
+use crate::layers::core::{ComputeInputGradient, ComputeOutput, ComputeParametersGradient, LayerType};
+use crate::typedefs::LeafBackend;
+
+use parenchyma::prelude::SharedTensor;
+
+/// ELU Activation Layer
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone)]
+pub struct ELU {
+    alpha: f32,
+}
+
+impl ELU {
+    /// Create a ELU layer from a ELUConfig.
+    pub fn from_config(config: &ELUConfig) -> ELU {
+        ELU {
+            alpha: config.alpha,
+        }
+    }
+}
+
+impl super::ActivationLayer for ELU {
+    fn supports_in_place(&self) -> bool {
+        // only a positive alpha keeps the sign of the input
+        self.alpha > 0f32
+    }
+}
+
+impl ComputeOutput<f32> for ELU {
+    fn compute_output(&self,
+        backend: &LeafBackend,
+        _weights: &[&SharedTensor<f32>],
+        input_data: &[&SharedTensor<f32>],
+        output_data: &mut [&mut SharedTensor<f32>]) {
+
+        let alpha = self.alpha;
+        let elu = |x: f32| if x > 0f32 { x } else { alpha * x.exp_m1() };
+        match input_data.get(0) {
+            Some(input) => {
+                let output = input.as_slice().unwrap().iter().map(|&x| elu(x)).collect::<Vec<_>>();
+                output_data[0].write_slice(&output[..]).unwrap();
+            }
+
+            None => {
+                // in-place: the output already holds the input
+                for value in output_data[0].as_mut_slice().unwrap().iter_mut() {
+                    *value = elu(*value);
+                }
+            }
+        }
+    }
+}
+
+impl ComputeInputGradient<f32> for ELU {
+    fn compute_input_gradient(&self,
+        backend: &LeafBackend,
+        weights_data: &[&SharedTensor<f32>],
+        output_data: &[&SharedTensor<f32>],
+        output_gradients: &[&SharedTensor<f32>],
+        input_data: &[&SharedTensor<f32>],
+        input_gradients: &mut [&mut SharedTensor<f32>]) {
+
+        let alpha = self.alpha;
+        match output_gradients.get(0) {
+            Some(output_gradient) => {
+                let input = input_data[0].as_slice().unwrap();
+                let gradient = output_gradient.as_slice().unwrap().iter().zip(input)
+                    .map(|(&dy, &x)| if x > 0f32 { dy } else { dy * alpha * x.exp() })
+                    .collect::<Vec<_>>();
+                input_gradients[0].write_slice(&gradient[..]).unwrap();
+            }
+
+            None => {
+                // in-place: the input holds the output `y` and the input gradient the
+                // output gradient, for negative inputs `alpha * exp(x) = y + alpha`.
+                let output = input_data[0].as_slice().unwrap();
+                for (grad, &y) in input_gradients[0].as_mut_slice().unwrap().iter_mut().zip(output) {
+                    if y <= 0f32 {
+                        *grad *= y + alpha;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl ComputeParametersGradient<f32> for ELU {
+    // ..
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a ELU Layer.
+pub struct ELUConfig {
+    /// The value the output saturates to for negative inputs is `-alpha`.
+    ///
+    /// Default: 1.0
+    pub alpha: f32,
+}
+
+impl Default for ELUConfig {
+    fn default() -> ELUConfig {
+        ELUConfig {
+            alpha: 1f32,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for ELUConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the ELUConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_alpha(self.alpha);
+    }
+}
+
+impl<'a> CapnpRead<'a> for ELUConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        ELUConfig {
+            alpha: reader.get_alpha(),
+        }
+    }
+}
+
+impl Into<LayerType> for ELUConfig {
+    fn into(self) -> LayerType {
+        LayerType::ELU(self)
+    }
+}
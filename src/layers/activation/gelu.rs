@@ -0,0 +1,94 @@
+//! Applies the Gaussian Error Linear Unit.
+//!
+//! Non-linearity activation function: y = x * Φ(x), where Φ is the cumulative
+//! distribution function of the standard normal distribution.
+//!
+//! It is computed with the tanh approximation
+//! y = 0.5 * x * (1 + tanh(sqrt(2 / π) * (x + 0.044715 * x³)))
+//!
+//! GELU weights inputs by their value instead of gating them by their sign like ReLU,
+//! and is popular in Transformer models.
+//!
+//! Since the function is not monotonic, the input can't be recovered from the output,
+//! so GELU can not be computed in-place.
+
+use crate::layers::core::{ComputeInputGradient, ComputeOutput, ComputeParametersGradient};
+use crate::typedefs::LeafBackend;
+
+use parenchyma::prelude::SharedTensor;
+
+/// sqrt(2 / π)
+const SQRT_2_OVER_PI: f32 = 0.7978845608;
+const COEFFICIENT: f32 = 0.044715;
+
+/// GELU Activation Layer
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone)]
+pub struct GELU;
+
+impl GELU {
+    fn inner(x: f32) -> f32 {
+        SQRT_2_OVER_PI * (x + COEFFICIENT * x * x * x)
+    }
+
+    fn gelu(x: f32) -> f32 {
+        0.5f32 * x * (1f32 + Self::inner(x).tanh())
+    }
+
+    fn derivative(x: f32) -> f32 {
+        let t = Self::inner(x).tanh();
+        let inner_derivative = SQRT_2_OVER_PI * (1f32 + 3f32 * COEFFICIENT * x * x);
+        0.5f32 * (1f32 + t) + 0.5f32 * x * (1f32 - t * t) * inner_derivative
+    }
+}
+
+impl super::ActivationLayer for GELU {
+    // ..
+}
+
+impl ComputeOutput<f32> for GELU {
+    fn compute_output(&self,
+        backend: &LeafBackend,
+        _weights: &[&SharedTensor<f32>],
+        input_data: &[&SharedTensor<f32>],
+        output_data: &mut [&mut SharedTensor<f32>]) {
+
+        let output = input_data[0].as_slice().unwrap().iter().map(|&x| Self::gelu(x)).collect::<Vec<_>>();
+        output_data[0].write_slice(&output[..]).unwrap();
+    }
+}
+
+impl ComputeInputGradient<f32> for GELU {
+    fn compute_input_gradient(&self,
+        backend: &LeafBackend,
+        weights_data: &[&SharedTensor<f32>],
+        output_data: &[&SharedTensor<f32>],
+        output_gradients: &[&SharedTensor<f32>],
+        input_data: &[&SharedTensor<f32>],
+        input_gradients: &mut [&mut SharedTensor<f32>]) {
+
+        let input = input_data[0].as_slice().unwrap();
+        let gradient = output_gradients[0].as_slice().unwrap().iter().zip(input)
+            .map(|(&dy, &x)| dy * Self::derivative(x))
+            .collect::<Vec<_>>();
+        input_gradients[0].write_slice(&gradient[..]).unwrap();
+    }
+}
+
+impl ComputeParametersGradient<f32> for GELU {
+    // ..
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GELU;
+
+    #[test]
+    fn derivative_matches_finite_difference() {
+        let h = 1e-3f32;
+        for &x in &[-2f32, -0.5f32, 0f32, 0.7f32, 3f32] {
+            let numeric = (GELU::gelu(x + h) - GELU::gelu(x - h)) / (2f32 * h);
+            assert!((GELU::derivative(x) - numeric).abs() < 1e-3);
+        }
+    }
+}
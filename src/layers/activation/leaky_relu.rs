@@ -0,0 +1,142 @@
+//! Applies the Leaky Rectified Linear Unit.
+//!
+//! Non-linearity activation function: y = x if x > 0, otherwise y = slope * x
+//!
+//! Unlike ReLU, the small `slope` for negative inputs keeps a gradient flowing,
+//! so units can't "die" by getting stuck in the negative range.
+//!
+//! Can be computed in-place as long as the slope is not negative.
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::leaky_relu_config as capnp_config;
+use crate::layers::core::{ComputeInputGradient, ComputeOutput, ComputeParametersGradient, LayerType};
+use crate::typedefs::LeafBackend;
+
+use parenchyma::prelude::SharedTensor;
+
+/// LeakyReLU Activation Layer
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone)]
+pub struct LeakyReLU {
+    slope: f32,
+}
+
+impl LeakyReLU {
+    /// Create a LeakyReLU layer from a LeakyReLUConfig.
+    pub fn from_config(config: &LeakyReLUConfig) -> LeakyReLU {
+        LeakyReLU {
+            slope: config.slope,
+        }
+    }
+}
+
+impl super::ActivationLayer for LeakyReLU {
+    fn supports_in_place(&self) -> bool {
+        // a negative slope flips the sign, so the input can't be told from the output
+        self.slope >= 0f32
+    }
+}
+
+impl ComputeOutput<f32> for LeakyReLU {
+    fn compute_output(&self,
+        backend: &LeafBackend,
+        _weights: &[&SharedTensor<f32>],
+        input_data: &[&SharedTensor<f32>],
+        output_data: &mut [&mut SharedTensor<f32>]) {
+
+        let slope = self.slope;
+        let leaky_relu = |x: f32| if x > 0f32 { x } else { slope * x };
+        match input_data.get(0) {
+            Some(input) => {
+                let output = input.as_slice().unwrap().iter().map(|&x| leaky_relu(x)).collect::<Vec<_>>();
+                output_data[0].write_slice(&output[..]).unwrap();
+            }
+
+            None => {
+                // in-place: the output already holds the input
+                for value in output_data[0].as_mut_slice().unwrap().iter_mut() {
+                    *value = leaky_relu(*value);
+                }
+            }
+        }
+    }
+}
+
+impl ComputeInputGradient<f32> for LeakyReLU {
+    fn compute_input_gradient(&self,
+        backend: &LeafBackend,
+        weights_data: &[&SharedTensor<f32>],
+        output_data: &[&SharedTensor<f32>],
+        output_gradients: &[&SharedTensor<f32>],
+        input_data: &[&SharedTensor<f32>],
+        input_gradients: &mut [&mut SharedTensor<f32>]) {
+
+        // with a non-negative slope, input and output have the same sign,
+        // so the derivative can be determined from either of them
+        let slope = self.slope;
+        let derivative = |value: f32| if value > 0f32 { 1f32 } else { slope };
+        match output_gradients.get(0) {
+            Some(output_gradient) => {
+                let input = input_data[0].as_slice().unwrap();
+                let gradient = output_gradient.as_slice().unwrap().iter().zip(input)
+                    .map(|(&dy, &x)| dy * derivative(x))
+                    .collect::<Vec<_>>();
+                input_gradients[0].write_slice(&gradient[..]).unwrap();
+            }
+
+            None => {
+                // in-place: the input holds the output and the input gradient the output gradient
+                let output = input_data[0].as_slice().unwrap();
+                for (grad, &y) in input_gradients[0].as_mut_slice().unwrap().iter_mut().zip(output) {
+                    *grad *= derivative(y);
+                }
+            }
+        }
+    }
+}
+
+impl ComputeParametersGradient<f32> for LeakyReLU {
+    // ..
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a LeakyReLU Layer.
+pub struct LeakyReLUConfig {
+    /// The slope for negative inputs.
+    ///
+    /// Default: 0.01
+    pub slope: f32,
+}
+
+impl Default for LeakyReLUConfig {
+    fn default() -> LeakyReLUConfig {
+        LeakyReLUConfig {
+            slope: 0.01f32,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for LeakyReLUConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the LeakyReLUConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_slope(self.slope);
+    }
+}
+
+impl<'a> CapnpRead<'a> for LeakyReLUConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        LeakyReLUConfig {
+            slope: reader.get_slope(),
+        }
+    }
+}
+
+impl Into<LayerType> for LeakyReLUConfig {
+    fn into(self) -> LayerType {
+        LayerType::LeakyReLU(self)
+    }
+}
@@ -22,12 +22,24 @@
 //! [mod_relu]: ./relu/index.html
 //! [struct_layerconfig]: ../../layer/struct.LayerConfig.html
 
+pub use self::elu::{ELU, ELUConfig};
+pub use self::gelu::GELU;
+pub use self::leaky_relu::{LeakyReLU, LeakyReLUConfig};
+pub use self::prelu::{PReLU, PReLUConfig};
 pub use self::relu::ReLU;
 pub use self::sigmoid::Sigmoid;
+pub use self::softplus::Softplus;
+pub use self::swish::Swish;
 pub use self::tanh::TanH;
 
+pub mod elu;
+pub mod gelu;
+pub mod leaky_relu;
+pub mod prelu;
 pub mod relu;
 pub mod sigmoid;
+pub mod softplus;
+pub mod swish;
 pub mod tanh;
 
 use crate::typedefs::{ArcLockTensor, LeafBackend};
@@ -41,7 +53,14 @@ use super::core::{ComputeInputGradient, ComputeOutput, ComputeParametersGradient
 pub trait ActivationLayer: ComputeParametersGradient<f32> 
     + ComputeOutput<f32>
     + ComputeInputGradient<f32>  {
-    // ...
+    /// Return wether the activation can be computed in-place.
+    ///
+    /// Activations returning `true` have to handle a missing input in `compute_output` and
+    /// missing outputs in `compute_input_gradient`, see [LayerWorker.compute_in_place][1].
+    /// [1]: ../core/trait.LayerWorker.html#method.compute_in_place
+    fn supports_in_place(&self) -> bool {
+        false
+    }
 }
 
 impl<A> LayerWorker for A where A: ActivationLayer {
@@ -53,6 +72,10 @@ impl<A> LayerWorker for A where A: ActivationLayer {
         return Some(1);
     }
 
+    fn compute_in_place(&self) -> bool {
+        self.supports_in_place()
+    }
+
     fn reshape(&mut self,
         backend: ::std::rc::Rc<LeafBackend>,
         input_data: &mut Vec<ArcLockTensor>,
@@ -0,0 +1,219 @@
+//! Applies the Parametric Rectified Linear Unit.
+//!
+//! Non-linearity activation function: y = x if x > 0, otherwise y = a * x
+//!
+//! Like [LeakyReLU][leaky_relu], but the slope `a` for negative inputs is a learnable weight.
+//! By default every channel (axis 1 of the input) learns its own slope,
+//! alternatively one slope can be shared by all values.
+//!
+//! Since the slope is learned and may become negative, PReLU can not be computed in-place.
+//!
+//! [leaky_relu]: ../leaky_relu/index.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::prelu_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+use crate::weight::FillerType;
+
+use parenchyma::prelude::SharedTensor;
+
+/// PReLU Activation Layer
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone)]
+pub struct PReLU {
+    shared: bool,
+    initial_slope: f32,
+}
+
+impl PReLU {
+    /// Create a PReLU layer from a PReLUConfig.
+    pub fn from_config(config: &PReLUConfig) -> PReLU {
+        PReLU {
+            shared: config.shared,
+            initial_slope: config.initial_slope,
+        }
+    }
+
+    /// Returns the number of slopes and the number of consecutive values that share a slope.
+    fn calculate_dims(&self, input_shape: &[usize]) -> (usize, usize) {
+        if self.shared || input_shape.len() < 2 {
+            (1, input_shape.iter().fold(1, |prod, i| prod * i))
+        } else {
+            (input_shape[1], input_shape.iter().skip(2).fold(1, |prod, i| prod * i))
+        }
+    }
+
+    /// Calls `f(slope_index, index)` for every value of the input.
+    fn for_each_value<F: FnMut(usize, usize)>(&self, input_shape: &[usize], mut f: F) {
+        let (num_slopes, inner_num) = self.calculate_dims(input_shape);
+        let capacity = input_shape.iter().fold(1, |prod, i| prod * i);
+        for index in 0..capacity {
+            f((index / inner_num) % num_slopes, index);
+        }
+    }
+}
+
+impl LayerWorker for PReLU {
+    fn exact_num_output_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn exact_num_input_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn auto_weight_blobs(&self) -> bool {
+        true
+    }
+
+    fn exact_num_weight_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let input = input_data[0].read().unwrap();
+        let input_shape = input.shape().clone();
+        input_gradient[0].write().unwrap().resize(input_shape.clone()).unwrap();
+        output_data[0].write().unwrap().resize(input_shape.clone()).unwrap();
+        output_gradient[0].write().unwrap().resize(input_shape.clone()).unwrap();
+
+        let (num_slopes, _) = self.calculate_dims(input_shape.dimensions());
+        if let Some(weight) = weights_data.get(0) {
+            let mut weight = weight.write().unwrap();
+            weight.resize(&[num_slopes][..]).unwrap();
+            FillerType::Constant { value: self.initial_slope }.fill(&mut weight);
+        }
+        if let Some(gradient) = weights_gradient.get(0) {
+            gradient.write().unwrap().resize(&[num_slopes][..]).unwrap();
+        }
+    }
+}
+
+impl ComputeOutput<f32> for PReLU {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let input = input_data[0].as_slice().unwrap();
+        let slopes = weights[0].as_slice().unwrap();
+        let output = output_data[0].as_mut_slice().unwrap();
+        self.for_each_value(input_data[0].shape().dimensions(), |c, i| {
+            output[i] = if input[i] > 0f32 { input[i] } else { slopes[c] * input[i] };
+        });
+    }
+}
+
+impl ComputeInputGradient<f32> for PReLU {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let input = input_data[0].as_slice().unwrap();
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+        let slopes = weights_data[0].as_slice().unwrap();
+        let input_gradient = input_gradients[0].as_mut_slice().unwrap();
+        self.for_each_value(input_data[0].shape().dimensions(), |c, i| {
+            input_gradient[i] = if input[i] > 0f32 { output_gradient[i] } else { slopes[c] * output_gradient[i] };
+        });
+    }
+}
+
+impl ComputeParametersGradient<f32> for PReLU {
+    fn compute_parameters_gradient(&self,
+                                   backend: &LeafBackend,
+                                   output_data: &[&SharedTensor<f32>],
+                                   output_gradients: &[&SharedTensor<f32>],
+                                   input_data: &[&SharedTensor<f32>],
+                                   parameters_gradients: &mut [&mut SharedTensor<f32>]) {
+        let input_shape = input_data[0].shape().dimensions();
+        let input = input_data[0].as_slice().unwrap();
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+
+        // only negative inputs depend on the slope
+        let (num_slopes, _) = self.calculate_dims(input_shape);
+        let mut slope_gradient = vec![0f32; num_slopes];
+        self.for_each_value(input_shape, |c, i| {
+            if input[i] <= 0f32 {
+                slope_gradient[c] += output_gradient[i] * input[i];
+            }
+        });
+        parameters_gradients[0].write_slice(&slope_gradient[..]).unwrap();
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a PReLU Layer.
+pub struct PReLUConfig {
+    /// Whether all channels share a single slope.
+    ///
+    /// Default: false
+    pub shared: bool,
+    /// The value the slopes are initialized with.
+    ///
+    /// Default: 0.25
+    pub initial_slope: f32,
+}
+
+impl Default for PReLUConfig {
+    fn default() -> PReLUConfig {
+        PReLUConfig {
+            shared: false,
+            initial_slope: 0.25f32,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for PReLUConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the PReLUConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_shared(self.shared);
+        builder.set_initial_slope(self.initial_slope);
+    }
+}
+
+impl<'a> CapnpRead<'a> for PReLUConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        PReLUConfig {
+            shared: reader.get_shared(),
+            initial_slope: reader.get_initial_slope(),
+        }
+    }
+}
+
+impl Into<LayerType> for PReLUConfig {
+    fn into(self) -> LayerType {
+        LayerType::PReLU(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PReLU, PReLUConfig};
+
+    #[test]
+    fn one_slope_per_channel() {
+        let layer = PReLU::from_config(&PReLUConfig::default());
+        let mut slopes = Vec::new();
+        layer.for_each_value(&[2, 3, 2], |c, _| slopes.push(c));
+        assert_eq!(slopes, vec![0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2]);
+
+        let layer = PReLU::from_config(&PReLUConfig { shared: true, ..PReLUConfig::default() });
+        assert_eq!(layer.calculate_dims(&[2, 3, 2]), (1, 12));
+    }
+}
@@ -0,0 +1,88 @@
+//! Applies the Softplus function.
+//!
+//! Non-linearity activation function: y = ln(1 + exp(x))
+//!
+//! Softplus is a smooth approximation of ReLU whose derivative is the Sigmoid function.
+//!
+//! Can be computed in-place, since the derivative can be recovered from the output
+//! as `1 - exp(-y)`.
+
+use crate::layers::core::{ComputeInputGradient, ComputeOutput, ComputeParametersGradient};
+use crate::typedefs::LeafBackend;
+
+use parenchyma::prelude::SharedTensor;
+
+/// Softplus Activation Layer
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone)]
+pub struct Softplus;
+
+impl Softplus {
+    /// `ln(1 + exp(x))` without overflowing for large `x`.
+    fn softplus(x: f32) -> f32 {
+        x.max(0f32) + (-x.abs()).exp().ln_1p()
+    }
+}
+
+impl super::ActivationLayer for Softplus {
+    fn supports_in_place(&self) -> bool {
+        true
+    }
+}
+
+impl ComputeOutput<f32> for Softplus {
+    fn compute_output(&self,
+        backend: &LeafBackend,
+        _weights: &[&SharedTensor<f32>],
+        input_data: &[&SharedTensor<f32>],
+        output_data: &mut [&mut SharedTensor<f32>]) {
+
+        match input_data.get(0) {
+            Some(input) => {
+                let output = input.as_slice().unwrap().iter().map(|&x| Self::softplus(x)).collect::<Vec<_>>();
+                output_data[0].write_slice(&output[..]).unwrap();
+            }
+
+            None => {
+                // in-place: the output already holds the input
+                for value in output_data[0].as_mut_slice().unwrap().iter_mut() {
+                    *value = Self::softplus(*value);
+                }
+            }
+        }
+    }
+}
+
+impl ComputeInputGradient<f32> for Softplus {
+    fn compute_input_gradient(&self,
+        backend: &LeafBackend,
+        weights_data: &[&SharedTensor<f32>],
+        output_data: &[&SharedTensor<f32>],
+        output_gradients: &[&SharedTensor<f32>],
+        input_data: &[&SharedTensor<f32>],
+        input_gradients: &mut [&mut SharedTensor<f32>]) {
+
+        match output_gradients.get(0) {
+            Some(output_gradient) => {
+                let input = input_data[0].as_slice().unwrap();
+                let gradient = output_gradient.as_slice().unwrap().iter().zip(input)
+                    .map(|(&dy, &x)| dy / (1f32 + (-x).exp()))
+                    .collect::<Vec<_>>();
+                input_gradients[0].write_slice(&gradient[..]).unwrap();
+            }
+
+            None => {
+                // in-place: the input holds the output `y` and the input gradient
+                // the output gradient, so `dx = dy * sigmoid(x) = dy * (1 - exp(-y))`.
+                let output = input_data[0].as_slice().unwrap();
+                for (grad, &y) in input_gradients[0].as_mut_slice().unwrap().iter_mut().zip(output) {
+                    *grad *= -(-y).exp_m1();
+                }
+            }
+        }
+    }
+}
+
+impl ComputeParametersGradient<f32> for Softplus {
+    // ..
+}
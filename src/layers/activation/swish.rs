@@ -0,0 +1,65 @@
+//! Applies the Swish function (also known as SiLU).
+//!
+//! Non-linearity activation function: y = x * sigmoid(x)
+//!
+//! Swish is smooth and non-monotonic and often performs better than ReLU in deep networks.
+//!
+//! Since the function is not monotonic, the input can't be recovered from the output,
+//! so Swish can not be computed in-place.
+
+use crate::layers::core::{ComputeInputGradient, ComputeOutput, ComputeParametersGradient};
+use crate::typedefs::LeafBackend;
+
+use parenchyma::prelude::SharedTensor;
+
+/// Swish Activation Layer
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone)]
+pub struct Swish;
+
+impl Swish {
+    fn sigmoid(x: f32) -> f32 {
+        1f32 / (1f32 + (-x).exp())
+    }
+}
+
+impl super::ActivationLayer for Swish {
+    // ..
+}
+
+impl ComputeOutput<f32> for Swish {
+    fn compute_output(&self,
+        backend: &LeafBackend,
+        _weights: &[&SharedTensor<f32>],
+        input_data: &[&SharedTensor<f32>],
+        output_data: &mut [&mut SharedTensor<f32>]) {
+
+        let output = input_data[0].as_slice().unwrap().iter().map(|&x| x * Self::sigmoid(x)).collect::<Vec<_>>();
+        output_data[0].write_slice(&output[..]).unwrap();
+    }
+}
+
+impl ComputeInputGradient<f32> for Swish {
+    fn compute_input_gradient(&self,
+        backend: &LeafBackend,
+        weights_data: &[&SharedTensor<f32>],
+        output_data: &[&SharedTensor<f32>],
+        output_gradients: &[&SharedTensor<f32>],
+        input_data: &[&SharedTensor<f32>],
+        input_gradients: &mut [&mut SharedTensor<f32>]) {
+
+        // dy/dx = sigmoid(x) * (1 + x * (1 - sigmoid(x)))
+        let input = input_data[0].as_slice().unwrap();
+        let gradient = output_gradients[0].as_slice().unwrap().iter().zip(input)
+            .map(|(&dy, &x)| {
+                let sigmoid = Self::sigmoid(x);
+                dy * sigmoid * (1f32 + x * (1f32 - sigmoid))
+            })
+            .collect::<Vec<_>>();
+        input_gradients[0].write_slice(&gradient[..]).unwrap();
+    }
+}
+
+impl ComputeParametersGradient<f32> for Swish {
+    // ..
+}
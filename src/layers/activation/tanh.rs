@@ -22,7 +22,9 @@ use parenchyma::prelude::SharedTensor;
 pub struct TanH;
 
 impl super::ActivationLayer for TanH {
-    // ..
+    fn supports_in_place(&self) -> bool {
+        true
+    }
 }
 
 impl ComputeOutput<f32> for TanH {
@@ -36,9 +38,11 @@ impl ComputeOutput<f32> for TanH {
             Some(input) => backend.tanh(input, output_data[0]).unwrap(),
             
             None => {
-              panic!("No input provided for TanH layer.")
-              // TODO
-              // backend.tanh_pointwise(output_data[0]).unwrap()
+                // in-place: the output already holds the input
+                let values = output_data[0].as_mut_slice().unwrap();
+                for value in values.iter_mut() {
+                    *value = value.tanh();
+                }
             }
         }
     }
@@ -60,9 +64,13 @@ impl ComputeInputGradient<f32> for TanH {
             }
 
             None => {
-              panic!("No output_data provided for TanH layer backward.")
-              // TODO
-              // backend.tanh_pointwise_grad(input_data[0], input_gradients[0]).unwrap()
+                // in-place: the input holds the output `y` and the input gradient holds the
+                // output gradient, so `dx = dy * (1 - y^2)`.
+                let output = input_data[0].as_slice().unwrap();
+                let gradient = input_gradients[0].as_mut_slice().unwrap();
+                for (grad, &y) in gradient.iter_mut().zip(output.iter()) {
+                    *grad *= 1f32 - y * y;
+                }
             }
         }
     }
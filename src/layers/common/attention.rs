@@ -0,0 +1,706 @@
+//! Provides the attention layers of transformer networks ([Vaswani et al. 2017][paper]).
+//!
+//! [ScaledDotProductAttention][sdpa] takes a query `[N, T_q, E]`, a key `[N, T_k, E]` and
+//! a value `[N, T_k, E]` input and computes for every query position
+//!
+//! `y = softmax(q * transpose(K) / sqrt(E)) * V`
+//!
+//! [MultiHeadAttention][mha] applies self-attention to a sequence `[N, T, E]`. It projects the
+//! input with learnable weights into queries, keys and values, splits them into `num_heads`
+//! heads of size `E / num_heads`, attends in every head separately and projects the
+//! concatenated heads back with a learnable output projection. Its weights are named
+//! `query_weight`, `query_bias`, `key_weight`, ..., `output_bias`.
+//!
+//! Both layers support two kinds of masks:
+//!
+//! - `causal` masking, which prevents position `i` from attending to positions `j > i`,
+//!   as used for autoregressive decoding.
+//! - an optional padding-mask input `[N, T_k]` (the last input), where a `0` marks a key
+//!   position that is padding and must not be attended to. A query that can't attend to any
+//!   position outputs zeros.
+//!
+//! The layers keep the batch as first axis, like [LayerNorm][layer_norm] which can be applied
+//! to their output directly. To apply a [Linear][linear] layer at every position, reshape the
+//! sequence to `[N * T, E]` first.
+//!
+//! [paper]: https://arxiv.org/abs/1706.03762
+//! [sdpa]: ./struct.ScaledDotProductAttention.html
+//! [mha]: ./struct.MultiHeadAttention.html
+//! [layer_norm]: ../layer_norm/index.html
+//! [linear]: ../linear/index.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::multi_head_attention_config as capnp_mha_config;
+use crate::cerealization_protocol::scaled_dot_product_attention_config as capnp_sdpa_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+use crate::weight::FillerType;
+
+use parenchyma::prelude::SharedTensor;
+use std::cell::RefCell;
+
+/// The dimensions of an attention computation.
+#[derive(Debug, Copy, Clone)]
+struct AttentionShape {
+    batch_size: usize,
+    query_len: usize,
+    key_len: usize,
+    num_heads: usize,
+    head_size: usize,
+}
+
+impl AttentionShape {
+    fn embedding_size(&self) -> usize {
+        self.num_heads * self.head_size
+    }
+
+    /// The offset of `head` at position `t` of sample `n` in a `[N, len, E]` tensor.
+    fn offset(&self, n: usize, t: usize, len: usize, head: usize) -> usize {
+        (n * len + t) * self.embedding_size() + head * self.head_size
+    }
+
+    /// The offset of the attention probabilities of query `i` in `head` of sample `n`.
+    fn probabilities_offset(&self, n: usize, head: usize, i: usize) -> usize {
+        ((n * self.num_heads + head) * self.query_len + i) * self.key_len
+    }
+}
+
+/// Which key positions a query position must not attend to.
+#[derive(Debug)]
+struct AttentionMask<'a> {
+    causal: bool,
+    padding: Option<&'a [f32]>,
+}
+
+impl<'a> AttentionMask<'a> {
+    fn is_masked(&self, shape: &AttentionShape, n: usize, i: usize, j: usize) -> bool {
+        (self.causal && j > i) || self.padding.map_or(false, |padding| padding[n * shape.key_len + j] == 0f32)
+    }
+}
+
+fn dot(left: &[f32], right: &[f32]) -> f32 {
+    left.iter().zip(right).fold(0f32, |sum, (&l, &r)| sum + l * r)
+}
+
+/// Computes the attention output `[N, T_q, E]` and the attention probabilities `[N, H, T_q, T_k]`.
+fn attention_forward(shape: &AttentionShape,
+                     query: &[f32],
+                     key: &[f32],
+                     value: &[f32],
+                     mask: &AttentionMask) -> (Vec<f32>, Vec<f32>) {
+    let d = shape.head_size;
+    let scale = 1f32 / (d as f32).sqrt();
+    let mut output = vec![0f32; shape.batch_size * shape.query_len * shape.embedding_size()];
+    let mut probabilities = vec![0f32; shape.batch_size * shape.num_heads * shape.query_len * shape.key_len];
+
+    for n in 0..shape.batch_size {
+        for head in 0..shape.num_heads {
+            for i in 0..shape.query_len {
+                let q = &query[shape.offset(n, i, shape.query_len, head)..][..d];
+                let row = &mut probabilities[shape.probabilities_offset(n, head, i)..][..shape.key_len];
+
+                let mut max = ::std::f32::NEG_INFINITY;
+                for j in 0..shape.key_len {
+                    row[j] = if mask.is_masked(shape, n, i, j) {
+                        ::std::f32::NEG_INFINITY
+                    } else {
+                        dot(q, &key[shape.offset(n, j, shape.key_len, head)..][..d]) * scale
+                    };
+                    max = max.max(row[j]);
+                }
+                if max == ::std::f32::NEG_INFINITY {
+                    // every position is masked
+                    for p in row.iter_mut() {
+                        *p = 0f32;
+                    }
+                    continue;
+                }
+                let mut sum = 0f32;
+                for p in row.iter_mut() {
+                    *p = (*p - max).exp();
+                    sum += *p;
+                }
+
+                let out = &mut output[shape.offset(n, i, shape.query_len, head)..][..d];
+                for (j, p) in row.iter_mut().enumerate() {
+                    *p /= sum;
+                    let v = &value[shape.offset(n, j, shape.key_len, head)..][..d];
+                    for (o, &v) in out.iter_mut().zip(v) {
+                        *o += *p * v;
+                    }
+                }
+            }
+        }
+    }
+    (output, probabilities)
+}
+
+/// Computes the gradients w.r.t. the query, key and value of an attention computation.
+fn attention_backward(shape: &AttentionShape,
+                      query: &[f32],
+                      key: &[f32],
+                      value: &[f32],
+                      probabilities: &[f32],
+                      output_gradient: &[f32]) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    let d = shape.head_size;
+    let scale = 1f32 / (d as f32).sqrt();
+    let mut query_gradient = vec![0f32; query.len()];
+    let mut key_gradient = vec![0f32; key.len()];
+    let mut value_gradient = vec![0f32; value.len()];
+    let mut probabilities_gradient = vec![0f32; shape.key_len];
+
+    for n in 0..shape.batch_size {
+        for head in 0..shape.num_heads {
+            for i in 0..shape.query_len {
+                let query_offset = shape.offset(n, i, shape.query_len, head);
+                let row = &probabilities[shape.probabilities_offset(n, head, i)..][..shape.key_len];
+                let dy = &output_gradient[query_offset..][..d];
+
+                for j in 0..shape.key_len {
+                    probabilities_gradient[j] = dot(dy, &value[shape.offset(n, j, shape.key_len, head)..][..d]);
+                }
+                let weighted_sum = dot(row, &probabilities_gradient);
+
+                for (j, &p) in row.iter().enumerate() {
+                    if p == 0f32 {
+                        continue;
+                    }
+                    let key_offset = shape.offset(n, j, shape.key_len, head);
+                    // gradient w.r.t. the scaled score through the softmax
+                    let score_gradient = p * (probabilities_gradient[j] - weighted_sum) * scale;
+                    for c in 0..d {
+                        query_gradient[query_offset + c] += score_gradient * key[key_offset + c];
+                        key_gradient[key_offset + c] += score_gradient * query[query_offset + c];
+                        value_gradient[key_offset + c] += p * dy[c];
+                    }
+                }
+            }
+        }
+    }
+    (query_gradient, key_gradient, value_gradient)
+}
+
+/// Splits a `[N, T, E]` input shape into `(N, T, E)`.
+fn calculate_dims(input_shape: &[usize]) -> (usize, usize, usize) {
+    match input_shape.len() {
+        3 => (input_shape[0], input_shape[1], input_shape[2]),
+        _ => panic!("Attention layers expect inputs of shape [batch_size, seq_len, embedding_size]."),
+    }
+}
+
+/// Checks that the padding mask has the shape `[N, T_k]`.
+fn check_padding_mask(mask: &ArcLockTensor, batch_size: usize, key_len: usize) {
+    if mask.read().unwrap().shape().dimensions() != &[batch_size, key_len][..] {
+        panic!("The padding mask of an attention layer needs to have the shape [{}, {}].", batch_size, key_len);
+    }
+}
+
+/// `y = x * transpose(weight) + bias` for every row of `input` with `input_size` values.
+fn project(input: &[f32], input_size: usize, weight: &[f32], bias: &[f32]) -> Vec<f32> {
+    let mut output = Vec::with_capacity(input.len() / input_size * bias.len());
+    for x in input.chunks(input_size) {
+        for (row, &b) in weight.chunks(input_size).zip(bias) {
+            output.push(dot(row, x) + b);
+        }
+    }
+    output
+}
+
+/// Adds the gradient w.r.t. the input of a `project` with `output_size` outputs to `input_gradient`.
+fn add_project_input_gradient(output_gradient: &[f32], output_size: usize, weight: &[f32], input_gradient: &mut [f32]) {
+    let input_size = weight.len() / output_size;
+    for (dy, dx) in output_gradient.chunks(output_size).zip(input_gradient.chunks_mut(input_size)) {
+        for (&g, row) in dy.iter().zip(weight.chunks(input_size)) {
+            for (x, &w) in dx.iter_mut().zip(row) {
+                *x += g * w;
+            }
+        }
+    }
+}
+
+/// Computes the gradients w.r.t. the weight and the bias of a `project`.
+fn project_parameters_gradient(output_gradient: &[f32], output_size: usize, input: &[f32], input_size: usize) -> (Vec<f32>, Vec<f32>) {
+    let mut weight_gradient = vec![0f32; output_size * input_size];
+    let mut bias_gradient = vec![0f32; output_size];
+    for (dy, x) in output_gradient.chunks(output_size).zip(input.chunks(input_size)) {
+        for ((&g, row), b) in dy.iter().zip(weight_gradient.chunks_mut(input_size)).zip(bias_gradient.iter_mut()) {
+            *b += g;
+            for (w, &x) in row.iter_mut().zip(x) {
+                *w += g * x;
+            }
+        }
+    }
+    (weight_gradient, bias_gradient)
+}
+
+#[derive(Debug)]
+/// ScaledDotProductAttention Layer
+pub struct ScaledDotProductAttention {
+    causal: bool,
+
+    // the attention probabilities of the last forward pass
+    probabilities: RefCell<Vec<f32>>,
+}
+
+impl ScaledDotProductAttention {
+    /// Create a ScaledDotProductAttention layer from a ScaledDotProductAttentionConfig.
+    pub fn from_config(config: &ScaledDotProductAttentionConfig) -> ScaledDotProductAttention {
+        ScaledDotProductAttention {
+            causal: config.causal,
+
+            probabilities: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn calculate_shape(input_data: &[&SharedTensor<f32>]) -> AttentionShape {
+        let (batch_size, query_len, embedding_size) = calculate_dims(input_data[0].shape().dimensions());
+        AttentionShape {
+            batch_size: batch_size,
+            query_len: query_len,
+            key_len: input_data[1].shape().dimensions()[1],
+            num_heads: 1,
+            head_size: embedding_size,
+        }
+    }
+}
+
+impl LayerWorker for ScaledDotProductAttention {
+    fn exact_num_output_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        if input_data.len() < 3 || input_data.len() > 4 {
+            panic!("ScaledDotProductAttention layer expects a query, a key, a value and optionally a padding mask input.");
+        }
+        let query_shape = input_data[0].read().unwrap().shape().dimensions().to_owned();
+        let key_shape = input_data[1].read().unwrap().shape().dimensions().to_owned();
+        let (batch_size, _, embedding_size) = calculate_dims(&query_shape);
+        let (key_batch_size, key_len, key_size) = calculate_dims(&key_shape);
+        if key_batch_size != batch_size || key_size != embedding_size {
+            panic!("The key of a ScaledDotProductAttention layer needs to have the shape [{}, _, {}].", batch_size, embedding_size);
+        }
+        if input_data[2].read().unwrap().shape().dimensions() != &key_shape[..] {
+            panic!("The value of a ScaledDotProductAttention layer needs to have the shape of the key.");
+        }
+        if let Some(mask) = input_data.get(3) {
+            check_padding_mask(mask, batch_size, key_len);
+        }
+
+        for (input, gradient) in input_data.iter().zip(input_gradient.iter()) {
+            let shape = input.read().unwrap().shape().clone();
+            gradient.write().unwrap().resize(shape).unwrap();
+        }
+        output_data[0].write().unwrap().resize(&query_shape[..]).unwrap();
+        output_gradient[0].write().unwrap().resize(&query_shape[..]).unwrap();
+    }
+}
+
+impl ComputeOutput<f32> for ScaledDotProductAttention {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      _weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let shape = Self::calculate_shape(input_data);
+        let mask = AttentionMask {
+            causal: self.causal,
+            padding: input_data.get(3).map(|mask| mask.as_slice().unwrap()),
+        };
+        let (output, probabilities) = attention_forward(&shape,
+                                                        input_data[0].as_slice().unwrap(),
+                                                        input_data[1].as_slice().unwrap(),
+                                                        input_data[2].as_slice().unwrap(),
+                                                        &mask);
+
+        output_data[0].write_slice(&output[..]).unwrap();
+        *self.probabilities.borrow_mut() = probabilities;
+    }
+}
+
+impl ComputeInputGradient<f32> for ScaledDotProductAttention {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let shape = Self::calculate_shape(input_data);
+        let (query_gradient, key_gradient, value_gradient) = attention_backward(&shape,
+                                                                                input_data[0].as_slice().unwrap(),
+                                                                                input_data[1].as_slice().unwrap(),
+                                                                                input_data[2].as_slice().unwrap(),
+                                                                                &self.probabilities.borrow(),
+                                                                                output_gradients[0].as_slice().unwrap());
+
+        input_gradients[0].write_slice(&query_gradient[..]).unwrap();
+        input_gradients[1].write_slice(&key_gradient[..]).unwrap();
+        input_gradients[2].write_slice(&value_gradient[..]).unwrap();
+        if let Some(mask_gradient) = input_gradients.get_mut(3) {
+            // the mask is not differentiable
+            let zeros = vec![0f32; shape.batch_size * shape.key_len];
+            mask_gradient.write_slice(&zeros[..]).unwrap();
+        }
+    }
+}
+
+impl ComputeParametersGradient<f32> for ScaledDotProductAttention { }
+
+// the order of the projections in the weights of a MultiHeadAttention layer
+const PROJECTIONS: [&'static str; 4] = ["query", "key", "value", "output"];
+const QUERY: usize = 0;
+const KEY: usize = 1;
+const VALUE: usize = 2;
+const OUTPUT: usize = 3;
+
+/// The intermediate results of the last forward pass of a MultiHeadAttention layer.
+#[derive(Debug, Clone, Default)]
+struct MultiHeadAttentionCache {
+    query: Vec<f32>,
+    key: Vec<f32>,
+    value: Vec<f32>,
+    // the concatenated heads before the output projection
+    attended: Vec<f32>,
+    probabilities: Vec<f32>,
+}
+
+#[derive(Debug)]
+/// MultiHeadAttention Layer
+pub struct MultiHeadAttention {
+    num_heads: usize,
+    causal: bool,
+
+    cache: RefCell<MultiHeadAttentionCache>,
+    // the weight gradients computed together with the input gradient
+    weights_gradients: RefCell<Vec<Vec<f32>>>,
+}
+
+impl MultiHeadAttention {
+    /// Create a MultiHeadAttention layer from a MultiHeadAttentionConfig.
+    pub fn from_config(config: &MultiHeadAttentionConfig) -> MultiHeadAttention {
+        if config.num_heads == 0 {
+            panic!("MultiHeadAttention layer needs at least one head.");
+        }
+
+        MultiHeadAttention {
+            num_heads: config.num_heads,
+            causal: config.causal,
+
+            cache: RefCell::new(MultiHeadAttentionCache::default()),
+            weights_gradients: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn calculate_shape(&self, input_shape: &[usize]) -> AttentionShape {
+        let (batch_size, seq_len, embedding_size) = calculate_dims(input_shape);
+        if embedding_size % self.num_heads != 0 {
+            panic!("The embedding size {} of a MultiHeadAttention layer needs to be divisible by its {} heads.",
+                   embedding_size, self.num_heads);
+        }
+        AttentionShape {
+            batch_size: batch_size,
+            query_len: seq_len,
+            key_len: seq_len,
+            num_heads: self.num_heads,
+            head_size: embedding_size / self.num_heads,
+        }
+    }
+}
+
+impl LayerWorker for MultiHeadAttention {
+    fn exact_num_output_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn auto_weight_blobs(&self) -> bool {
+        true
+    }
+
+    fn exact_num_weight_blobs(&self) -> Option<usize> {
+        Some(2 * PROJECTIONS.len())
+    }
+
+    fn weight_name(&self, weight_id: usize) -> Option<String> {
+        let kind = if weight_id % 2 == 0 { "weight" } else { "bias" };
+        PROJECTIONS.get(weight_id / 2).map(|projection| format!("{}_{}", projection, kind))
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        if input_data.is_empty() || input_data.len() > 2 {
+            panic!("MultiHeadAttention layer expects a sequence and optionally a padding mask input.");
+        }
+        let input_shape = input_data[0].read().unwrap().shape().dimensions().to_owned();
+        let shape = self.calculate_shape(&input_shape);
+        if let Some(mask) = input_data.get(1) {
+            check_padding_mask(mask, shape.batch_size, shape.key_len);
+        }
+
+        for (input, gradient) in input_data.iter().zip(input_gradient.iter()) {
+            let input_shape = input.read().unwrap().shape().clone();
+            gradient.write().unwrap().resize(input_shape).unwrap();
+        }
+        output_data[0].write().unwrap().resize(&input_shape[..]).unwrap();
+        output_gradient[0].write().unwrap().resize(&input_shape[..]).unwrap();
+
+        let embedding_size = shape.embedding_size();
+        for (weight_id, (weight, gradient)) in weights_data.iter().zip(weights_gradient.iter()).enumerate() {
+            let (weight_shape, filler) = if weight_id % 2 == 0 {
+                (vec![embedding_size, embedding_size],
+                 FillerType::Glorot { input_size: embedding_size, output_size: embedding_size })
+            } else {
+                (vec![embedding_size], FillerType::Constant { value: 0f32 })
+            };
+            let mut weight = weight.write().unwrap();
+            weight.resize(&weight_shape[..]).unwrap();
+            filler.fill(&mut weight);
+            gradient.write().unwrap().resize(&weight_shape[..]).unwrap();
+        }
+    }
+}
+
+impl ComputeOutput<f32> for MultiHeadAttention {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let shape = self.calculate_shape(input_data[0].shape().dimensions());
+        let embedding_size = shape.embedding_size();
+        let input = input_data[0].as_slice().unwrap();
+        let weights = weights.iter().map(|weight| weight.as_slice().unwrap()).collect::<Vec<_>>();
+        let projection = |id: usize, input: &[f32]| project(input, embedding_size, weights[2 * id], weights[2 * id + 1]);
+
+        let query = projection(QUERY, input);
+        let key = projection(KEY, input);
+        let value = projection(VALUE, input);
+        let mask = AttentionMask {
+            causal: self.causal,
+            padding: input_data.get(1).map(|mask| mask.as_slice().unwrap()),
+        };
+        let (attended, probabilities) = attention_forward(&shape, &query, &key, &value, &mask);
+        let output = projection(OUTPUT, &attended);
+
+        output_data[0].write_slice(&output[..]).unwrap();
+        *self.cache.borrow_mut() = MultiHeadAttentionCache {
+            query: query,
+            key: key,
+            value: value,
+            attended: attended,
+            probabilities: probabilities,
+        };
+    }
+}
+
+impl ComputeInputGradient<f32> for MultiHeadAttention {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let shape = self.calculate_shape(input_data[0].shape().dimensions());
+        let embedding_size = shape.embedding_size();
+        let input = input_data[0].as_slice().unwrap();
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+        let weights = weights_data.iter().map(|weight| weight.as_slice().unwrap()).collect::<Vec<_>>();
+        let cache = self.cache.borrow();
+
+        let mut attended_gradient = vec![0f32; cache.attended.len()];
+        add_project_input_gradient(output_gradient, embedding_size, weights[2 * OUTPUT], &mut attended_gradient);
+        let (query_gradient, key_gradient, value_gradient) = attention_backward(&shape,
+                                                                                &cache.query,
+                                                                                &cache.key,
+                                                                                &cache.value,
+                                                                                &cache.probabilities,
+                                                                                &attended_gradient);
+
+        let mut input_gradient = vec![0f32; input.len()];
+        let mut weights_gradients = Vec::with_capacity(weights.len());
+        for &(id, gradient, projected) in &[(QUERY, &query_gradient[..], input),
+                                            (KEY, &key_gradient[..], input),
+                                            (VALUE, &value_gradient[..], input),
+                                            (OUTPUT, output_gradient, &cache.attended[..])] {
+            if id != OUTPUT {
+                add_project_input_gradient(gradient, embedding_size, weights[2 * id], &mut input_gradient);
+            }
+            let (weight_gradient, bias_gradient) = project_parameters_gradient(gradient, embedding_size, projected, embedding_size);
+            weights_gradients.push(weight_gradient);
+            weights_gradients.push(bias_gradient);
+        }
+
+        input_gradients[0].write_slice(&input_gradient[..]).unwrap();
+        if let Some(mask_gradient) = input_gradients.get_mut(1) {
+            // the mask is not differentiable
+            let zeros = vec![0f32; shape.batch_size * shape.key_len];
+            mask_gradient.write_slice(&zeros[..]).unwrap();
+        }
+        *self.weights_gradients.borrow_mut() = weights_gradients;
+    }
+}
+
+impl ComputeParametersGradient<f32> for MultiHeadAttention {
+    fn compute_parameters_gradient(&self,
+                                   backend: &LeafBackend,
+                                   output_data: &[&SharedTensor<f32>],
+                                   output_gradients: &[&SharedTensor<f32>],
+                                   input_data: &[&SharedTensor<f32>],
+                                   parameters_gradients: &mut [&mut SharedTensor<f32>]) {
+        // computed by compute_input_gradient, which has access to the weights
+        let weights_gradients = self.weights_gradients.borrow();
+        for (parameter_gradient, gradient) in parameters_gradients.iter_mut().zip(weights_gradients.iter()) {
+            parameter_gradient.write_slice(&gradient[..]).unwrap();
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+/// Specifies configuration parameters for a ScaledDotProductAttention Layer.
+pub struct ScaledDotProductAttentionConfig {
+    /// Whether a query position may only attend to key positions up to its own.
+    ///
+    /// Default: false
+    pub causal: bool,
+}
+
+impl<'a> CapnpWrite<'a> for ScaledDotProductAttentionConfig {
+    type Builder = capnp_sdpa_config::Builder<'a>;
+
+    /// Write the ScaledDotProductAttentionConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_causal(self.causal);
+    }
+}
+
+impl<'a> CapnpRead<'a> for ScaledDotProductAttentionConfig {
+    type Reader = capnp_sdpa_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        ScaledDotProductAttentionConfig {
+            causal: reader.get_causal(),
+        }
+    }
+}
+
+impl Into<LayerType> for ScaledDotProductAttentionConfig {
+    fn into(self) -> LayerType {
+        LayerType::ScaledDotProductAttention(self)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a MultiHeadAttention Layer.
+pub struct MultiHeadAttentionConfig {
+    /// The number of heads. Needs to divide the embedding size of the input.
+    pub num_heads: usize,
+    /// Whether a position may only attend to the positions up to itself.
+    ///
+    /// Default: false
+    pub causal: bool,
+}
+
+impl MultiHeadAttentionConfig {
+    /// Create a MultiHeadAttentionConfig for `num_heads` heads without causal masking.
+    pub fn new(num_heads: usize) -> MultiHeadAttentionConfig {
+        MultiHeadAttentionConfig {
+            num_heads: num_heads,
+            causal: false,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for MultiHeadAttentionConfig {
+    type Builder = capnp_mha_config::Builder<'a>;
+
+    /// Write the MultiHeadAttentionConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_num_heads(self.num_heads as u64);
+        builder.set_causal(self.causal);
+    }
+}
+
+impl<'a> CapnpRead<'a> for MultiHeadAttentionConfig {
+    type Reader = capnp_mha_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        MultiHeadAttentionConfig {
+            num_heads: reader.get_num_heads() as usize,
+            causal: reader.get_causal(),
+        }
+    }
+}
+
+impl Into<LayerType> for MultiHeadAttentionConfig {
+    fn into(self) -> LayerType {
+        LayerType::MultiHeadAttention(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{attention_backward, attention_forward, AttentionMask, AttentionShape};
+
+    fn shape(query_len: usize, key_len: usize) -> AttentionShape {
+        AttentionShape { batch_size: 1, query_len: query_len, key_len: key_len, num_heads: 1, head_size: 2 }
+    }
+
+    #[test]
+    fn causal_mask_hides_future_positions() {
+        let input = [1f32, 0f32, 0f32, 1f32, 1f32, 1f32];
+        let mask = AttentionMask { causal: true, padding: None };
+        let (output, probabilities) = attention_forward(&shape(3, 3), &input, &input, &input, &mask);
+
+        assert_eq!(&probabilities[..3], &[1f32, 0f32, 0f32]);
+        assert_eq!(probabilities[5], 0f32);
+        assert_eq!(&output[..2], &[1f32, 0f32]);
+    }
+
+    #[test]
+    fn fully_padded_queries_output_zeros() {
+        let query = [1f32, 2f32];
+        let key = [1f32, 1f32, 2f32, 2f32];
+        let padding = [0f32, 0f32];
+        let mask = AttentionMask { causal: false, padding: Some(&padding) };
+        let (output, probabilities) = attention_forward(&shape(1, 2), &query, &key, &key, &mask);
+
+        assert_eq!(output, vec![0f32, 0f32]);
+        assert_eq!(probabilities, vec![0f32, 0f32]);
+    }
+
+    #[test]
+    fn query_gradient_matches_finite_differences() {
+        let query = [0.3f32, -0.2f32];
+        let key = [0.5f32, 0.1f32, -0.4f32, 0.8f32];
+        let value = [1f32, 2f32, -1f32, 0.5f32];
+        let mask = AttentionMask { causal: false, padding: None };
+        let (_, probabilities) = attention_forward(&shape(1, 2), &query, &key, &value, &mask);
+        // d(sum(output)) / d(query)
+        let (query_gradient, _, _) = attention_backward(&shape(1, 2), &query, &key, &value, &probabilities, &[1f32, 1f32]);
+
+        let epsilon = 1e-3f32;
+        for c in 0..2 {
+            let mut shifted = query;
+            shifted[c] += epsilon;
+            let (plus, _) = attention_forward(&shape(1, 2), &shifted, &key, &value, &mask);
+            shifted[c] -= 2f32 * epsilon;
+            let (minus, _) = attention_forward(&shape(1, 2), &shifted, &key, &value, &mask);
+            let numeric = (plus.iter().sum::<f32>() - minus.iter().sum::<f32>()) / (2f32 * epsilon);
+            assert!((numeric - query_gradient[c]).abs() < 1e-2);
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! Normalizes the input per channel over the batch ([Batch Normalization][paper]).
+//!
+//! The input has the shape `[batch_size, channels, ...]`. Every channel is normalized over
+//! the batch and all spatial positions to zero mean and unit variance, then scaled by the
+//! learnable `gamma` and shifted by the learnable `beta` weight:
+//!
+//! `y = gamma * (x - mean) / sqrt(variance + epsilon) + beta`
+//!
+//! In [training mode][mode] the statistics of the current batch are used and running
+//! averages of them are updated with the configured `momentum`:
+//!
+//! `running = (1 - momentum) * running + momentum * batch_statistic`
+//!
+//! In evaluation mode the running averages are used instead, so the output of a sample
+//! doesn't depend on the rest of the batch. The running averages are [buffers][buffers] of
+//! the layer, they are saved and loaded with it but not updated by the Solver.
+//!
+//! Normalizing the inputs of every layer allows higher learning rates and makes deeper
+//! networks train faster.
+//!
+//! [paper]: https://arxiv.org/abs/1502.03167
+//! [mode]: ../../core/enum.Mode.html
+//! [buffers]: ../../core/trait.LayerWorker.html#method.buffers
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::batch_norm_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+use crate::weight::FillerType;
+
+use parenchyma::prelude::SharedTensor;
+use std::cell::RefCell;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug)]
+/// BatchNorm Layer
+pub struct BatchNorm {
+    epsilon: f32,
+    momentum: f32,
+    mode: Mode,
+
+    running_mean: ArcLockTensor,
+    running_variance: ArcLockTensor,
+
+    // the normalized input of the last forward pass
+    normalized: RefCell<Vec<f32>>,
+    // 1 / sqrt(variance + epsilon) per channel of the last forward pass
+    inverse_std: RefCell<Vec<f32>>,
+}
+
+impl BatchNorm {
+    /// Create a BatchNorm layer from a BatchNormConfig.
+    pub fn from_config(config: &BatchNormConfig) -> BatchNorm {
+        BatchNorm {
+            epsilon: config.epsilon,
+            momentum: config.momentum,
+            mode: Mode::default(),
+
+            running_mean: Arc::new(RwLock::new(SharedTensor::from([1]))),
+            running_variance: Arc::new(RwLock::new(SharedTensor::from([1]))),
+
+            normalized: RefCell::new(Vec::new()),
+            inverse_std: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Splits the input shape into `(batch_size, channels, spatial size)`.
+    fn calculate_dims(input_shape: &[usize]) -> (usize, usize, usize) {
+        if input_shape.len() < 2 {
+            panic!("BatchNorm layer expects an input of shape [batch_size, channels, ...].");
+        }
+        let spatial_size = input_shape.iter().skip(2).fold(1, |prod, i| prod * i);
+        (input_shape[0], input_shape[1], spatial_size)
+    }
+
+    /// Calls `f(channel, index)` for every value of the input.
+    fn for_each_value<F: FnMut(usize, usize)>(dims: (usize, usize, usize), mut f: F) {
+        let (batch_size, channels, spatial_size) = dims;
+        for n in 0..batch_size {
+            for c in 0..channels {
+                let offset = (n * channels + c) * spatial_size;
+                for index in offset..(offset + spatial_size) {
+                    f(c, index);
+                }
+            }
+        }
+    }
+
+    /// Computes the mean and the biased variance of every channel.
+    fn batch_statistics(input: &[f32], dims: (usize, usize, usize)) -> (Vec<f32>, Vec<f32>) {
+        let (batch_size, channels, spatial_size) = dims;
+        let count = (batch_size * spatial_size) as f32;
+
+        let mut mean = vec![0f32; channels];
+        Self::for_each_value(dims, |c, i| mean[c] += input[i]);
+        for value in &mut mean {
+            *value /= count;
+        }
+
+        let mut variance = vec![0f32; channels];
+        Self::for_each_value(dims, |c, i| variance[c] += (input[i] - mean[c]) * (input[i] - mean[c]));
+        for value in &mut variance {
+            *value /= count;
+        }
+
+        (mean, variance)
+    }
+
+    /// Blends the batch statistics into the running averages.
+    fn update_running_statistics(&self, mean: &[f32], variance: &[f32], count: usize) {
+        // the running variance is an estimate of the population variance
+        let correction = if count > 1 { count as f32 / (count - 1) as f32 } else { 1f32 };
+
+        let mut running_mean = self.running_mean.write().unwrap();
+        for (running, &batch) in running_mean.as_mut_slice().unwrap().iter_mut().zip(mean) {
+            *running = (1f32 - self.momentum) * *running + self.momentum * batch;
+        }
+        let mut running_variance = self.running_variance.write().unwrap();
+        for (running, &batch) in running_variance.as_mut_slice().unwrap().iter_mut().zip(variance) {
+            *running = (1f32 - self.momentum) * *running + self.momentum * batch * correction;
+        }
+    }
+}
+
+impl LayerWorker for BatchNorm {
+    impl_ilayer_common!();
+
+    fn auto_weight_blobs(&self) -> bool {
+        true
+    }
+
+    fn exact_num_weight_blobs(&self) -> Option<usize> {
+        Some(2)
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    fn buffers(&self) -> Vec<(String, ArcLockTensor)> {
+        vec![("running_mean".to_owned(), self.running_mean.clone()),
+             ("running_variance".to_owned(), self.running_variance.clone())]
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let input = input_data[0].read().unwrap();
+        let input_shape = input.shape().clone();
+        let (_, channels, _) = Self::calculate_dims(input_shape.dimensions());
+
+        input_gradient[0].write().unwrap().resize(input_shape.clone()).unwrap();
+        output_data[0].write().unwrap().resize(input_shape.clone()).unwrap();
+        output_gradient[0].write().unwrap().resize(input_shape.clone()).unwrap();
+
+        // gamma starts as 1 and beta as 0, so the layer initially only normalizes
+        for (weight, &value) in weights_data.iter().zip(&[1f32, 0f32]) {
+            let mut weight = weight.write().unwrap();
+            weight.resize(&[channels][..]).unwrap();
+            FillerType::Constant { value: value }.fill(&mut weight);
+        }
+        for gradient in weights_gradient.iter() {
+            gradient.write().unwrap().resize(&[channels][..]).unwrap();
+        }
+
+        for (buffer, &value) in [&self.running_mean, &self.running_variance].iter().zip(&[0f32, 1f32]) {
+            let mut buffer = buffer.write().unwrap();
+            buffer.resize(&[channels][..]).unwrap();
+            FillerType::Constant { value: value }.fill(&mut buffer);
+        }
+        self.normalized.borrow_mut().resize(input_shape.capacity(), 0f32);
+        self.inverse_std.borrow_mut().resize(channels, 0f32);
+    }
+}
+
+impl ComputeOutput<f32> for BatchNorm {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let dims = Self::calculate_dims(input_data[0].shape().dimensions());
+        let input = input_data[0].as_slice().unwrap();
+        let gamma = weights[0].as_slice().unwrap();
+        let beta = weights[1].as_slice().unwrap();
+
+        let (mean, variance) = match self.mode {
+            Mode::Train => {
+                let (mean, variance) = Self::batch_statistics(input, dims);
+                self.update_running_statistics(&mean, &variance, dims.0 * dims.2);
+                (mean, variance)
+            }
+            Mode::Eval => {
+                let mean = self.running_mean.read().unwrap().as_slice().unwrap().to_vec();
+                let variance = self.running_variance.read().unwrap().as_slice().unwrap().to_vec();
+                (mean, variance)
+            }
+        };
+
+        let mut inverse_std = self.inverse_std.borrow_mut();
+        for (inv, &var) in inverse_std.iter_mut().zip(&variance) {
+            *inv = 1f32 / (var + self.epsilon).sqrt();
+        }
+
+        let mut normalized = self.normalized.borrow_mut();
+        let output = output_data[0].as_mut_slice().unwrap();
+        Self::for_each_value(dims, |c, i| {
+            normalized[i] = (input[i] - mean[c]) * inverse_std[c];
+            output[i] = gamma[c] * normalized[i] + beta[c];
+        });
+    }
+}
+
+impl ComputeInputGradient<f32> for BatchNorm {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let dims = Self::calculate_dims(input_data[0].shape().dimensions());
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+        let gamma = weights_data[0].as_slice().unwrap();
+        let normalized = self.normalized.borrow();
+        let inverse_std = self.inverse_std.borrow();
+        let input_gradient = input_gradients[0].as_mut_slice().unwrap();
+
+        match self.mode {
+            Mode::Train => {
+                // the batch statistics depend on the input as well
+                let count = (dims.0 * dims.2) as f32;
+                let mut sum_gradient = vec![0f32; dims.1];
+                let mut sum_gradient_normalized = vec![0f32; dims.1];
+                Self::for_each_value(dims, |c, i| {
+                    sum_gradient[c] += output_gradient[i];
+                    sum_gradient_normalized[c] += output_gradient[i] * normalized[i];
+                });
+                Self::for_each_value(dims, |c, i| {
+                    input_gradient[i] = gamma[c] * inverse_std[c] / count *
+                        (count * output_gradient[i] - sum_gradient[c] - normalized[i] * sum_gradient_normalized[c]);
+                });
+            }
+            Mode::Eval => {
+                Self::for_each_value(dims, |c, i| {
+                    input_gradient[i] = gamma[c] * inverse_std[c] * output_gradient[i];
+                });
+            }
+        }
+    }
+}
+
+impl ComputeParametersGradient<f32> for BatchNorm {
+    fn compute_parameters_gradient(&self,
+                                   backend: &LeafBackend,
+                                   output_data: &[&SharedTensor<f32>],
+                                   output_gradients: &[&SharedTensor<f32>],
+                                   input_data: &[&SharedTensor<f32>],
+                                   parameters_gradients: &mut [&mut SharedTensor<f32>]) {
+        let dims = Self::calculate_dims(input_data[0].shape().dimensions());
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+        let normalized = self.normalized.borrow();
+
+        let mut gamma_gradient = vec![0f32; dims.1];
+        let mut beta_gradient = vec![0f32; dims.1];
+        Self::for_each_value(dims, |c, i| {
+            gamma_gradient[c] += output_gradient[i] * normalized[i];
+            beta_gradient[c] += output_gradient[i];
+        });
+
+        parameters_gradients[0].write_slice(&gamma_gradient[..]).unwrap();
+        parameters_gradients[1].write_slice(&beta_gradient[..]).unwrap();
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a BatchNorm Layer.
+pub struct BatchNormConfig {
+    /// The value added to the variance to avoid a division by zero.
+    ///
+    /// Default: 1e-5
+    pub epsilon: f32,
+    /// The weight of the current batch when updating the running averages.
+    ///
+    /// Default: 0.1
+    pub momentum: f32,
+}
+
+impl Default for BatchNormConfig {
+    fn default() -> BatchNormConfig {
+        BatchNormConfig {
+            epsilon: 1e-5f32,
+            momentum: 0.1f32,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for BatchNormConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the BatchNormConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_epsilon(self.epsilon);
+        builder.set_momentum(self.momentum);
+    }
+}
+
+impl<'a> CapnpRead<'a> for BatchNormConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        BatchNormConfig {
+            epsilon: reader.get_epsilon(),
+            momentum: reader.get_momentum(),
+        }
+    }
+}
+
+impl Into<LayerType> for BatchNormConfig {
+    fn into(self) -> LayerType {
+        LayerType::BatchNorm(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BatchNorm;
+
+    #[test]
+    fn statistics_per_channel() {
+        // [batch 2, channels 2, spatial 2]
+        let input = [1f32, 3f32, 10f32, 10f32,
+                     5f32, 7f32, 20f32, 20f32];
+        let (mean, variance) = BatchNorm::batch_statistics(&input, (2, 2, 2));
+        assert_eq!(mean, vec![4f32, 15f32]);
+        assert_eq!(variance, vec![5f32, 25f32]);
+    }
+}
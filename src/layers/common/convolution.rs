@@ -10,44 +10,45 @@
 //!
 //! The layer expects the input to be in 4D NCHW format (2 spatial dimensions).
 //!
+//! ## Implementation
+//!
+//! The convolution is computed via [im2col][im2col]: the receptive fields of every output
+//! position are unrolled on the host into the columns of a matrix, which turns the convolution
+//! into a matrix multiplication. The columns of all samples of the batch are placed side by side,
+//! so the whole batch needs a single matrix multiplication, which is computed by the BLAS of the
+//! backend.
+//!
 //! [cs231n_convnets]: https://cs231n.github.io/convolutional-networks
-use std::rc::Rc;
-use std::sync::{Arc, RwLock};
-use co::prelude::*;
-use conn;
-use conn::ConvolutionConfig as connConvolutionConfig;
-use layer::*;
-use util::{ArcLock, cast_vec_usize_to_i32};
-use weight::FillerType;
-use super::FilterLayer;
-use leaf_capnp::convolution_config as capnp_config;
+//! [im2col]: https://cs231n.github.io/convolutional-networks/#conv
 
 use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::convolution_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+use crate::weight::FillerType;
+use super::FilterLayer;
+
+use parenchyma::prelude::SharedTensor;
+use parenchyma_blas::Transposition;
 
 #[derive(Debug, Clone)]
 /// Convolution Layer
-pub struct Convolution<B: conn::Convolution<f32>> {
+pub struct Convolution {
     num_output: usize,
     filter_shape: Vec<usize>,
     stride: Vec<usize>,
     padding: Vec<usize>,
-
-    workspace: Option<ArcLock<SharedTensor<u8>>>,
-    convolution_config: Option<Rc<B::CC>>,
 }
 
-impl<B: conn::Convolution<f32>> Convolution<B> {
+impl Convolution {
     /// Create a Convolution layer from a ConvolutionConfig.
-    pub fn from_config(config: &ConvolutionConfig) -> Convolution<B> {
+    pub fn from_config(config: &ConvolutionConfig) -> Convolution {
         Convolution {
             num_output: config.num_output,
 
             filter_shape: config.filter_shape.clone(),
             stride: config.stride.clone(),
             padding: config.padding.clone(),
-
-            workspace: None,
-            convolution_config: None,
         }
     }
 
@@ -62,14 +63,157 @@ impl<B: conn::Convolution<f32>> Convolution<B> {
         vec![filter_n, filter_c, filter_h, filter_w]
     }
 
-    fn create_filter(&self, device: &DeviceType, input_shape: &[usize]) -> SharedTensor<f32> {
-        let filter_shape = self.calculate_filter_shape(input_shape);
+    /// Collects the dimensions needed to unroll a single sample of `input_shape`.
+    fn geometry(&self, input_shape: &[usize]) -> Geometry {
+        let num_spatial_dims = self.num_spatial_dims(input_shape);
+        let filter = self.spatial_filter_dims(num_spatial_dims);
+        let padding = self.padding_dims(num_spatial_dims);
+        let stride = self.stride_dims(num_spatial_dims);
+        let output = Self::calculate_spatial_output_dims(&input_shape[2..], &filter, &padding, &stride);
+
+        Geometry {
+            channels: input_shape[1],
+            height: input_shape[2],
+            width: input_shape[3],
+            filter_h: filter[0],
+            filter_w: filter[1],
+            pad_h: padding[0],
+            pad_w: padding[1],
+            stride_h: stride[0],
+            stride_w: stride[1],
+            output_h: output[0],
+            output_w: output[1],
+        }
+    }
+}
+
+/// The dimensions of a single sample, its filter and the resulting output.
+#[derive(Debug, Clone, Copy)]
+struct Geometry {
+    channels: usize,
+    height: usize,
+    width: usize,
+    filter_h: usize,
+    filter_w: usize,
+    pad_h: usize,
+    pad_w: usize,
+    stride_h: usize,
+    stride_w: usize,
+    output_h: usize,
+    output_w: usize,
+}
+
+impl Geometry {
+    /// The number of values in a single input sample.
+    fn input_size(&self) -> usize {
+        self.channels * self.height * self.width
+    }
+
+    /// The number of rows of the unrolled column matrix (= values in one receptive field).
+    fn column_rows(&self) -> usize {
+        self.channels * self.filter_h * self.filter_w
+    }
+
+    /// The number of columns of the unrolled column matrix (= output positions).
+    fn column_cols(&self) -> usize {
+        self.output_h * self.output_w
+    }
+
+    /// Calls `f(column_index, input_index)` for every value of the column matrix that
+    /// maps to a value inside the (unpadded) input.
+    fn for_each_mapping<F: FnMut(usize, usize)>(&self, mut f: F) {
+        let cols = self.column_cols();
+        for c in 0..self.channels {
+            for kh in 0..self.filter_h {
+                for kw in 0..self.filter_w {
+                    let row = (c * self.filter_h + kh) * self.filter_w + kw;
+                    for oh in 0..self.output_h {
+                        let h = (oh * self.stride_h + kh) as isize - self.pad_h as isize;
+                        if h < 0 || h >= self.height as isize {
+                            continue;
+                        }
+                        for ow in 0..self.output_w {
+                            let w = (ow * self.stride_w + kw) as isize - self.pad_w as isize;
+                            if w < 0 || w >= self.width as isize {
+                                continue;
+                            }
+                            let input_index = (c * self.height + h as usize) * self.width + w as usize;
+                            f(row * cols + oh * self.output_w + ow, input_index);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Unrolls a single input sample into a `[column_rows, column_cols]` matrix.
+    fn im2col(&self, input: &[f32], columns: &mut [f32]) {
+        for value in columns.iter_mut() {
+            *value = 0f32;
+        }
+        self.for_each_mapping(|column_index, input_index| columns[column_index] = input[input_index]);
+    }
+
+    /// Accumulates a `[column_rows, column_cols]` matrix back into a single input sample.
+    fn col2im(&self, columns: &[f32], input: &mut [f32]) {
+        for value in input.iter_mut() {
+            *value = 0f32;
+        }
+        self.for_each_mapping(|column_index, input_index| input[input_index] += columns[column_index]);
+    }
+
+    /// Unrolls all samples of `input` into a `[column_rows, batch_size * column_cols]` matrix.
+    fn im2col_batch(&self, input: &[f32], batch_size: usize) -> Vec<f32> {
+        let (rows, cols) = (self.column_rows(), self.column_cols());
+        let mut columns = vec![0f32; batch_size * rows * cols];
+        for (input_sample, columns_sample) in input.chunks(self.input_size()).zip(columns.chunks_mut(rows * cols)) {
+            self.im2col(input_sample, columns_sample);
+        }
+        side_by_side(&columns, rows, cols, batch_size)
+    }
+
+    /// Accumulates a `[column_rows, batch_size * column_cols]` matrix back into all samples of `input`.
+    fn col2im_batch(&self, columns: &[f32], input: &mut [f32], batch_size: usize) {
+        let (rows, cols) = (self.column_rows(), self.column_cols());
+        let mut sample_columns = vec![0f32; batch_size * rows * cols];
+        one_after_another(columns, rows, cols, batch_size, &mut sample_columns);
+        for (input_sample, columns_sample) in input.chunks_mut(self.input_size()).zip(sample_columns.chunks(rows * cols)) {
+            self.col2im(columns_sample, input_sample);
+        }
+    }
+}
+
+/// Places `batch_size` row-major `[rows, cols]` matrices that are stored one after another side by side,
+/// in a single `[rows, batch_size * cols]` matrix.
+fn side_by_side(matrices: &[f32], rows: usize, cols: usize, batch_size: usize) -> Vec<f32> {
+    let mut matrix = vec![0f32; batch_size * rows * cols];
+    for (sample, sample_matrix) in matrices.chunks(rows * cols).enumerate() {
+        for (row, sample_row) in sample_matrix.chunks(cols).enumerate() {
+            let start = (row * batch_size + sample) * cols;
+            matrix[start..start + cols].copy_from_slice(sample_row);
+        }
+    }
+    matrix
+}
 
-        SharedTensor::<f32>::new(device, &filter_shape).unwrap()
+/// The inverse of [side_by_side](fn.side_by_side.html).
+fn one_after_another(matrix: &[f32], rows: usize, cols: usize, batch_size: usize, matrices: &mut [f32]) {
+    for (sample, sample_matrix) in matrices.chunks_mut(rows * cols).enumerate() {
+        for (row, sample_row) in sample_matrix.chunks_mut(cols).enumerate() {
+            let start = (row * batch_size + sample) * cols;
+            sample_row.copy_from_slice(&matrix[start..start + cols]);
+        }
     }
 }
 
-impl<B: conn::Convolution<f32>> FilterLayer for Convolution<B> {
+/// Creates a `[rows, cols]` tensor holding `values`.
+fn matrix_tensor(values: &[f32], rows: usize, cols: usize) -> SharedTensor<f32> {
+    let mut tensor = SharedTensor::<f32>::from([rows, cols]);
+    tensor.write_slice(values).unwrap();
+    tensor
+}
+
+impl FilterLayer for Convolution {
     /// Calculates the number of spatial dimensions for the convolution operation.
     fn num_spatial_dims(&self, input_shape: &[usize]) -> usize {
         match input_shape.len() {
@@ -108,113 +252,117 @@ impl<B: conn::Convolution<f32>> FilterLayer for Convolution<B> {
     }
 }
 
-impl<B: IBackend + conn::Convolution<f32>> ILayer<B> for Convolution<B> {
+impl LayerWorker for Convolution {
     impl_ilayer_common!();
 
     fn auto_weight_blobs(&self) -> bool {
         true
     }
 
+    fn sync_native(&self) -> bool {
+        true
+    }
+
     fn reshape(&mut self,
-               backend: Rc<B>,
-               input_data: &mut Vec<ArcLock<SharedTensor<f32>>>,
-               input_gradient: &mut Vec<ArcLock<SharedTensor<f32>>>,
-               weights_data: &mut Vec<ArcLock<SharedTensor<f32>>>,
-               weights_gradient: &mut Vec<ArcLock<SharedTensor<f32>>>,
-               output_data: &mut Vec<ArcLock<SharedTensor<f32>>>,
-               output_gradient: &mut Vec<ArcLock<SharedTensor<f32>>>) {
-        for i in 0..input_data.len() {
-            let inp = input_data[0].read().unwrap();
-            let mut output_data = output_data[0].write().unwrap();
-            let mut output_gradient = output_gradient[0].write().unwrap();
-            let input_shape = inp.desc();
-            let output_shape = self.calculate_output_shape(input_shape);
-            output_data.resize(&output_shape).unwrap();
-            output_gradient.resize(&output_shape).unwrap();
-
-            let device = <B as IBackend>::device(&backend);
-            let num_spatial_dims = self.num_spatial_dims(inp.desc());
-            let mut filter = self.create_filter(device, input_shape);
-            let stride = cast_vec_usize_to_i32(self.stride_dims(num_spatial_dims));
-            let padding = cast_vec_usize_to_i32(self.padding_dims(num_spatial_dims));
-
-            let config = backend.new_convolution_config(&inp, &output_data, &mut filter,
-                                                        conn::ConvForwardAlgo::Auto, conn::ConvBackwardFilterAlgo::Auto, conn::ConvBackwardDataAlgo::Auto,
-                                                        &stride, &padding).unwrap();
-
-            // resize and fill weights
-            weights_data[0].write().unwrap().resize(filter.desc()).unwrap();
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let inp = input_data[0].read().unwrap();
+        let input_shape = inp.shape().dimensions().to_owned();
+        let output_shape = self.calculate_output_shape(&input_shape);
+        input_gradient[0].write().unwrap().resize(&input_shape[..]).unwrap();
+        output_data[0].write().unwrap().resize(&output_shape[..]).unwrap();
+        output_gradient[0].write().unwrap().resize(&output_shape[..]).unwrap();
+
+        // resize and fill weights
+        let filter_shape = self.calculate_filter_shape(&input_shape);
+        if let Some(weight) = weights_data.get(0) {
+            weight.write().unwrap().resize(&filter_shape[..]).unwrap();
             let filler = FillerType::Glorot {
-                input_size: inp.desc().size(),
-                output_size: output_shape.size(),
+                input_size: filter_shape.iter().skip(1).fold(1, |prod, i| prod * i),
+                output_size: self.num_output * filter_shape[2] * filter_shape[3],
             };
-            filler.fill(&mut weights_data[0].write().unwrap());
-            weights_gradient[0].write().unwrap().resize(filter.desc()).unwrap();
-            self.convolution_config = Some(Rc::new(config));
+            filler.fill(&mut weight.write().unwrap());
+        }
+        if let Some(weight) = weights_gradient.get(0) {
+            weight.write().unwrap().resize(&filter_shape[..]).unwrap();
         }
-    }
-
-    fn resize_shared_workspace(&mut self, backend: Rc<B>, workspace: Option<ArcLock<SharedTensor<u8>>>) -> Option<ArcLock<SharedTensor<u8>>> {
-        let required_size = self.convolution_config.as_ref().unwrap().workspace_size();
-        let new_workspace = if workspace.is_none() {
-            Arc::new(RwLock::new(SharedTensor::<u8>::new(IBackend::device(&*backend), &(required_size)).unwrap()))
-        } else {
-            let old_workspace = workspace.as_ref().unwrap().clone();
-            let old_workspace_size = old_workspace.read().unwrap().capacity();
-            if old_workspace_size < required_size {
-                Arc::new(RwLock::new(SharedTensor::<u8>::new(IBackend::device(&*backend), &(required_size)).unwrap()))
-            } else {
-                workspace.unwrap()
-            }
-        };
-
-        self.workspace = Some(new_workspace.clone());
-        Some(new_workspace)
     }
 }
 
-impl<B: IBackend + conn::Convolution<f32>> ComputeOutput<f32, B> for Convolution<B> {
+impl ComputeOutput<f32> for Convolution {
     fn compute_output(&self,
-                      backend: &B,
+                      backend: &LeafBackend,
                       weights: &[&SharedTensor<f32>],
                       input_data: &[&SharedTensor<f32>],
                       output_data: &mut [&mut SharedTensor<f32>]) {
-        let filter_data = weights[0];
-        let conv_config = self.convolution_config.as_ref().unwrap();
-        let mut workspace = self.workspace.as_ref().unwrap().write().unwrap();
-        backend.convolution_plain(filter_data, input_data[0], output_data[0], &mut workspace, conv_config).unwrap();
+        let input_shape = input_data[0].shape().dimensions().to_owned();
+        let geometry = self.geometry(&input_shape);
+        let (rows, cols) = (geometry.column_rows(), geometry.column_cols());
+        let batch_size = input_shape[0];
+
+        let filter = matrix_tensor(weights[0].as_slice().unwrap(), self.num_output, rows);
+        let columns = matrix_tensor(&geometry.im2col_batch(input_data[0].as_slice().unwrap(), batch_size),
+                                    rows, batch_size * cols);
+        // output = filter * columns
+        let mut output = SharedTensor::<f32>::from([self.num_output, batch_size * cols]);
+        backend.gemm(&SharedTensor::scalar(1f32), Transposition::NoTranspose, &filter,
+                     Transposition::NoTranspose, &columns, &SharedTensor::scalar(0f32), &mut output).unwrap();
+        one_after_another(output.as_slice().unwrap(), self.num_output, cols, batch_size,
+                          output_data[0].as_mut_slice().unwrap());
     }
 }
 
-impl<B: IBackend + conn::Convolution<f32>> ComputeInputGradient<f32, B> for Convolution<B> {
+impl ComputeInputGradient<f32> for Convolution {
     fn compute_input_gradient(&self,
-                              backend: &B,
+                              backend: &LeafBackend,
                               weights_data: &[&SharedTensor<f32>],
                               _output_data: &[&SharedTensor<f32>],
                               output_gradients: &[&SharedTensor<f32>],
                               input_data: &[&SharedTensor<f32>],
                               input_gradients: &mut [&mut SharedTensor<f32>]) {
-        let filter_data = weights_data[0];
-        let conv_config = self.convolution_config.as_ref().unwrap();
-        let mut workspace = self.workspace.as_ref().unwrap().write().unwrap();
-        // compute gradient w.r.t. input
-        backend.convolution_grad_data_plain(filter_data, output_gradients[0], input_gradients[0], &mut workspace, conv_config).unwrap();
+        let input_shape = input_data[0].shape().dimensions().to_owned();
+        let geometry = self.geometry(&input_shape);
+        let (rows, cols) = (geometry.column_rows(), geometry.column_cols());
+        let batch_size = input_shape[0];
+
+        let filter = matrix_tensor(weights_data[0].as_slice().unwrap(), self.num_output, rows);
+        let output_gradient = matrix_tensor(&side_by_side(output_gradients[0].as_slice().unwrap(), self.num_output, cols, batch_size),
+                                            self.num_output, batch_size * cols);
+        // compute gradient w.r.t. input: columns = filter^T * output_gradient
+        let mut columns = SharedTensor::<f32>::from([rows, batch_size * cols]);
+        backend.gemm(&SharedTensor::scalar(1f32), Transposition::Transpose, &filter,
+                     Transposition::NoTranspose, &output_gradient, &SharedTensor::scalar(0f32), &mut columns).unwrap();
+        geometry.col2im_batch(columns.as_slice().unwrap(), input_gradients[0].as_mut_slice().unwrap(), batch_size);
     }
 }
 
-impl<B: IBackend + conn::Convolution<f32>> ComputeParametersGradient<f32, B> for Convolution<B> {
+impl ComputeParametersGradient<f32> for Convolution {
     fn compute_parameters_gradient(&self,
-                                   backend: &B,
+                                   backend: &LeafBackend,
                                    _output_data: &[&SharedTensor<f32>],
                                    output_gradients: &[&SharedTensor<f32>],
                                    input_data: &[&SharedTensor<f32>],
                                    parameters_gradients: &mut [&mut SharedTensor<f32>]) {
         // TODO: compute gradient w.r.t to bias
-        let filter_gradient = &mut parameters_gradients[0];
-        let conv_config = self.convolution_config.as_ref().unwrap();
-        let mut workspace = self.workspace.as_ref().unwrap().write().unwrap();
-        // compute gradient w.r.t. filter
-        backend.convolution_grad_filter_plain(input_data[0], output_gradients[0], filter_gradient, &mut workspace, conv_config).unwrap();
+        let input_shape = input_data[0].shape().dimensions().to_owned();
+        let geometry = self.geometry(&input_shape);
+        let (rows, cols) = (geometry.column_rows(), geometry.column_cols());
+        let batch_size = input_shape[0];
+
+        let columns = matrix_tensor(&geometry.im2col_batch(input_data[0].as_slice().unwrap(), batch_size),
+                                    rows, batch_size * cols);
+        let output_gradient = matrix_tensor(&side_by_side(output_gradients[0].as_slice().unwrap(), self.num_output, cols, batch_size),
+                                            self.num_output, batch_size * cols);
+        // compute gradient w.r.t. filter, summed over the whole batch: output_gradient * columns^T
+        let mut filter_gradient = SharedTensor::<f32>::from([self.num_output, rows]);
+        backend.gemm(&SharedTensor::scalar(1f32), Transposition::NoTranspose, &output_gradient,
+                     Transposition::Transpose, &columns, &SharedTensor::scalar(0f32), &mut filter_gradient).unwrap();
+        parameters_gradients[0].as_mut_slice().unwrap().copy_from_slice(filter_gradient.as_slice().unwrap());
     }
 }
 
@@ -298,12 +446,10 @@ impl<'a> CapnpRead<'a> for ConvolutionConfig {
 
 #[cfg(test)]
 mod tests {
-    use co::*;
-    use super::{Convolution, ConvolutionConfig};
+    use super::{Convolution, ConvolutionConfig, Geometry, one_after_another, side_by_side};
     use super::super::FilterLayer;
 
     #[test]
-    #[cfg(feature="cuda")]
     fn correct_shapes() {
         let cfg = ConvolutionConfig {
             num_output: 64,
@@ -312,7 +458,7 @@ mod tests {
             padding: vec![2],
             stride: vec![4],
         };
-        let layer = Convolution::<Backend<Cuda>>::from_config(&cfg);
+        let layer = Convolution::from_config(&cfg);
         let num_spatial_dims = layer.num_spatial_dims(&vec![1, 3, 224, 224]);
         assert_eq!(2, num_spatial_dims);
         assert_eq!(vec![11, 11], layer.spatial_filter_dims(2));
@@ -321,4 +467,51 @@ mod tests {
         assert_eq!(vec![64, 3, 11, 11], layer.calculate_filter_shape(&vec![1, 3, 224, 224]));
         assert_eq!(vec![1, 64, 55, 55], layer.calculate_output_shape(&vec![1, 3, 224, 224]));
     }
+
+    #[test]
+    fn im2col_unrolls_receptive_fields() {
+        let cfg = ConvolutionConfig {
+            num_output: 1,
+
+            filter_shape: vec![2],
+            padding: vec![0],
+            stride: vec![1],
+        };
+        let layer = Convolution::from_config(&cfg);
+        let geometry: Geometry = layer.geometry(&[1, 1, 3, 3]);
+        let input = [1f32, 2f32, 3f32,
+                     4f32, 5f32, 6f32,
+                     7f32, 8f32, 9f32];
+        let mut columns = vec![0f32; geometry.column_rows() * geometry.column_cols()];
+        geometry.im2col(&input, &mut columns);
+
+        assert_eq!(vec![1f32, 2f32, 4f32, 5f32,
+                        2f32, 3f32, 5f32, 6f32,
+                        4f32, 5f32, 7f32, 8f32,
+                        5f32, 6f32, 8f32, 9f32], columns);
+
+        let mut restored = vec![0f32; 9];
+        geometry.col2im(&columns, &mut restored);
+        // every input value is accumulated once per receptive field it is part of
+        assert_eq!(vec![1f32, 4f32, 3f32,
+                        8f32, 20f32, 12f32,
+                        7f32, 16f32, 9f32], restored);
+    }
+
+    #[test]
+    fn batch_is_placed_side_by_side() {
+        // two samples of [2, 2] each
+        let matrices = [1f32, 2f32,
+                        3f32, 4f32,
+
+                        5f32, 6f32,
+                        7f32, 8f32];
+        let matrix = side_by_side(&matrices, 2, 2, 2);
+        assert_eq!(vec![1f32, 2f32, 5f32, 6f32,
+                        3f32, 4f32, 7f32, 8f32], matrix);
+
+        let mut restored = vec![0f32; 8];
+        one_after_another(&matrix, 2, 2, 2, &mut restored);
+        assert_eq!(matrices.to_vec(), restored);
+    }
 }
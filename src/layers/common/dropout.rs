@@ -0,0 +1,242 @@
+//! Randomly sets a fraction of the input to zero during training.
+//!
+//! Every value of the input is dropped with the configured `probability`
+//! and the remaining values are scaled by `1 / (1 - probability)` ("inverted dropout"),
+//! so the expected value of every output matches the input.
+//! Thanks to that scaling the layer is the identity in [evaluation mode][mode],
+//! where nothing is dropped.
+//!
+//! Dropout prevents units from co-adapting too much and is a cheap and effective
+//! way to regularize large networks, see [Srivastava et al.][paper].
+//!
+//! The random mask is drawn from a generator that is seeded with the configured `seed`,
+//! which makes training runs reproducible. Without a seed it is seeded from the system's entropy.
+//!
+//! This layer can be used as in-place operation.
+//!
+//! [mode]: ../../core/enum.Mode.html
+//! [paper]: http://jmlr.org/papers/v15/srivastava14a.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::dropout_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+use rand::{FromEntropy, Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::cell::RefCell;
+
+/// Dropout Layer
+#[derive(Debug)]
+pub struct Dropout {
+    probability: f32,
+    mode: Mode,
+
+    rng: RefCell<StdRng>,
+    // the factor each value was multiplied by in the last forward pass
+    mask: RefCell<Vec<f32>>,
+}
+
+impl Dropout {
+    /// Create a Dropout layer from a DropoutConfig.
+    pub fn from_config(config: &DropoutConfig) -> Dropout {
+        if config.probability < 0f32 || config.probability >= 1f32 {
+            panic!("The probability of a Dropout layer needs to be in [0, 1), got {}.", config.probability);
+        }
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        Dropout {
+            probability: config.probability,
+            mode: Mode::default(),
+
+            rng: RefCell::new(rng),
+            mask: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.mode == Mode::Train && self.probability > 0f32
+    }
+
+    /// Draws a new mask for the next forward pass.
+    fn draw_mask(&self) {
+        let scale = 1f32 / (1f32 - self.probability);
+        let mut rng = self.rng.borrow_mut();
+        for factor in self.mask.borrow_mut().iter_mut() {
+            *factor = if rng.gen::<f32>() < self.probability { 0f32 } else { scale };
+        }
+    }
+
+    fn apply_mask(&self, values: &mut [f32]) {
+        for (value, &factor) in values.iter_mut().zip(self.mask.borrow().iter()) {
+            *value *= factor;
+        }
+    }
+}
+
+impl LayerWorker for Dropout {
+    impl_ilayer_common!();
+
+    fn compute_in_place(&self) -> bool {
+        true
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let input_shape = match input_data.get(0) {
+            Some(inp) => {
+                let input_shape = inp.read().unwrap().shape().clone();
+                input_gradient[0].write().unwrap().resize(input_shape.clone()).unwrap();
+                output_data[0].write().unwrap().resize(input_shape.clone()).unwrap();
+                input_shape
+            }
+            // in-place: the output already has the shape of the input
+            None => output_data[0].read().unwrap().shape().clone(),
+        };
+        output_gradient[0].write().unwrap().resize(input_shape.clone()).unwrap();
+        self.mask.borrow_mut().resize(input_shape.capacity(), 1f32);
+    }
+}
+
+impl ComputeOutput<f32> for Dropout {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      _weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        if let Some(input) = input_data.get(0) {
+            backend.copy(input, output_data[0]).unwrap();
+        }
+        if self.is_active() {
+            self.draw_mask();
+            self.apply_mask(output_data[0].as_mut_slice().unwrap());
+        }
+    }
+}
+
+impl ComputeInputGradient<f32> for Dropout {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        // in-place the input gradient already holds the output gradient
+        if let Some(output_gradient) = output_gradients.get(0) {
+            backend.copy(output_gradient, input_gradients[0]).unwrap();
+        }
+        if self.is_active() {
+            self.apply_mask(input_gradients[0].as_mut_slice().unwrap());
+        }
+    }
+}
+
+impl ComputeParametersGradient<f32> for Dropout {}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a Dropout Layer.
+pub struct DropoutConfig {
+    /// The probability of a value being dropped, in `[0, 1)`.
+    ///
+    /// Default: 0.5
+    pub probability: f32,
+    /// The seed for the random mask.
+    ///
+    /// Default: None
+    pub seed: Option<u64>,
+}
+
+impl Default for DropoutConfig {
+    fn default() -> DropoutConfig {
+        DropoutConfig {
+            probability: 0.5f32,
+            seed: None,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for DropoutConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the DropoutConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_probability(self.probability);
+        builder.set_has_seed(self.seed.is_some());
+        builder.set_seed(self.seed.unwrap_or(0));
+    }
+}
+
+impl<'a> CapnpRead<'a> for DropoutConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        let seed = if reader.get_has_seed() {
+            Some(reader.get_seed())
+        } else {
+            None
+        };
+
+        DropoutConfig {
+            probability: reader.get_probability(),
+            seed: seed,
+        }
+    }
+}
+
+impl Into<LayerType> for DropoutConfig {
+    fn into(self) -> LayerType {
+        LayerType::Dropout(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dropout, DropoutConfig};
+    use crate::layers::core::{LayerWorker, Mode};
+
+    fn seeded(seed: u64) -> Dropout {
+        let layer = Dropout::from_config(&DropoutConfig { probability: 0.5f32, seed: Some(seed) });
+        layer.mask.borrow_mut().resize(64, 1f32);
+        layer
+    }
+
+    #[test]
+    fn mask_is_reproducible_with_seed() {
+        let first = seeded(42);
+        let second = seeded(42);
+        first.draw_mask();
+        second.draw_mask();
+        assert_eq!(*first.mask.borrow(), *second.mask.borrow());
+    }
+
+    #[test]
+    fn mask_drops_or_scales() {
+        let layer = seeded(7);
+        layer.draw_mask();
+        assert!(layer.mask.borrow().iter().all(|&factor| factor == 0f32 || factor == 2f32));
+        assert!(layer.mask.borrow().iter().any(|&factor| factor == 0f32));
+    }
+
+    #[test]
+    fn inactive_in_eval_mode() {
+        let mut layer = seeded(7);
+        assert!(layer.is_active());
+        layer.set_mode(Mode::Eval);
+        assert!(!layer.is_active());
+    }
+}
@@ -0,0 +1,252 @@
+//! Combines any number of inputs of the same shape element-wise.
+//!
+//! The combination is chosen by the [EltwiseOperation][operation]:
+//!
+//! - `Sum`: `y = c_1 * x_1 + c_2 * x_2 + ...` with the per-input `coefficients` (all `1` by default),
+//!   e.g. to add the shortcut of a residual block or to subtract two inputs with `[1, -1]`.
+//! - `Product`: `y = x_1 * x_2 * ...`, e.g. for gating.
+//! - `Max`: `y = max(x_1, x_2, ...)`, where the gradient only flows to the (first) maximal input.
+//!
+//! [operation]: ./enum.EltwiseOperation.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::eltwise_config as capnp_config;
+use crate::cerealization_protocol::EltwiseOperation as CapnpEltwiseOperation;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+use std::cell::RefCell;
+
+#[derive(Debug)]
+/// Eltwise Layer
+pub struct Eltwise {
+    operation: EltwiseOperation,
+    coefficients: Vec<f32>,
+
+    // the index of the maximal input for every value of the last forward pass in `Max` mode
+    max_indices: RefCell<Vec<usize>>,
+}
+
+impl Eltwise {
+    /// Create a Eltwise layer from a EltwiseConfig.
+    pub fn from_config(config: &EltwiseConfig) -> Eltwise {
+        if !config.coefficients.is_empty() && config.operation != EltwiseOperation::Sum {
+            panic!("Eltwise layer only supports coefficients for the Sum operation.");
+        }
+
+        Eltwise {
+            operation: config.operation,
+            coefficients: config.coefficients.clone(),
+
+            max_indices: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn coefficient(&self, input_id: usize) -> f32 {
+        self.coefficients.get(input_id).cloned().unwrap_or(1f32)
+    }
+
+    /// The product of all inputs except `input_id` at `index`.
+    fn product_of_others(inputs: &[&[f32]], input_id: usize, index: usize) -> f32 {
+        inputs.iter().enumerate()
+              .filter(|&(other_id, _)| other_id != input_id)
+              .fold(1f32, |product, (_, input)| product * input[index])
+    }
+}
+
+impl LayerWorker for Eltwise {
+    fn exact_num_output_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        if input_data.len() < 2 {
+            panic!("Eltwise layer needs at least two inputs.");
+        }
+        if !self.coefficients.is_empty() && self.coefficients.len() != input_data.len() {
+            panic!("Eltwise layer has {} coefficients for {} inputs.", self.coefficients.len(), input_data.len());
+        }
+        let input_shape = input_data[0].read().unwrap().shape().dimensions().to_owned();
+        for (input, gradient) in input_data.iter().zip(input_gradient.iter()) {
+            if input.read().unwrap().shape().dimensions() != &input_shape[..] {
+                panic!("All inputs of a Eltwise layer need to have the shape {:?}.", input_shape);
+            }
+            gradient.write().unwrap().resize(&input_shape[..]).unwrap();
+        }
+        output_data[0].write().unwrap().resize(&input_shape[..]).unwrap();
+        output_gradient[0].write().unwrap().resize(&input_shape[..]).unwrap();
+    }
+}
+
+impl ComputeOutput<f32> for Eltwise {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      _weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let inputs = input_data.iter().map(|input| input.as_slice().unwrap()).collect::<Vec<_>>();
+        let len = inputs[0].len();
+
+        let output = match self.operation {
+            EltwiseOperation::Sum => (0..len).map(|i| {
+                inputs.iter().enumerate().fold(0f32, |sum, (input_id, input)| sum + self.coefficient(input_id) * input[i])
+            }).collect::<Vec<_>>(),
+            EltwiseOperation::Product => (0..len).map(|i| {
+                inputs.iter().fold(1f32, |product, input| product * input[i])
+            }).collect::<Vec<_>>(),
+            EltwiseOperation::Max => {
+                let mut max_indices = self.max_indices.borrow_mut();
+                max_indices.clear();
+                (0..len).map(|i| {
+                    let mut max_id = 0;
+                    for (input_id, input) in inputs.iter().enumerate().skip(1) {
+                        if input[i] > inputs[max_id][i] {
+                            max_id = input_id;
+                        }
+                    }
+                    max_indices.push(max_id);
+                    inputs[max_id][i]
+                }).collect::<Vec<_>>()
+            }
+        };
+
+        output_data[0].write_slice(&output[..]).unwrap();
+    }
+}
+
+impl ComputeInputGradient<f32> for Eltwise {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let inputs = input_data.iter().map(|input| input.as_slice().unwrap()).collect::<Vec<_>>();
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+        let max_indices = self.max_indices.borrow();
+
+        for (input_id, input_gradient) in input_gradients.iter_mut().enumerate() {
+            let gradient = output_gradient.iter().enumerate().map(|(i, &dy)| {
+                match self.operation {
+                    EltwiseOperation::Sum => self.coefficient(input_id) * dy,
+                    EltwiseOperation::Product => Self::product_of_others(&inputs, input_id, i) * dy,
+                    EltwiseOperation::Max => if max_indices[i] == input_id { dy } else { 0f32 },
+                }
+            }).collect::<Vec<_>>();
+            input_gradient.write_slice(&gradient[..]).unwrap();
+        }
+    }
+}
+
+impl ComputeParametersGradient<f32> for Eltwise { }
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// The element-wise operations of a Eltwise layer.
+pub enum EltwiseOperation {
+    /// The weighted sum of the inputs.
+    Sum,
+    /// The product of the inputs.
+    Product,
+    /// The maximum of the inputs.
+    Max,
+}
+
+impl EltwiseOperation {
+    /// Return the corresponding Cap'n Proto value.
+    fn to_capnp(&self) -> CapnpEltwiseOperation {
+        match *self {
+            EltwiseOperation::Sum => CapnpEltwiseOperation::Sum,
+            EltwiseOperation::Product => CapnpEltwiseOperation::Product,
+            EltwiseOperation::Max => CapnpEltwiseOperation::Max,
+        }
+    }
+
+    /// Return the enum value for a Cap'n Proto value.
+    fn from_capnp(value: CapnpEltwiseOperation) -> Self {
+        match value {
+            CapnpEltwiseOperation::Sum => EltwiseOperation::Sum,
+            CapnpEltwiseOperation::Product => EltwiseOperation::Product,
+            CapnpEltwiseOperation::Max => EltwiseOperation::Max,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Specifies configuration parameters for a Eltwise Layer.
+pub struct EltwiseConfig {
+    /// The operation that combines the inputs.
+    ///
+    /// Default: Sum
+    pub operation: EltwiseOperation,
+    /// The coefficient of every input for the `Sum` operation.
+    ///
+    /// If empty, all inputs have the coefficient `1`.
+    pub coefficients: Vec<f32>,
+}
+
+impl Default for EltwiseConfig {
+    fn default() -> EltwiseConfig {
+        EltwiseConfig {
+            operation: EltwiseOperation::Sum,
+            coefficients: Vec::new(),
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for EltwiseConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the EltwiseConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.borrow().set_operation(self.operation.to_capnp());
+        let mut coefficients = builder.borrow().init_coefficients(self.coefficients.len() as u32);
+        for (i, coefficient) in self.coefficients.iter().enumerate() {
+            coefficients.set(i as u32, *coefficient);
+        }
+    }
+}
+
+impl<'a> CapnpRead<'a> for EltwiseConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        let read_coefficients = reader.get_coefficients().unwrap();
+        let mut coefficients = Vec::new();
+        for i in 0..read_coefficients.len() {
+            coefficients.push(read_coefficients.get(i))
+        }
+
+        EltwiseConfig {
+            operation: EltwiseOperation::from_capnp(reader.get_operation().unwrap()),
+            coefficients: coefficients,
+        }
+    }
+}
+
+impl Into<LayerType> for EltwiseConfig {
+    fn into(self) -> LayerType {
+        LayerType::Eltwise(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Eltwise;
+
+    #[test]
+    fn product_gradient_handles_zeros() {
+        let inputs: [&[f32]; 3] = [&[0f32, 2f32], &[3f32, 4f32], &[5f32, 0f32]];
+        assert_eq!(Eltwise::product_of_others(&inputs, 0, 0), 15f32);
+        assert_eq!(Eltwise::product_of_others(&inputs, 1, 0), 0f32);
+        assert_eq!(Eltwise::product_of_others(&inputs, 2, 1), 8f32);
+    }
+}
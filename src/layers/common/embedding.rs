@@ -0,0 +1,341 @@
+//! Looks up the embedding vectors of integer indices, e.g. of tokens in a vocabulary.
+//!
+//! The input holds indices in `[0, vocab_size)` and can have an arbitrary shape, e.g.
+//! `[batch_size, sequence_length]`. Every index is replaced by its row of the learnable
+//! `[vocab_size, embedding_dim]` weight, so the output has the shape of the input with
+//! an additional trailing axis of size `embedding_dim`.
+//!
+//! This is equivalent to a [Linear][linear] layer applied to one-hot encoded indices,
+//! but doesn't require to materialize the one-hot vectors. Likewise the gradient only
+//! accumulates into the rows of the indices in the batch, all other rows stay zero.
+//!
+//! Optionally a `padding_index` can be configured, whose embedding is always zero
+//! and never receives a gradient. The padding row of the weight is zeroed when the
+//! layer is created, but a filler configured in the [WeightConfig][weight_config] or
+//! loaded weights may leave other values in it. They are ignored, since the padding
+//! index is never looked up in the weight. With a `max_norm`, embeddings with a larger
+//! euclidean norm are scaled down to it before they are used.
+//!
+//! The weight is created like the weight of any other layer, so it can be shared
+//! between layers by giving it a name in the [WeightConfig][weight_config], e.g. to tie
+//! the embedding to the output projection of a language model.
+//!
+//! [linear]: ../linear/index.html
+//! [weight_config]: ../../../weight/struct.WeightConfig.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::embedding_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+use crate::weight::FillerType;
+
+use parenchyma::prelude::SharedTensor;
+use std::cell::RefCell;
+
+#[derive(Debug, Clone)]
+/// Embedding Layer
+pub struct Embedding {
+    vocab_size: usize,
+    embedding_dim: usize,
+    padding_index: Option<usize>,
+    max_norm: Option<f32>,
+
+    // the factor every looked up embedding was scaled by in the last forward pass
+    scales: RefCell<Vec<f32>>,
+}
+
+impl Embedding {
+    /// Create a Embedding layer from a EmbeddingConfig.
+    pub fn from_config(config: &EmbeddingConfig) -> Embedding {
+        if let Some(padding_index) = config.padding_index {
+            if padding_index >= config.vocab_size {
+                panic!("Padding index {} is out of range for an Embedding layer with a vocabulary of {}.", padding_index, config.vocab_size);
+            }
+        }
+
+        Embedding {
+            vocab_size: config.vocab_size,
+            embedding_dim: config.embedding_dim,
+            padding_index: config.padding_index,
+            max_norm: config.max_norm,
+
+            scales: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn calculate_output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        let mut output_shape = input_shape.to_vec();
+        output_shape.push(self.embedding_dim);
+        output_shape
+    }
+
+    /// Returns the embedding row of `index`, or `None` if it is the padding index.
+    fn row(&self, index: f32) -> Option<usize> {
+        if index.fract() != 0f32 {
+            panic!("Index {} is not a valid index for an Embedding layer.", index);
+        }
+        let row = index as usize;
+        if index < 0f32 || row >= self.vocab_size {
+            panic!("Index {} is out of range for an Embedding layer with a vocabulary of {}.", index, self.vocab_size);
+        }
+        if Some(row) == self.padding_index {
+            None
+        } else {
+            Some(row)
+        }
+    }
+
+    /// The factor an embedding is scaled by to satisfy the `max_norm`.
+    fn scale(&self, embedding: &[f32]) -> f32 {
+        match self.max_norm {
+            Some(max_norm) => {
+                let norm = embedding.iter().fold(0f32, |sum, &value| sum + value * value).sqrt();
+                if norm > max_norm { max_norm / norm } else { 1f32 }
+            }
+            None => 1f32,
+        }
+    }
+}
+
+impl LayerWorker for Embedding {
+    impl_ilayer_common!();
+
+    fn auto_weight_blobs(&self) -> bool {
+        true
+    }
+
+    fn exact_num_weight_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let input = input_data[0].read().unwrap();
+        let output_shape = self.calculate_output_shape(input.shape().dimensions());
+        input_gradient[0].write().unwrap().resize(input.shape().clone()).unwrap();
+        output_data[0].write().unwrap().resize(&output_shape[..]).unwrap();
+        output_gradient[0].write().unwrap().resize(&output_shape[..]).unwrap();
+
+        let weight_shape = [self.vocab_size, self.embedding_dim];
+        if let Some(weight) = weights_data.get(0) {
+            let mut weight = weight.write().unwrap();
+            weight.resize(&weight_shape[..]).unwrap();
+            let filler = FillerType::Glorot {
+                input_size: self.vocab_size,
+                output_size: self.embedding_dim,
+            };
+            filler.fill(&mut weight);
+            if let Some(padding_index) = self.padding_index {
+                let dim = self.embedding_dim;
+                for value in &mut weight.as_mut_slice().unwrap()[(padding_index * dim)..((padding_index + 1) * dim)] {
+                    *value = 0f32;
+                }
+            }
+        }
+        if let Some(gradient) = weights_gradient.get(0) {
+            gradient.write().unwrap().resize(&weight_shape[..]).unwrap();
+        }
+        self.scales.borrow_mut().resize(input.shape().capacity(), 1f32);
+    }
+}
+
+impl ComputeOutput<f32> for Embedding {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let dim = self.embedding_dim;
+        let indices = input_data[0].as_slice().unwrap();
+        let weight = weights[0].as_slice().unwrap();
+        let output = output_data[0].as_mut_slice().unwrap();
+        let mut scales = self.scales.borrow_mut();
+
+        for ((&index, scale), embedding) in indices.iter().zip(scales.iter_mut()).zip(output.chunks_mut(dim)) {
+            match self.row(index) {
+                Some(row) => {
+                    let weight_row = &weight[(row * dim)..((row + 1) * dim)];
+                    *scale = self.scale(weight_row);
+                    for (value, &w) in embedding.iter_mut().zip(weight_row) {
+                        *value = *scale * w;
+                    }
+                }
+                None => {
+                    *scale = 0f32;
+                    for value in embedding.iter_mut() {
+                        *value = 0f32;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl ComputeInputGradient<f32> for Embedding {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        // the indices are not differentiable
+        let zeros = vec![0f32; input_gradients[0].shape().capacity()];
+        input_gradients[0].write_slice(&zeros[..]).unwrap();
+    }
+}
+
+impl ComputeParametersGradient<f32> for Embedding {
+    fn compute_parameters_gradient(&self,
+                                   backend: &LeafBackend,
+                                   output_data: &[&SharedTensor<f32>],
+                                   output_gradients: &[&SharedTensor<f32>],
+                                   input_data: &[&SharedTensor<f32>],
+                                   parameters_gradients: &mut [&mut SharedTensor<f32>]) {
+        let dim = self.embedding_dim;
+        let indices = input_data[0].as_slice().unwrap();
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+
+        // the solver leaves its update values in the gradient, so it is zeroed in place
+        // instead of allocating a new one. Only the rows of the looked up indices receive
+        // a gradient, an index that occurs multiple times accumulates all of them.
+        // The max_norm scaling is treated as a constant, like a renormalization of the weight.
+        let scales = self.scales.borrow();
+        let weight_gradient = parameters_gradients[0].as_mut_slice().unwrap();
+        for value in weight_gradient.iter_mut() {
+            *value = 0f32;
+        }
+        for ((&index, &scale), gradient) in indices.iter().zip(scales.iter()).zip(output_gradient.chunks(dim)) {
+            if let Some(row) = self.row(index) {
+                for (accumulated, &value) in weight_gradient[(row * dim)..((row + 1) * dim)].iter_mut().zip(gradient) {
+                    *accumulated += scale * value;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a Embedding Layer.
+pub struct EmbeddingConfig {
+    /// The number of different indices, i.e. the number of rows of the weight.
+    pub vocab_size: usize,
+    /// The size of every embedding vector, i.e. the number of columns of the weight.
+    pub embedding_dim: usize,
+    /// An index whose embedding is always zero and doesn't receive a gradient.
+    ///
+    /// Default: None
+    pub padding_index: Option<usize>,
+    /// The maximal euclidean norm of an embedding.
+    ///
+    /// Default: None
+    pub max_norm: Option<f32>,
+}
+
+impl EmbeddingConfig {
+    /// Create a EmbeddingConfig for a vocabulary of `vocab_size` indices
+    /// embedded into `embedding_dim` dimensions.
+    pub fn new(vocab_size: usize, embedding_dim: usize) -> EmbeddingConfig {
+        EmbeddingConfig {
+            vocab_size: vocab_size,
+            embedding_dim: embedding_dim,
+            padding_index: None,
+            max_norm: None,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for EmbeddingConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the EmbeddingConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_vocab_size(self.vocab_size as u64);
+        builder.set_embedding_dim(self.embedding_dim as u64);
+        builder.set_padding_index(self.padding_index.map_or(-1, |index| index as i64));
+        builder.set_max_norm(self.max_norm.unwrap_or(0f32));
+    }
+}
+
+impl<'a> CapnpRead<'a> for EmbeddingConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        let padding_index = match reader.get_padding_index() {
+            index if index >= 0 => Some(index as usize),
+            _ => None,
+        };
+        let max_norm = match reader.get_max_norm() {
+            max_norm if max_norm > 0f32 => Some(max_norm),
+            _ => None,
+        };
+
+        EmbeddingConfig {
+            vocab_size: reader.get_vocab_size() as usize,
+            embedding_dim: reader.get_embedding_dim() as usize,
+            padding_index: padding_index,
+            max_norm: max_norm,
+        }
+    }
+}
+
+impl Into<LayerType> for EmbeddingConfig {
+    fn into(self) -> LayerType {
+        LayerType::Embedding(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Embedding, EmbeddingConfig};
+
+    #[test]
+    fn appends_embedding_axis() {
+        let layer = Embedding::from_config(&EmbeddingConfig::new(100, 8));
+        assert_eq!(layer.calculate_output_shape(&[4, 12]), vec![4, 12, 8]);
+    }
+
+    #[test]
+    fn masks_padding_index() {
+        let layer = Embedding::from_config(&EmbeddingConfig { padding_index: Some(0), ..EmbeddingConfig::new(10, 2) });
+        assert_eq!(layer.row(0f32), None);
+        assert_eq!(layer.row(3f32), Some(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "not a valid index")]
+    fn rejects_fractional_indices() {
+        Embedding::from_config(&EmbeddingConfig::new(10, 2)).row(2.7f32);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a valid index")]
+    fn rejects_nan_indices() {
+        Embedding::from_config(&EmbeddingConfig::new(10, 2)).row(::std::f32::NAN);
+    }
+
+    #[test]
+    #[should_panic(expected = "Index -1 is out of range")]
+    fn rejects_negative_indices() {
+        Embedding::from_config(&EmbeddingConfig::new(10, 2)).row(-1f32);
+    }
+
+    #[test]
+    #[should_panic(expected = "Padding index 10 is out of range")]
+    fn rejects_padding_index_outside_of_vocabulary() {
+        Embedding::from_config(&EmbeddingConfig { padding_index: Some(10), ..EmbeddingConfig::new(10, 2) });
+    }
+
+    #[test]
+    fn scales_down_to_max_norm() {
+        let layer = Embedding::from_config(&EmbeddingConfig { max_norm: Some(1f32), ..EmbeddingConfig::new(10, 2) });
+        assert_eq!(layer.scale(&[3f32, 4f32]), 0.2f32);
+        assert_eq!(layer.scale(&[0.3f32, 0.4f32]), 1f32);
+    }
+}
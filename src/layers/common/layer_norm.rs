@@ -0,0 +1,275 @@
+//! Normalizes every sample over its trailing axes ([Layer Normalization][paper]).
+//!
+//! The last `num_axes` axes of the input are normalized to zero mean and unit variance,
+//! independently for every position of the leading axes, and then scaled by the learnable
+//! `gain` and shifted by the learnable `bias` weight. Both weights have the shape of the
+//! normalized axes:
+//!
+//! `y = gain * (x - mean) / sqrt(variance + epsilon) + bias`
+//!
+//! Since the statistics are computed per sample, the layer behaves the same during
+//! training and evaluation and doesn't need running statistics like [BatchNorm][batch_norm].
+//! That makes it suitable for small batches, down to a batch size of 1.
+//!
+//! [paper]: https://arxiv.org/abs/1607.06450
+//! [batch_norm]: ../batch_norm/index.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::layer_norm_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+use crate::weight::FillerType;
+
+use parenchyma::prelude::SharedTensor;
+use std::cell::RefCell;
+
+#[derive(Debug)]
+/// LayerNorm Layer
+pub struct LayerNorm {
+    num_axes: usize,
+    epsilon: f32,
+
+    // the normalized input of the last forward pass
+    normalized: RefCell<Vec<f32>>,
+    // 1 / sqrt(variance + epsilon) per sample of the last forward pass
+    inverse_std: RefCell<Vec<f32>>,
+}
+
+impl LayerNorm {
+    /// Create a LayerNorm layer from a LayerNormConfig.
+    pub fn from_config(config: &LayerNormConfig) -> LayerNorm {
+        if config.num_axes == 0 {
+            panic!("LayerNorm layer needs to normalize over at least one axis.");
+        }
+
+        LayerNorm {
+            num_axes: config.num_axes,
+            epsilon: config.epsilon,
+
+            normalized: RefCell::new(Vec::new()),
+            inverse_std: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Calculates the shape of the normalized trailing axes, which is also the shape of the weights.
+    fn calculate_weight_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        if self.num_axes > input_shape.len() {
+            panic!("Can not normalize {} axes of a {}D input.", self.num_axes, input_shape.len());
+        }
+        input_shape[(input_shape.len() - self.num_axes)..].to_vec()
+    }
+
+    fn calculate_norm_size(&self, input_shape: &[usize]) -> usize {
+        self.calculate_weight_shape(input_shape).iter().fold(1, |prod, i| prod * i)
+    }
+
+    /// Normalizes every chunk of `norm_size` values of `input` into `normalized`
+    /// and returns the inverse standard deviation of every chunk.
+    fn normalize(input: &[f32], norm_size: usize, epsilon: f32, normalized: &mut [f32]) -> Vec<f32> {
+        input.chunks(norm_size).zip(normalized.chunks_mut(norm_size)).map(|(x, x_hat)| {
+            let mean = x.iter().fold(0f32, |sum, &value| sum + value) / norm_size as f32;
+            let variance = x.iter().fold(0f32, |sum, &value| sum + (value - mean) * (value - mean)) / norm_size as f32;
+            let inverse_std = 1f32 / (variance + epsilon).sqrt();
+            for (normalized_value, &value) in x_hat.iter_mut().zip(x) {
+                *normalized_value = (value - mean) * inverse_std;
+            }
+            inverse_std
+        }).collect()
+    }
+}
+
+impl LayerWorker for LayerNorm {
+    impl_ilayer_common!();
+
+    fn auto_weight_blobs(&self) -> bool {
+        true
+    }
+
+    fn exact_num_weight_blobs(&self) -> Option<usize> {
+        Some(2)
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let input = input_data[0].read().unwrap();
+        let input_shape = input.shape().clone();
+        let weight_shape = self.calculate_weight_shape(input_shape.dimensions());
+
+        input_gradient[0].write().unwrap().resize(input_shape.clone()).unwrap();
+        output_data[0].write().unwrap().resize(input_shape.clone()).unwrap();
+        output_gradient[0].write().unwrap().resize(input_shape.clone()).unwrap();
+
+        // the gain starts as 1 and the bias as 0, so the layer initially only normalizes
+        let fillers = [FillerType::Constant { value: 1f32 }, FillerType::Constant { value: 0f32 }];
+        for (weight, filler) in weights_data.iter().zip(&fillers) {
+            let mut weight = weight.write().unwrap();
+            weight.resize(&weight_shape[..]).unwrap();
+            filler.fill(&mut weight);
+        }
+        for gradient in weights_gradient.iter() {
+            gradient.write().unwrap().resize(&weight_shape[..]).unwrap();
+        }
+
+        let norm_size = self.calculate_norm_size(input_shape.dimensions());
+        self.normalized.borrow_mut().resize(input_shape.capacity(), 0f32);
+        self.inverse_std.borrow_mut().resize(input_shape.capacity() / norm_size, 0f32);
+    }
+}
+
+impl ComputeOutput<f32> for LayerNorm {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let norm_size = self.calculate_norm_size(input_data[0].shape().dimensions());
+        let input = input_data[0].as_slice().unwrap();
+        let gain = weights[0].as_slice().unwrap();
+        let bias = weights[1].as_slice().unwrap();
+
+        let mut normalized = self.normalized.borrow_mut();
+        *self.inverse_std.borrow_mut() = Self::normalize(input, norm_size, self.epsilon, &mut normalized);
+
+        let output = output_data[0].as_mut_slice().unwrap();
+        for (y, x_hat) in output.chunks_mut(norm_size).zip(normalized.chunks(norm_size)) {
+            for i in 0..norm_size {
+                y[i] = gain[i] * x_hat[i] + bias[i];
+            }
+        }
+    }
+}
+
+impl ComputeInputGradient<f32> for LayerNorm {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let norm_size = self.calculate_norm_size(input_data[0].shape().dimensions());
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+        let gain = weights_data[0].as_slice().unwrap();
+        let normalized = self.normalized.borrow();
+        let inverse_std = self.inverse_std.borrow();
+        let input_gradient = input_gradients[0].as_mut_slice().unwrap();
+
+        let n = norm_size as f32;
+        for (((dx, dy), x_hat), &inv) in input_gradient.chunks_mut(norm_size)
+                                                        .zip(output_gradient.chunks(norm_size))
+                                                        .zip(normalized.chunks(norm_size))
+                                                        .zip(inverse_std.iter()) {
+            // gradient w.r.t. the normalized input
+            let mut sum = 0f32;
+            let mut sum_normalized = 0f32;
+            for i in 0..norm_size {
+                let dx_hat = dy[i] * gain[i];
+                sum += dx_hat;
+                sum_normalized += dx_hat * x_hat[i];
+            }
+            for i in 0..norm_size {
+                dx[i] = inv / n * (n * dy[i] * gain[i] - sum - x_hat[i] * sum_normalized);
+            }
+        }
+    }
+}
+
+impl ComputeParametersGradient<f32> for LayerNorm {
+    fn compute_parameters_gradient(&self,
+                                   backend: &LeafBackend,
+                                   output_data: &[&SharedTensor<f32>],
+                                   output_gradients: &[&SharedTensor<f32>],
+                                   input_data: &[&SharedTensor<f32>],
+                                   parameters_gradients: &mut [&mut SharedTensor<f32>]) {
+        let norm_size = self.calculate_norm_size(input_data[0].shape().dimensions());
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+        let normalized = self.normalized.borrow();
+
+        let mut gain_gradient = vec![0f32; norm_size];
+        let mut bias_gradient = vec![0f32; norm_size];
+        for (dy, x_hat) in output_gradient.chunks(norm_size).zip(normalized.chunks(norm_size)) {
+            for i in 0..norm_size {
+                gain_gradient[i] += dy[i] * x_hat[i];
+                bias_gradient[i] += dy[i];
+            }
+        }
+
+        parameters_gradients[0].write_slice(&gain_gradient[..]).unwrap();
+        parameters_gradients[1].write_slice(&bias_gradient[..]).unwrap();
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a LayerNorm Layer.
+pub struct LayerNormConfig {
+    /// The number of trailing axes that are normalized.
+    ///
+    /// Default: 1
+    pub num_axes: usize,
+    /// The value added to the variance to avoid a division by zero.
+    ///
+    /// Default: 1e-5
+    pub epsilon: f32,
+}
+
+impl Default for LayerNormConfig {
+    fn default() -> LayerNormConfig {
+        LayerNormConfig {
+            num_axes: 1,
+            epsilon: 1e-5f32,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for LayerNormConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the LayerNormConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_num_axes(self.num_axes as u64);
+        builder.set_epsilon(self.epsilon);
+    }
+}
+
+impl<'a> CapnpRead<'a> for LayerNormConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        LayerNormConfig {
+            num_axes: reader.get_num_axes() as usize,
+            epsilon: reader.get_epsilon(),
+        }
+    }
+}
+
+impl Into<LayerType> for LayerNormConfig {
+    fn into(self) -> LayerType {
+        LayerType::LayerNorm(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LayerNorm, LayerNormConfig};
+
+    #[test]
+    fn normalizes_trailing_axes() {
+        let layer = LayerNorm::from_config(&LayerNormConfig { num_axes: 2, ..LayerNormConfig::default() });
+        assert_eq!(layer.calculate_weight_shape(&[4, 3, 2, 5]), vec![2, 5]);
+        assert_eq!(layer.calculate_norm_size(&[4, 3, 2, 5]), 10);
+    }
+
+    #[test]
+    fn normalizes_every_sample() {
+        let mut normalized = [0f32; 4];
+        let inverse_std = LayerNorm::normalize(&[1f32, 3f32, 10f32, 14f32], 2, 0f32, &mut normalized);
+        assert_eq!(normalized, [-1f32, 1f32, -1f32, 1f32]);
+        assert_eq!(inverse_std, vec![1f32, 0.5f32]);
+    }
+}
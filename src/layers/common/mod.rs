@@ -10,20 +10,31 @@ macro_rules! impl_ilayer_common {
     )
 }
 
-#[cfg(all(feature="cuda", not(feature="native")))]
+pub use self::attention::{MultiHeadAttention, MultiHeadAttentionConfig,
+                          ScaledDotProductAttention, ScaledDotProductAttentionConfig};
+pub use self::batch_norm::{BatchNorm, BatchNormConfig};
 pub use self::convolution::{Convolution, ConvolutionConfig};
+pub use self::dropout::{Dropout, DropoutConfig};
+pub use self::eltwise::{Eltwise, EltwiseConfig, EltwiseOperation};
+pub use self::embedding::{Embedding, EmbeddingConfig};
+pub use self::layer_norm::{LayerNorm, LayerNormConfig};
 pub use self::linear::{Linear, LinearConfig};
 pub use self::log_softmax::LogSoftmax;
-#[cfg(all(feature="cuda", not(feature="native")))]
 pub use self::pooling::{Pooling, PoolingConfig, PoolingMode};
+pub use self::recurrent::{CellType, Recurrent, RecurrentConfig};
 pub use self::softmax::Softmax;
 
-#[cfg(all(feature="cuda", not(feature="native")))]
+pub mod attention;
+pub mod batch_norm;
 pub mod convolution;
+pub mod dropout;
+pub mod eltwise;
+pub mod embedding;
+pub mod layer_norm;
 pub mod linear;
 pub mod log_softmax;
-#[cfg(all(feature="cuda", not(feature="native")))]
 pub mod pooling;
+pub mod recurrent;
 pub mod softmax;
 
 /// Provides common utilities for Layers that utilize a filter with stride and padding.
@@ -8,53 +8,98 @@
 //!
 //! ## Input Data
 //!
-//! The layer expects the input to be in either 4D NCHW (2 spatial dimensions)
-//! or 5D NCDHW (3 spatial dimensions) format.
-use std::rc::Rc;
-use co::{IBackend, SharedTensor};
-use conn;
-use layer::*;
-use util::{ArcLock, cast_vec_usize_to_i32};
+//! The layer expects the input to be in 4D NCHW format (2 spatial dimensions).
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::pooling_config as capnp_config;
+use crate::cerealization_protocol::PoolingMode as CapnpPoolingMode;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
 use super::FilterLayer;
-use leaf_capnp::pooling_config as capnp_config;
-use leaf_capnp::PoolingMode as CapnpPoolingMode;
 
-use crate::utilities::cerealization_protocol::*;
+use parenchyma::prelude::SharedTensor;
 
 #[derive(Debug, Clone)]
 /// [Pooling](./index.html) Layer
-pub struct Pooling<T, B: conn::Pooling<T>> {
+pub struct Pooling {
     mode: PoolingMode,
 
     filter_shape: Vec<usize>,
     stride: Vec<usize>,
     padding: Vec<usize>,
-
-    pooling_configs: Vec<Rc<B::CPOOL>>,
 }
 
-impl<T, B: conn::Pooling<T>> Pooling<T, B> {
+impl Pooling {
     /// Create a Pooling layer from a PoolingConfig.
-    pub fn from_config(config: &PoolingConfig) -> Pooling<T, B> {
+    pub fn from_config(config: &PoolingConfig) -> Pooling {
         Pooling {
             mode: config.mode,
 
             filter_shape: config.filter_shape.clone(),
             stride: config.stride.clone(),
             padding: config.padding.clone(),
+        }
+    }
+
+    /// Calls `f(output_index, window)` for every output value, where `window` contains the
+    /// indices of all input values (inside the unpadded input) that are pooled into it.
+    fn for_each_window<F: FnMut(usize, &[usize])>(&self, input_shape: &[usize], mut f: F) {
+        let num_spatial_dims = self.num_spatial_dims(input_shape);
+        let filter = self.spatial_filter_dims(num_spatial_dims);
+        let padding = self.padding_dims(num_spatial_dims);
+        let stride = self.stride_dims(num_spatial_dims);
+        let output = Self::calculate_spatial_output_dims(&input_shape[2..], &filter, &padding, &stride);
+        let (height, width) = (input_shape[2], input_shape[3]);
+        let num_maps = input_shape[0] * input_shape[1];
 
-            pooling_configs: vec![],
+        let mut window = Vec::with_capacity(filter[0] * filter[1]);
+        for map in 0..num_maps {
+            for oh in 0..output[0] {
+                for ow in 0..output[1] {
+                    window.clear();
+                    let h_start = (oh * stride[0]) as isize - padding[0] as isize;
+                    let w_start = (ow * stride[1]) as isize - padding[1] as isize;
+                    for h in h_start..(h_start + filter[0] as isize) {
+                        for w in w_start..(w_start + filter[1] as isize) {
+                            if h >= 0 && h < height as isize && w >= 0 && w < width as isize {
+                                window.push((map * height + h as usize) * width + w as usize);
+                            }
+                        }
+                    }
+                    f((map * output[0] + oh) * output[1] + ow, &window);
+                }
+            }
         }
     }
+
+    /// Pools every window of `input` into the corresponding value of `output`.
+    fn pool(&self, input_shape: &[usize], input: &[f32], output: &mut [f32]) {
+        let window_size = self.window_size(input_shape) as f32;
+        match self.mode {
+            PoolingMode::Max => self.for_each_window(input_shape, |output_index, window| {
+                output[output_index] = window.iter()
+                    .map(|&i| input[i])
+                    .fold(::std::f32::NEG_INFINITY, f32::max);
+            }),
+            PoolingMode::Average => self.for_each_window(input_shape, |output_index, window| {
+                output[output_index] = window.iter().map(|&i| input[i]).sum::<f32>() / window_size;
+            }),
+        }
+    }
+
+    /// The number of values an average is computed over, including padded values.
+    fn window_size(&self, input_shape: &[usize]) -> usize {
+        let num_spatial_dims = self.num_spatial_dims(input_shape);
+        self.spatial_filter_dims(num_spatial_dims).iter().fold(1, |prod, i| prod * i)
+    }
 }
 
-impl<T, B: conn::Pooling<T>> FilterLayer for Pooling<T, B> {
+impl FilterLayer for Pooling {
     /// Calculates the number of spatial dimensions for the pooling operation.
     fn num_spatial_dims(&self, input_shape: &[usize]) -> usize {
         match input_shape.len() {
             4 => 2,
-            5 => 3,
-            _ => panic!("A pooling layer currently only supports 4D or 5D input.")
+            _ => panic!("A pooling layer currently only supports 4D input.")
         }
     }
 
@@ -87,66 +132,82 @@ impl<T, B: conn::Pooling<T>> FilterLayer for Pooling<T, B> {
     }
 }
 
-impl<B: IBackend + conn::Pooling<f32>> LayerWorker<B> for Pooling<f32, B> {
+impl LayerWorker for Pooling {
     impl_ilayer_common!();
 
+    fn sync_native(&self) -> bool {
+        true
+    }
+
     fn reshape(&mut self,
-               backend: ::std::rc::Rc<B>,
-               input_data: &mut Vec<ArcLock<SharedTensor<f32>>>,
-               input_gradient: &mut Vec<ArcLock<SharedTensor<f32>>>,
-               weights_data: &mut Vec<ArcLock<SharedTensor<f32>>>,
-               weights_gradient: &mut Vec<ArcLock<SharedTensor<f32>>>,
-               output_data: &mut Vec<ArcLock<SharedTensor<f32>>>,
-               output_gradient: &mut Vec<ArcLock<SharedTensor<f32>>>) {
-        for i in 0..input_data.len() {
-            let inp = input_data[0].read().unwrap();
-            let input_shape = inp.desc();
-            let output_shape = self.calculate_output_shape(input_shape);
-            output_data[0].write().unwrap().resize(&output_shape).unwrap();
-            output_gradient[0].write().unwrap().resize(&output_shape).unwrap();
-
-            let num_spatial_dims = self.num_spatial_dims(inp.desc());
-            let filter = cast_vec_usize_to_i32(self.spatial_filter_dims(num_spatial_dims));
-            let stride = cast_vec_usize_to_i32(self.stride_dims(num_spatial_dims));
-            let padding = cast_vec_usize_to_i32(self.padding_dims(num_spatial_dims));
-
-            let config = backend.new_pooling_config(&filter, &padding, &stride).unwrap();
-            self.pooling_configs.push(Rc::new(config));
-        }
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let inp = input_data[0].read().unwrap();
+        let input_shape = inp.shape().dimensions().to_owned();
+        let output_shape = self.calculate_output_shape(&input_shape);
+        input_gradient[0].write().unwrap().resize(&input_shape[..]).unwrap();
+        output_data[0].write().unwrap().resize(&output_shape[..]).unwrap();
+        output_gradient[0].write().unwrap().resize(&output_shape[..]).unwrap();
     }
 }
 
-impl<B: IBackend + conn::Pooling<f32>> ComputeOutput<f32, B> for Pooling<f32, B> {
+impl ComputeOutput<f32> for Pooling {
     fn compute_output(&self,
-                      backend: &B,
+                      backend: &LeafBackend,
                       weights: &[&SharedTensor<f32>],
                       input_data: &[&SharedTensor<f32>],
                       output_data: &mut [&mut SharedTensor<f32>]) {
-        let config = &self.pooling_configs[0];
-        match self.mode {
-            PoolingMode::Max => backend.pooling_max_plain(input_data[0], output_data[0], &*config).unwrap(),
-            // TODO: implement average pooling
-            // PoolingMode::Average => unimplemented!(),
-        }
+        let input_shape = input_data[0].shape().dimensions().to_owned();
+        self.pool(&input_shape, input_data[0].as_slice().unwrap(), output_data[0].as_mut_slice().unwrap());
     }
 }
 
-impl<B: IBackend + conn::Pooling<f32>> ComputeInputGradient<f32, B> for Pooling<f32, B> {
+impl ComputeInputGradient<f32> for Pooling {
     fn compute_input_gradient(&self,
-                              backend: &B,
+                              backend: &LeafBackend,
                               _weights_data: &[&SharedTensor<f32>],
                               output_data: &[&SharedTensor<f32>],
                               output_gradients: &[&SharedTensor<f32>],
                               input_data: &[&SharedTensor<f32>],
                               input_gradients: &mut [&mut SharedTensor<f32>]) {
-        let config = &self.pooling_configs[0];
+        let input_shape = input_data[0].shape().dimensions().to_owned();
+        let window_size = self.window_size(&input_shape) as f32;
+        let input = input_data[0].as_slice().unwrap();
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+        let input_gradient = input_gradients[0].as_mut_slice().unwrap();
+        for value in input_gradient.iter_mut() {
+            *value = 0f32;
+        }
+
         match self.mode {
-            PoolingMode::Max => backend.pooling_max_grad_plain(output_data[0], output_gradients[0], input_data[0], input_gradients[0], config).unwrap()
+            // the gradient only flows to the (first) maximum of each window
+            PoolingMode::Max => self.for_each_window(&input_shape, |output_index, window| {
+                let mut max_index = None;
+                for &i in window {
+                    match max_index {
+                        Some(m) if input[m] >= input[i] => {},
+                        _ => max_index = Some(i),
+                    }
+                }
+                if let Some(m) = max_index {
+                    input_gradient[m] += output_gradient[output_index];
+                }
+            }),
+            PoolingMode::Average => self.for_each_window(&input_shape, |output_index, window| {
+                for &i in window {
+                    input_gradient[i] += output_gradient[output_index] / window_size;
+                }
+            }),
         }
     }
 }
 
-impl<B: IBackend + conn::Pooling<f32>> ComputeParametersGradient<f32, B> for Pooling<f32, B> { }
+impl ComputeParametersGradient<f32> for Pooling { }
 
 #[derive(Debug, Clone)]
 /// Specifies configuration parameters for a Pooling Layer.
@@ -230,8 +291,10 @@ impl<'a> CapnpRead<'a> for PoolingConfig {
 pub enum PoolingMode {
     /// The maximum value inside the pooling window will be used as result.
     Max,
-    // /// The average of all values inside the pooling window will be used as result.
-    // Average,
+    /// The average of all values inside the pooling window will be used as result.
+    ///
+    /// Padded values count as zeros, so the sum is always divided by the full window size.
+    Average,
 }
 
 impl PoolingMode {
@@ -239,6 +302,7 @@ impl PoolingMode {
     fn to_capnp(&self) -> CapnpPoolingMode {
         match *self {
             PoolingMode::Max => CapnpPoolingMode::Max,
+            PoolingMode::Average => CapnpPoolingMode::Average,
         }
     }
 
@@ -246,7 +310,67 @@ impl PoolingMode {
     fn from_capnp(value: CapnpPoolingMode) -> Self {
         match value {
             CapnpPoolingMode::Max => PoolingMode::Max,
-            CapnpPoolingMode::Average => unimplemented!(),
+            CapnpPoolingMode::Average => PoolingMode::Average,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Pooling, PoolingConfig, PoolingMode};
+
+    fn pooling(mode: PoolingMode, padding: usize) -> Pooling {
+        Pooling::from_config(&PoolingConfig {
+            mode: mode,
+            filter_shape: vec![2],
+            stride: vec![2],
+            padding: vec![padding],
+        })
+    }
+
+    #[test]
+    fn max_selects_largest_value_of_each_window() {
+        let input = [1f32, -2f32, 3f32, 0f32,
+                     5f32, -6f32, -1f32, 2f32,
+                     -9f32, -8f32, 4f32, 4f32,
+                     -7f32, -5f32, 4f32, 1f32];
+        let mut output = vec![0f32; 4];
+        pooling(PoolingMode::Max, 0).pool(&[1, 1, 4, 4], &input, &mut output);
+        assert_eq!(vec![5f32, 3f32,
+                        -5f32, 4f32], output);
+    }
+
+    #[test]
+    fn max_ignores_padding() {
+        // a padded zero would be larger than every input value
+        let input = [-1f32, -2f32,
+                     -3f32, -4f32];
+        let mut output = vec![0f32; 4];
+        pooling(PoolingMode::Max, 1).pool(&[1, 1, 2, 2], &input, &mut output);
+        assert_eq!(vec![-1f32, -2f32,
+                        -3f32, -4f32], output);
+    }
+
+    #[test]
+    fn average_divides_by_full_window_size() {
+        let input = [1f32, 2f32, 3f32, 4f32,
+                     5f32, 6f32, 7f32, 8f32,
+                     1f32, 1f32, 2f32, 2f32,
+                     1f32, 1f32, 2f32, 2f32];
+        let mut output = vec![0f32; 4];
+        pooling(PoolingMode::Average, 0).pool(&[1, 1, 4, 4], &input, &mut output);
+        assert_eq!(vec![3.5f32, 5.5f32,
+                        1f32, 2f32], output);
+    }
+
+    #[test]
+    fn average_counts_padding_as_zeros() {
+        // every window contains a single input value and three padded values
+        let input = [4f32, 8f32,
+                     12f32, 16f32];
+        let mut output = vec![0f32; 4];
+        pooling(PoolingMode::Average, 1).pool(&[1, 1, 2, 2], &input, &mut output);
+        assert_eq!(vec![1f32, 2f32,
+                        3f32, 4f32], output);
+    }
+}
@@ -0,0 +1,567 @@
+//! Applies a recurrent neural network (RNN, LSTM or GRU) to a sequence.
+//!
+//! The input has the shape `[seq_len, batch_size, input_size]`. The layer unrolls the
+//! recurrence over the sequence internally and outputs the hidden state of every step,
+//! as a tensor of shape `[seq_len, batch_size, num_directions * hidden_size]`.
+//!
+//! The supported cells are:
+//!
+//! - `RNN`: `h = tanh(W_ih x + W_hh h' + b)`
+//! - `LSTM` ([Hochreiter and Schmidhuber 1997][lstm]) with input, forget, cell and output gates
+//! - `GRU` ([Cho et al. 2014][gru]) with reset, update and new gates,
+//!   where `n = tanh(W_in x + b_n + r * (W_hn h'))`
+//!
+//! Optionally the initial hidden state (and for LSTMs the initial cell state) can be supplied as
+//! second (and third) input of shape `[num_directions, batch_size, hidden_size]`, otherwise the
+//! recurrence starts with zeros. The gradients w.r.t. these states are computed as well.
+//!
+//! In `bidirectional` mode a second recurrence runs from the end of the sequence to its start,
+//! and its hidden states are concatenated to the ones of the forward recurrence.
+//!
+//! Every gate has its own input weight `[hidden_size, input_size]`, hidden weight
+//! `[hidden_size, hidden_size]` and bias `[hidden_size]`, which are named after the gate,
+//! e.g. `weight_ih_forget` or `bias_reverse` (for the reverse direction of a bidirectional RNN).
+//!
+//! The gradients are computed with backpropagation through time over the whole sequence.
+//! The weight gradients are computed together with the input gradients.
+//!
+//! [lstm]: http://www.bioinf.jku.at/publications/older/2604.pdf
+//! [gru]: https://arxiv.org/abs/1406.1078
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::recurrent_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+use crate::weight::FillerType;
+
+use parenchyma::prelude::SharedTensor;
+use std::cell::RefCell;
+
+// the weights of every gate, in the order they are created
+const WEIGHT_INPUT: usize = 0;
+const WEIGHT_HIDDEN: usize = 1;
+const BIAS: usize = 2;
+const WEIGHTS_PER_GATE: usize = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// The cell that is applied at every step of a [Recurrent][1] layer.
+/// [1]: ./struct.Recurrent.html
+pub enum CellType {
+    /// A simple (Elman) RNN cell with a tanh activation.
+    RNN,
+    /// A Long Short-Term Memory cell.
+    LSTM,
+    /// A Gated Recurrent Unit.
+    GRU,
+}
+
+impl CellType {
+    fn gate_names(&self) -> &'static [&'static str] {
+        match *self {
+            CellType::RNN => &[""],
+            CellType::LSTM => &["input", "forget", "cell", "output"],
+            CellType::GRU => &["reset", "update", "new"],
+        }
+    }
+
+    fn num_gates(&self) -> usize {
+        self.gate_names().len()
+    }
+}
+
+/// The states of one direction during the last forward pass, which are needed for backpropagation.
+#[derive(Debug, Clone, Default)]
+struct DirectionStates {
+    // the activated gates `[seq_len, batch_size, num_gates * hidden_size]`
+    gates: Vec<f32>,
+    // the hidden states `[seq_len + 1, batch_size, hidden_size]`, starting with the initial state
+    hidden: Vec<f32>,
+    // LSTM only: the cell states `[seq_len + 1, batch_size, hidden_size]`
+    cell: Vec<f32>,
+    // GRU only: `W_hn h'` for every step `[seq_len, batch_size, hidden_size]`
+    hidden_new: Vec<f32>,
+}
+
+#[derive(Debug)]
+/// Recurrent Layer
+pub struct Recurrent {
+    cell_type: CellType,
+    hidden_size: usize,
+    bidirectional: bool,
+
+    states: RefCell<Vec<DirectionStates>>,
+    // the weight gradients computed during backpropagation through time
+    weights_gradients: RefCell<Vec<Vec<f32>>>,
+}
+
+impl Recurrent {
+    /// Create a Recurrent layer with `cell_type` cells from a RecurrentConfig.
+    pub fn from_config(cell_type: CellType, config: &RecurrentConfig) -> Recurrent {
+        Recurrent {
+            cell_type: cell_type,
+            hidden_size: config.hidden_size,
+            bidirectional: config.bidirectional,
+
+            states: RefCell::new(Vec::new()),
+            weights_gradients: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn num_directions(&self) -> usize {
+        if self.bidirectional { 2 } else { 1 }
+    }
+
+    fn num_inputs(&self) -> usize {
+        match self.cell_type {
+            CellType::LSTM => 3,
+            _ => 2,
+        }
+    }
+
+    /// Splits the input shape into `(seq_len, batch_size, input_size)`.
+    fn calculate_dims(input_shape: &[usize]) -> (usize, usize, usize) {
+        match input_shape.len() {
+            3 => (input_shape[0], input_shape[1], input_shape[2]),
+            _ => panic!("Recurrent layer expects an input of shape [seq_len, batch_size, input_size]."),
+        }
+    }
+
+    fn weight_index(&self, direction: usize, gate: usize, kind: usize) -> usize {
+        (direction * self.cell_type.num_gates() + gate) * WEIGHTS_PER_GATE + kind
+    }
+
+    /// The point in time of the `step`th step of a direction.
+    fn time(direction: usize, step: usize, seq_len: usize) -> usize {
+        if direction == 0 { step } else { seq_len - 1 - step }
+    }
+}
+
+/// `output += matrix * vector` for a row-major `[output.len(), vector.len()]` matrix.
+fn add_matrix_vector(matrix: &[f32], vector: &[f32], output: &mut [f32]) {
+    for (out, row) in output.iter_mut().zip(matrix.chunks(vector.len())) {
+        *out += row.iter().zip(vector).fold(0f32, |sum, (&m, &v)| sum + m * v);
+    }
+}
+
+/// `output += transpose(matrix) * vector` for a row-major `[vector.len(), output.len()]` matrix.
+fn add_transposed_matrix_vector(matrix: &[f32], vector: &[f32], output: &mut [f32]) {
+    let cols = output.len();
+    for (&v, row) in vector.iter().zip(matrix.chunks(cols)) {
+        for (out, &m) in output.iter_mut().zip(row) {
+            *out += m * v;
+        }
+    }
+}
+
+/// `matrix += left * transpose(right)` for a row-major `[left.len(), right.len()]` matrix.
+fn add_outer_product(matrix: &mut [f32], left: &[f32], right: &[f32]) {
+    for (row, &l) in matrix.chunks_mut(right.len()).zip(left) {
+        for (m, &r) in row.iter_mut().zip(right) {
+            *m += l * r;
+        }
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1f32 / (1f32 + (-x).exp())
+}
+
+impl LayerWorker for Recurrent {
+    fn exact_num_output_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn auto_weight_blobs(&self) -> bool {
+        true
+    }
+
+    fn exact_num_weight_blobs(&self) -> Option<usize> {
+        Some(self.num_directions() * self.cell_type.num_gates() * WEIGHTS_PER_GATE)
+    }
+
+    fn weight_name(&self, weight_id: usize) -> Option<String> {
+        let num_gates = self.cell_type.num_gates();
+        let kind = match weight_id % WEIGHTS_PER_GATE {
+            WEIGHT_INPUT => "weight_ih",
+            WEIGHT_HIDDEN => "weight_hh",
+            _ => "bias",
+        };
+        let gate = self.cell_type.gate_names()[(weight_id / WEIGHTS_PER_GATE) % num_gates];
+        let direction = weight_id / (WEIGHTS_PER_GATE * num_gates);
+
+        let mut name = kind.to_owned();
+        if !gate.is_empty() {
+            name.push('_');
+            name.push_str(gate);
+        }
+        if direction == 1 {
+            name.push_str("_reverse");
+        }
+        Some(name)
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        if input_data.is_empty() || input_data.len() > self.num_inputs() {
+            panic!("{:?} layer expects the input sequence and optionally {} initial state(s).",
+                   self.cell_type, self.num_inputs() - 1);
+        }
+        let input_shape = input_data[0].read().unwrap().shape().dimensions().to_owned();
+        let (seq_len, batch_size, input_size) = Self::calculate_dims(&input_shape);
+        let hidden_size = self.hidden_size;
+
+        let state_shape = [self.num_directions(), batch_size, hidden_size];
+        for (input, gradient) in input_data.iter().zip(input_gradient.iter()).skip(1) {
+            if input.read().unwrap().shape().dimensions() != &state_shape[..] {
+                panic!("The initial states of a {:?} layer need to have the shape {:?}.", self.cell_type, state_shape);
+            }
+            gradient.write().unwrap().resize(&state_shape[..]).unwrap();
+        }
+        input_gradient[0].write().unwrap().resize(&input_shape[..]).unwrap();
+
+        let output_shape = [seq_len, batch_size, self.num_directions() * hidden_size];
+        output_data[0].write().unwrap().resize(&output_shape[..]).unwrap();
+        output_gradient[0].write().unwrap().resize(&output_shape[..]).unwrap();
+
+        for (weight_id, (weight, gradient)) in weights_data.iter().zip(weights_gradient.iter()).enumerate() {
+            let gate = (weight_id / WEIGHTS_PER_GATE) % self.cell_type.num_gates();
+            let (shape, filler) = match weight_id % WEIGHTS_PER_GATE {
+                WEIGHT_INPUT => (vec![hidden_size, input_size],
+                                 FillerType::Glorot { input_size: input_size, output_size: hidden_size }),
+                WEIGHT_HIDDEN => (vec![hidden_size, hidden_size],
+                                  FillerType::Glorot { input_size: hidden_size, output_size: hidden_size }),
+                // start with an open forget gate, so the LSTM remembers by default
+                _ if self.cell_type == CellType::LSTM && gate == 1 => (vec![hidden_size], FillerType::Constant { value: 1f32 }),
+                _ => (vec![hidden_size], FillerType::Constant { value: 0f32 }),
+            };
+            let mut weight = weight.write().unwrap();
+            weight.resize(&shape[..]).unwrap();
+            filler.fill(&mut weight);
+            gradient.write().unwrap().resize(&shape[..]).unwrap();
+        }
+    }
+}
+
+impl ComputeOutput<f32> for Recurrent {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let (seq_len, batch_size, input_size) = Self::calculate_dims(input_data[0].shape().dimensions());
+        let h = self.hidden_size;
+        let num_gates = self.cell_type.num_gates();
+        let num_directions = self.num_directions();
+
+        let input = input_data[0].as_slice().unwrap();
+        let initial_hidden = input_data.get(1).map(|state| state.as_slice().unwrap());
+        let initial_cell = input_data.get(2).map(|state| state.as_slice().unwrap());
+        let weights = weights.iter().map(|weight| weight.as_slice().unwrap()).collect::<Vec<_>>();
+        let output = output_data[0].as_mut_slice().unwrap();
+
+        let mut states = self.states.borrow_mut();
+        states.clear();
+        for direction in 0..num_directions {
+            let state_len = batch_size * h;
+            let mut state = DirectionStates {
+                gates: vec![0f32; seq_len * batch_size * num_gates * h],
+                hidden: vec![0f32; (seq_len + 1) * state_len],
+                ..DirectionStates::default()
+            };
+            if self.cell_type == CellType::LSTM {
+                state.cell = vec![0f32; (seq_len + 1) * state_len];
+            }
+            if self.cell_type == CellType::GRU {
+                state.hidden_new = vec![0f32; seq_len * state_len];
+            }
+            let initial_offset = direction * state_len;
+            if let Some(initial_hidden) = initial_hidden {
+                state.hidden[..state_len].copy_from_slice(&initial_hidden[initial_offset..(initial_offset + state_len)]);
+            }
+            if let Some(initial_cell) = initial_cell {
+                state.cell[..state_len].copy_from_slice(&initial_cell[initial_offset..(initial_offset + state_len)]);
+            }
+
+            for step in 0..seq_len {
+                let t = Self::time(direction, step, seq_len);
+                for n in 0..batch_size {
+                    let x = &input[((t * batch_size + n) * input_size)..][..input_size];
+                    let (previous_hidden, next_hidden) = state.hidden.split_at_mut((step + 1) * state_len);
+                    let h_prev = &previous_hidden[((step * batch_size + n) * h)..][..h];
+                    let h_next = &mut next_hidden[(n * h)..][..h];
+                    let gates = &mut state.gates[((step * batch_size + n) * num_gates * h)..][..(num_gates * h)];
+
+                    // pre-activations of all gates
+                    for gate in 0..num_gates {
+                        let pre_activation = &mut gates[(gate * h)..((gate + 1) * h)];
+                        pre_activation.copy_from_slice(weights[self.weight_index(direction, gate, BIAS)]);
+                        add_matrix_vector(weights[self.weight_index(direction, gate, WEIGHT_INPUT)], x, pre_activation);
+                        // the hidden part of the GRU new gate is added after the reset
+                        if !(self.cell_type == CellType::GRU && gate == 2) {
+                            add_matrix_vector(weights[self.weight_index(direction, gate, WEIGHT_HIDDEN)], h_prev, pre_activation);
+                        }
+                    }
+
+                    match self.cell_type {
+                        CellType::RNN => {
+                            for j in 0..h {
+                                gates[j] = gates[j].tanh();
+                                h_next[j] = gates[j];
+                            }
+                        }
+                        CellType::LSTM => {
+                            let (previous_cell, next_cell) = state.cell.split_at_mut((step + 1) * state_len);
+                            let c_prev = &previous_cell[((step * batch_size + n) * h)..][..h];
+                            let c_next = &mut next_cell[(n * h)..][..h];
+                            for j in 0..h {
+                                let i = sigmoid(gates[j]);
+                                let f = sigmoid(gates[h + j]);
+                                let g = gates[2 * h + j].tanh();
+                                let o = sigmoid(gates[3 * h + j]);
+                                gates[j] = i;
+                                gates[h + j] = f;
+                                gates[2 * h + j] = g;
+                                gates[3 * h + j] = o;
+                                c_next[j] = f * c_prev[j] + i * g;
+                                h_next[j] = o * c_next[j].tanh();
+                            }
+                        }
+                        CellType::GRU => {
+                            let hidden_new = &mut state.hidden_new[((step * batch_size + n) * h)..][..h];
+                            for value in hidden_new.iter_mut() {
+                                *value = 0f32;
+                            }
+                            add_matrix_vector(weights[self.weight_index(direction, 2, WEIGHT_HIDDEN)], h_prev, hidden_new);
+                            for j in 0..h {
+                                let r = sigmoid(gates[j]);
+                                let z = sigmoid(gates[h + j]);
+                                let new = (gates[2 * h + j] + r * hidden_new[j]).tanh();
+                                gates[j] = r;
+                                gates[h + j] = z;
+                                gates[2 * h + j] = new;
+                                h_next[j] = (1f32 - z) * new + z * h_prev[j];
+                            }
+                        }
+                    }
+
+                    let output_offset = (t * batch_size + n) * num_directions * h + direction * h;
+                    output[output_offset..(output_offset + h)].copy_from_slice(h_next);
+                }
+            }
+            states.push(state);
+        }
+    }
+}
+
+impl ComputeInputGradient<f32> for Recurrent {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let (seq_len, batch_size, input_size) = Self::calculate_dims(input_data[0].shape().dimensions());
+        let h = self.hidden_size;
+        let num_gates = self.cell_type.num_gates();
+        let num_directions = self.num_directions();
+        let state_len = batch_size * h;
+
+        let input = input_data[0].as_slice().unwrap();
+        let output_gradient = output_gradients[0].as_slice().unwrap();
+        let weights = weights_data.iter().map(|weight| weight.as_slice().unwrap()).collect::<Vec<_>>();
+        let states = self.states.borrow();
+
+        let mut input_gradient = vec![0f32; input.len()];
+        let mut initial_hidden_gradient = vec![0f32; num_directions * state_len];
+        let mut initial_cell_gradient = vec![0f32; num_directions * state_len];
+        let mut weights_gradients = weights.iter().map(|weight| vec![0f32; weight.len()]).collect::<Vec<_>>();
+
+        for direction in 0..num_directions {
+            let state = &states[direction];
+            // the gradients w.r.t. the states that are passed on to the previous step
+            let mut hidden_gradient = vec![0f32; state_len];
+            let mut cell_gradient = vec![0f32; state_len];
+            // the gradients w.r.t. the pre-activations of the input and the hidden part of every gate
+            let mut input_pre_gradient = vec![0f32; num_gates * h];
+            let mut hidden_pre_gradient = vec![0f32; num_gates * h];
+
+            for step in (0..seq_len).rev() {
+                let t = Self::time(direction, step, seq_len);
+                for n in 0..batch_size {
+                    let x = &input[((t * batch_size + n) * input_size)..][..input_size];
+                    let h_prev = &state.hidden[((step * batch_size + n) * h)..][..h];
+                    let gates = &state.gates[((step * batch_size + n) * num_gates * h)..][..(num_gates * h)];
+                    let output_offset = (t * batch_size + n) * num_directions * h + direction * h;
+
+                    let dh = output_gradient[output_offset..(output_offset + h)].iter()
+                        .zip(&hidden_gradient[(n * h)..((n + 1) * h)])
+                        .map(|(&dy, &dh_next)| dy + dh_next)
+                        .collect::<Vec<_>>();
+                    let mut dh_prev = vec![0f32; h];
+
+                    match self.cell_type {
+                        CellType::RNN => {
+                            for j in 0..h {
+                                input_pre_gradient[j] = dh[j] * (1f32 - gates[j] * gates[j]);
+                            }
+                            hidden_pre_gradient.copy_from_slice(&input_pre_gradient);
+                        }
+                        CellType::LSTM => {
+                            let c_prev = &state.cell[((step * batch_size + n) * h)..][..h];
+                            let c = &state.cell[(((step + 1) * batch_size + n) * h)..][..h];
+                            for j in 0..h {
+                                let (i, f, g, o) = (gates[j], gates[h + j], gates[2 * h + j], gates[3 * h + j]);
+                                let tanh_c = c[j].tanh();
+                                let dc = dh[j] * o * (1f32 - tanh_c * tanh_c) + cell_gradient[n * h + j];
+                                input_pre_gradient[j] = dc * g * i * (1f32 - i);
+                                input_pre_gradient[h + j] = dc * c_prev[j] * f * (1f32 - f);
+                                input_pre_gradient[2 * h + j] = dc * i * (1f32 - g * g);
+                                input_pre_gradient[3 * h + j] = dh[j] * tanh_c * o * (1f32 - o);
+                                cell_gradient[n * h + j] = dc * f;
+                            }
+                            hidden_pre_gradient.copy_from_slice(&input_pre_gradient);
+                        }
+                        CellType::GRU => {
+                            let hidden_new = &state.hidden_new[((step * batch_size + n) * h)..][..h];
+                            for j in 0..h {
+                                let (r, z, new) = (gates[j], gates[h + j], gates[2 * h + j]);
+                                let new_pre_gradient = dh[j] * (1f32 - z) * (1f32 - new * new);
+                                let dr = new_pre_gradient * hidden_new[j];
+                                let dz = dh[j] * (h_prev[j] - new);
+                                dh_prev[j] += dh[j] * z;
+
+                                input_pre_gradient[j] = dr * r * (1f32 - r);
+                                input_pre_gradient[h + j] = dz * z * (1f32 - z);
+                                input_pre_gradient[2 * h + j] = new_pre_gradient;
+                                hidden_pre_gradient[j] = input_pre_gradient[j];
+                                hidden_pre_gradient[h + j] = input_pre_gradient[h + j];
+                                hidden_pre_gradient[2 * h + j] = new_pre_gradient * r;
+                            }
+                        }
+                    }
+
+                    let dx = &mut input_gradient[((t * batch_size + n) * input_size)..][..input_size];
+                    for gate in 0..num_gates {
+                        let input_pre = &input_pre_gradient[(gate * h)..((gate + 1) * h)];
+                        let hidden_pre = &hidden_pre_gradient[(gate * h)..((gate + 1) * h)];
+                        let input_weight = self.weight_index(direction, gate, WEIGHT_INPUT);
+                        let hidden_weight = self.weight_index(direction, gate, WEIGHT_HIDDEN);
+                        let bias = self.weight_index(direction, gate, BIAS);
+
+                        add_outer_product(&mut weights_gradients[input_weight], input_pre, x);
+                        add_outer_product(&mut weights_gradients[hidden_weight], hidden_pre, h_prev);
+                        for (bias_gradient, &value) in weights_gradients[bias].iter_mut().zip(input_pre) {
+                            *bias_gradient += value;
+                        }
+                        add_transposed_matrix_vector(weights[input_weight], input_pre, dx);
+                        add_transposed_matrix_vector(weights[hidden_weight], hidden_pre, &mut dh_prev);
+                    }
+                    hidden_gradient[(n * h)..((n + 1) * h)].copy_from_slice(&dh_prev);
+                }
+            }
+
+            // what is left are the gradients w.r.t. the initial states
+            let initial_offset = direction * state_len;
+            initial_hidden_gradient[initial_offset..(initial_offset + state_len)].copy_from_slice(&hidden_gradient);
+            initial_cell_gradient[initial_offset..(initial_offset + state_len)].copy_from_slice(&cell_gradient);
+        }
+
+        input_gradients[0].write_slice(&input_gradient[..]).unwrap();
+        if let Some(gradient) = input_gradients.get_mut(1) {
+            gradient.write_slice(&initial_hidden_gradient[..]).unwrap();
+        }
+        if let Some(gradient) = input_gradients.get_mut(2) {
+            gradient.write_slice(&initial_cell_gradient[..]).unwrap();
+        }
+        *self.weights_gradients.borrow_mut() = weights_gradients;
+    }
+}
+
+impl ComputeParametersGradient<f32> for Recurrent {
+    fn compute_parameters_gradient(&self,
+                                   backend: &LeafBackend,
+                                   output_data: &[&SharedTensor<f32>],
+                                   output_gradients: &[&SharedTensor<f32>],
+                                   input_data: &[&SharedTensor<f32>],
+                                   parameters_gradients: &mut [&mut SharedTensor<f32>]) {
+        // computed by compute_input_gradient, since both need the same backpropagation through time
+        let weights_gradients = self.weights_gradients.borrow();
+        for (parameter_gradient, gradient) in parameters_gradients.iter_mut().zip(weights_gradients.iter()) {
+            parameter_gradient.write_slice(&gradient[..]).unwrap();
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a RNN, LSTM or GRU Layer.
+pub struct RecurrentConfig {
+    /// The size of the hidden state.
+    pub hidden_size: usize,
+    /// Whether a second recurrence should run in reverse direction.
+    ///
+    /// Default: false
+    pub bidirectional: bool,
+}
+
+impl RecurrentConfig {
+    /// Create a RecurrentConfig for a unidirectional layer with a hidden state of `hidden_size`.
+    pub fn new(hidden_size: usize) -> RecurrentConfig {
+        RecurrentConfig {
+            hidden_size: hidden_size,
+            bidirectional: false,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for RecurrentConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the RecurrentConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_hidden_size(self.hidden_size as u64);
+        builder.set_bidirectional(self.bidirectional);
+    }
+}
+
+impl<'a> CapnpRead<'a> for RecurrentConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        RecurrentConfig {
+            hidden_size: reader.get_hidden_size() as usize,
+            bidirectional: reader.get_bidirectional(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CellType, Recurrent, RecurrentConfig};
+    use crate::layers::core::LayerWorker;
+
+    #[test]
+    fn names_gate_weights() {
+        let config = RecurrentConfig { bidirectional: true, ..RecurrentConfig::new(4) };
+        let layer = Recurrent::from_config(CellType::LSTM, &config);
+        assert_eq!(layer.exact_num_weight_blobs(), Some(24));
+        assert_eq!(layer.weight_name(0), Some("weight_ih_input".to_owned()));
+        assert_eq!(layer.weight_name(5), Some("bias_forget".to_owned()));
+        assert_eq!(layer.weight_name(13), Some("weight_hh_input_reverse".to_owned()));
+
+        let layer = Recurrent::from_config(CellType::RNN, &RecurrentConfig::new(4));
+        assert_eq!(layer.weight_name(1), Some("weight_hh".to_owned()));
+    }
+
+    #[test]
+    fn reverse_direction_runs_backwards_in_time() {
+        assert_eq!(Recurrent::time(0, 1, 5), 1);
+        assert_eq!(Recurrent::time(1, 1, 5), 3);
+    }
+}
@@ -0,0 +1,663 @@
+//! A container layer that runs the contained layers as a directed acyclic graph.
+//!
+//! Unlike [Sequential][sequential], the layers of a Graph are not connected automatically.
+//! Every [LayerConfig][layer_config] names its input and output tensors, and the Graph runs
+//! the layers in a topological order of these connections, no matter in which order they are
+//! added. A layer without configured outputs gets a single output named after the layer.
+//!
+//! A tensor can be the input of any number of layers (fan-out). Every consumer then computes
+//! its own gradient w.r.t. the tensor, and the gradients are summed up before the gradient is
+//! propagated further. The outputs of the Graph are the tensors named in
+//! [GraphConfig.outputs][outputs], or all tensors that are not consumed by any layer.
+//! This makes it possible to build residual blocks, siamese towers and multi-task heads.
+//!
+//! Since the tensors of a Graph are shared between layers, a layer can not compute in-place
+//! and needs to name its outputs differently from its inputs.
+//!
+//! [sequential]: ../sequential/index.html
+//! [layer_config]: ../../../layer/struct.LayerConfig.html
+//! [outputs]: ./struct.GraphConfig.html#structfield.outputs
+
+use crate::layers::core::*;
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::graph_config as capnp_config;
+use crate::cerealization_protocol::shaped_input as capnp_shaped_input;
+use crate::typedefs::{ArcLockTensor, ArcLockTensorBlob, LeafBackend, WeightArcLockTensorBlob};
+use crate::weight::FillerType;
+
+use parenchyma::prelude::SharedTensor;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+/// A tensor that is the input of several layers or also an output of the Graph.
+///
+/// Every consumer writes its gradient into its own partial gradient, and their sum
+/// is the gradient of the tensor.
+#[derive(Debug)]
+struct FanOut {
+    gradient: ArcLockTensor,
+    partial_gradients: Vec<ArcLockTensor>,
+    // the partial gradient coming from outside of the Graph, if the tensor is an output
+    output_gradient: Option<ArcLockTensor>,
+}
+
+impl FanOut {
+    /// Sums up the partial gradients into the gradient of the tensor.
+    ///
+    /// The gradient that was passed for the tensor as output of the Graph is used instead of
+    /// the exposed output gradient if one is given.
+    fn accumulate(&self, backend: &LeafBackend, output_gradient: Option<&ArcLockTensor>) {
+        let one = SharedTensor::scalar(1f32);
+        let mut gradient = self.gradient.write().unwrap();
+        FillerType::Constant { value: 0f32 }.fill(&mut gradient);
+        for partial_gradient in self.partial_gradients.iter().chain(output_gradient.or(self.output_gradient.as_ref())) {
+            backend.axpy(&one, &partial_gradient.read().unwrap(), &mut gradient).unwrap();
+        }
+    }
+}
+
+/// Graph Layer
+#[derive(Debug)]
+pub struct Graph {
+    // the layers in topological order
+    layers: Vec<RefCell<Layer>>,
+
+    input_tensor_names: Vec<String>,
+    input_data_tensors: Vec<ArcLockTensor>,
+    input_gradient_tensors: Vec<ArcLockTensor>,
+
+    output_tensor_names: Vec<String>,
+    output_data_tensors: Vec<ArcLockTensor>,
+    output_gradient_tensors: Vec<ArcLockTensor>,
+
+    fan_outs: HashMap<String, FanOut>,
+}
+
+impl Graph {
+    /// Create a empty Graph container layer.
+    pub fn empty() -> Graph {
+        Graph {
+            layers: vec![],
+
+            input_tensor_names: vec![],
+            input_data_tensors: vec![],
+            input_gradient_tensors: vec![],
+
+            output_tensor_names: vec![],
+            output_data_tensors: vec![],
+            output_gradient_tensors: vec![],
+
+            fan_outs: HashMap::new(),
+        }
+    }
+
+    /// Create a Graph layer from a GraphConfig.
+    pub fn from_config(backend: Rc<LeafBackend>, config: &GraphConfig) -> Graph {
+        let mut layer = Self::empty();
+
+        layer.init_layers(backend, &config.clone());
+
+        layer
+    }
+
+    /// Initializes a graph container.
+    ///
+    /// Sorts the layers of the supplied [GraphConfig][1] topologically, connects their input and
+    /// output tensors, sets up the gradient accumulation of tensors with multiple consumers and
+    /// determines if the backpropagation has to be executed for each tensor and layer.
+    ///
+    /// [1]: ./struct.GraphConfig.html
+    pub fn init_layers(&mut self, backend: Rc<LeafBackend>, in_config: &GraphConfig) {
+        let mut config = in_config.clone();
+        let mut registry = HashMap::<String, ArcLockTensorBlob>::new();
+        let weight_registry = &mut HashMap::<String, WeightArcLockTensorBlob>::new();
+
+        for layer_config in &mut config.layers {
+            if layer_config.outputs.is_empty() {
+                let output_name = layer_config.name.clone();
+                layer_config.add_output(&output_name);
+            }
+        }
+        let order = config.topological_order();
+
+        for &(ref input_name, ref input_shape) in &config.inputs {
+            self.init_input_blob(input_name, input_shape, &mut registry);
+        }
+
+        let mut shared_workspace = None;
+        for &layer_id in &order {
+            self.init_layer(backend.clone(), &config.layers[layer_id], &mut registry, weight_registry);
+            shared_workspace = self.resize_shared_workspace(backend.clone(), shared_workspace);
+        }
+
+        // Tensors that no layer consumes are the default outputs
+        let mut consumers = HashMap::<String, Vec<(usize, usize)>>::new();
+        for (position, layer) in self.layers.iter().enumerate() {
+            for (input_id, input_name) in layer.borrow().input_blob_names().iter().enumerate() {
+                consumers.entry(input_name.clone()).or_insert_with(Vec::new).push((position, input_id));
+            }
+        }
+        self.output_tensor_names = if config.outputs.is_empty() {
+            order.iter()
+                 .flat_map(|&layer_id| config.layers[layer_id].outputs.clone())
+                 .filter(|output_name| !consumers.contains_key(output_name))
+                 .collect()
+        } else {
+            config.outputs.clone()
+        };
+
+        self.init_fan_outs(&consumers, &registry);
+
+        let blobs_under_loss = &mut HashSet::<String>::new();
+        let blobs_skip_backp = &mut HashSet::<String>::new();
+        for layer in &mut self.layers.iter_mut().rev() {
+            layer.borrow_mut().init_backprop(blobs_under_loss, blobs_skip_backp);
+        }
+
+        if config.force_backward {
+            for layer in &mut self.layers {
+                layer.borrow_mut().init_force_backward();
+            }
+        }
+
+        for output_name in &self.output_tensor_names.clone() {
+            let &(ref data_tensor, ref gradient_tensor) = registry.get(output_name)
+                .expect(&format!("Unknown output tensor {} of Graph.", output_name));
+            self.output_data_tensors.push(data_tensor.clone());
+            match self.fan_outs.get(output_name).and_then(|fan_out| fan_out.output_gradient.clone()) {
+                Some(output_gradient) => self.output_gradient_tensors.push(output_gradient),
+                None => self.output_gradient_tensors.push(gradient_tensor.clone()),
+            }
+        }
+
+        info!("Graph container initialization done.");
+    }
+
+    /// Initialize a input tensor for the Graph container.
+    ///
+    /// Used during initialization of the Graph container.
+    fn init_input_blob(&mut self,
+                       tensor_name: &str,
+                       input_shape: &[usize],
+                       registry: &mut HashMap<String, ArcLockTensorBlob>) {
+        info!("Input {} -> {}", self.input_data_tensors.len(), tensor_name);
+
+        let data_tensor: ArcLockTensor = Arc::new(RwLock::new(SharedTensor::from(input_shape)));
+        let gradient_tensor: ArcLockTensor = Arc::new(RwLock::new(SharedTensor::from(input_shape)));
+
+        self.input_data_tensors.push(data_tensor.clone());
+        self.input_gradient_tensors.push(gradient_tensor.clone());
+        self.input_tensor_names.push(tensor_name.to_owned());
+        registry.insert(tensor_name.to_owned(), (data_tensor, gradient_tensor));
+    }
+
+    /// Initializes a single layer of the Graph container.
+    fn init_layer(&mut self,
+                  backend: Rc<LeafBackend>,
+                  layer_config: &LayerConfig,
+                  registry: &mut HashMap<String, ArcLockTensorBlob>,
+                  weight_registry: &mut HashMap<String, WeightArcLockTensorBlob>) {
+        if let Err(e) = layer_config.validate() {
+            error!("{}", e);
+        }
+
+        info!("Creating Layer {}", &layer_config.name);
+        let mut layer = Layer::from_config(backend, &layer_config);
+        layer.connect(registry, weight_registry);
+
+        self.layers.push(RefCell::new(layer));
+    }
+
+    /// Gives every consumer of a tensor with multiple consumers its own gradient.
+    ///
+    /// `consumers` maps every tensor to the positions of the layers using it and the input id
+    /// it has in them.
+    fn init_fan_outs(&mut self,
+                     consumers: &HashMap<String, Vec<(usize, usize)>>,
+                     registry: &HashMap<String, ArcLockTensorBlob>) {
+        for (tensor_name, tensor_consumers) in consumers {
+            let is_output = self.output_tensor_names.contains(tensor_name);
+            if tensor_consumers.len() + (is_output as usize) < 2 {
+                continue;
+            }
+            info!("Tensor {} fans out to {} consumers", tensor_name, tensor_consumers.len() + (is_output as usize));
+
+            let &(ref data_tensor, ref gradient_tensor) = &registry[tensor_name];
+            let new_gradient = || {
+                let mut gradient = SharedTensor::from(data_tensor.read().unwrap().shape().dimensions());
+                FillerType::Constant { value: 0f32 }.fill(&mut gradient);
+                Arc::new(RwLock::new(gradient))
+            };
+
+            let mut partial_gradients = Vec::new();
+            for &(position, input_id) in tensor_consumers {
+                let partial_gradient = new_gradient();
+                self.layers[position].borrow_mut().input_blobs_gradient[input_id] = partial_gradient.clone();
+                partial_gradients.push(partial_gradient);
+            }
+            let fan_out = FanOut {
+                gradient: gradient_tensor.clone(),
+                partial_gradients: partial_gradients,
+                output_gradient: if is_output { Some(new_gradient()) } else { None },
+            };
+            self.fan_outs.insert(tensor_name.clone(), fan_out);
+        }
+    }
+}
+
+impl LayerWorker for Graph {
+    fn is_container(&self) -> bool {
+        true
+    }
+
+    fn inputs_data(&self) -> Option<Vec<ArcLockTensor>> {
+        Some(self.input_data_tensors.clone())
+    }
+
+    fn inputs_gradients(&self) -> Option<Vec<ArcLockTensor>> {
+        Some(self.input_gradient_tensors.clone())
+    }
+
+    fn outputs_data(&self) -> Option<Vec<ArcLockTensor>> {
+        Some(self.output_data_tensors.clone())
+    }
+
+    fn outputs_gradients(&self) -> Option<Vec<ArcLockTensor>> {
+        Some(self.output_gradient_tensors.clone())
+    }
+
+    fn learnable_weights(&self) -> Option<Vec<ArcLockTensor>> {
+        let weights = self.layers.iter().flat_map(|layer| layer.borrow().learnable_weights_data()).collect();
+        Some(weights)
+    }
+
+    fn learnable_weights_gradients(&self) -> Option<Vec<ArcLockTensor>> {
+        let gradients = self.layers.iter().flat_map(|layer| layer.borrow().learnable_weights_gradients()).collect();
+        Some(gradients)
+    }
+
+    fn learnable_weights_names(&self) -> Option<Vec<String>> {
+        let names = self.layers.iter().flat_map(|layer| layer.borrow().learnable_weights_names()).collect();
+        Some(names)
+    }
+
+    fn learnable_weights_lr(&self) -> Option<Vec<Option<f32>>> {
+        let lr = self.layers.iter().flat_map(|layer| layer.borrow().learnable_weights_lr()).collect();
+        Some(lr)
+    }
+
+    fn learnable_weights_weight_decay(&self) -> Option<Vec<Option<f32>>> {
+        let decay = self.layers.iter().flat_map(|layer| layer.borrow().learnable_weights_weight_decay()).collect();
+        Some(decay)
+    }
+
+    fn buffers(&self) -> Vec<(String, ArcLockTensor)> {
+        self.layers.iter().flat_map(|layer| layer.borrow().buffers()).collect()
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        for layer in &self.layers {
+            layer.borrow_mut().set_mode(mode);
+        }
+    }
+
+    fn resize_shared_workspace(&mut self, backend: Rc<LeafBackend>, workspace: Option<ArcLockTensor<u8>>) -> Option<ArcLockTensor<u8>> {
+        debug!("Resizing shared workspace {:?}", workspace.is_some());
+        let mut shared_workspace = workspace;
+
+        for layer in &self.layers {
+            shared_workspace = layer.borrow_mut().worker.resize_shared_workspace(backend.clone(), shared_workspace);
+        }
+
+        shared_workspace
+    }
+
+    fn forward(&self,
+               backend: &LeafBackend,
+               input_data: &[ArcLockTensor],
+               weights_data: &[ArcLockTensor],
+               output_data: &mut [ArcLockTensor]) {
+        for layer in &self.layers {
+            let mut layer = layer.borrow_mut();
+            let input_blob_names = layer.input_blob_names().to_owned();
+            for (input_id, input_name) in input_blob_names.iter().enumerate() {
+                let container_input_id = self.input_tensor_names.iter().position(|name| name == input_name);
+                if let Some(input) = container_input_id.and_then(|id| input_data.get(id)) {
+                    layer.input_blobs_data[input_id] = input.clone();
+                }
+            }
+            layer.forward(&[]);
+        }
+        if let Some(last_layer) = self.layers.last() {
+            last_layer.borrow_mut().synchronize();
+        }
+    }
+
+    fn backward_input(&self,
+                backend: &LeafBackend,
+                weights_data: &[ArcLockTensor],
+                output_data: &[ArcLockTensor],
+                output_gradients: &[ArcLockTensor],
+                input_data: &[ArcLockTensor],
+                input_gradients: &mut [ArcLockTensor]) {
+        let mut fanned_out_gradients = HashMap::<&str, &ArcLockTensor>::new();
+        for (output_name, output_gradient) in self.output_tensor_names.iter().zip(output_gradients) {
+            if self.fan_outs.contains_key(output_name) {
+                fanned_out_gradients.insert(output_name, output_gradient);
+                continue;
+            }
+            for layer in &self.layers {
+                let mut layer = layer.borrow_mut();
+                if let Some(output_id) = layer.config.outputs.iter().position(|name| name == output_name) {
+                    layer.output_blobs_gradient[output_id] = output_gradient.clone();
+                }
+            }
+        }
+
+        for layer in self.layers.iter().rev() {
+            // all consumers of the outputs have computed their gradients at this point
+            for output_name in &layer.borrow().config.outputs {
+                if let Some(fan_out) = self.fan_outs.get(output_name) {
+                    fan_out.accumulate(backend, fanned_out_gradients.get(&output_name[..]).cloned());
+                }
+            }
+            layer.borrow_mut().backward_input(&[]);
+        }
+        for input_name in &self.input_tensor_names {
+            if let Some(fan_out) = self.fan_outs.get(input_name) {
+                fan_out.accumulate(backend, fanned_out_gradients.get(&input_name[..]).cloned());
+            }
+        }
+        if let Some(first_layer) = self.layers.first() {
+            first_layer.borrow_mut().synchronize();
+        }
+    }
+
+    fn backward_parameters(&self,
+                backend: &LeafBackend,
+                output_data: &[ArcLockTensor],
+                output_gradients: &[ArcLockTensor],
+                input_data: &[ArcLockTensor],
+                weights_gradients: &mut [ArcLockTensor]) {
+        for layer in self.layers.iter().rev() {
+            layer.borrow_mut().backward_parameters();
+        }
+        // the gradients of shared weights are complete once all layers using them are done
+        for layer in &self.layers {
+            layer.borrow().accumulate_shared_weights_gradients(backend);
+        }
+        if let Some(first_layer) = self.layers.first() {
+            first_layer.borrow_mut().synchronize();
+        }
+    }
+}
+
+impl ComputeOutput<f32> for Graph {
+    // we are overriding `forward` and not calling `compute_output`
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) { }
+}
+
+impl ComputeInputGradient<f32> for Graph {
+    // we are overriding `backward_input` and not calling `compute_input_gradient`
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) { }
+}
+
+impl ComputeParametersGradient<f32> for Graph {
+    // we are overriding `backward_parameters` and not calling `compute_parameters_gradient`
+    fn compute_parameters_gradient(&self,
+                                   backend: &LeafBackend,
+                                   output_data: &[&SharedTensor<f32>],
+                                   output_gradients: &[&SharedTensor<f32>],
+                                   input_data: &[&SharedTensor<f32>],
+                                   parameters_gradients: &mut [&mut SharedTensor<f32>]) { }
+}
+
+#[derive(Debug, Clone)]
+#[allow(missing_copy_implementations)]
+/// Specifies configuration parameters for a Graph Layer.
+pub struct GraphConfig {
+    /// Defines the layers of the container via [LayerConfig][layer_config]s.
+    ///
+    /// The layers are connected through the names of their inputs and outputs,
+    /// so they can be added in any order.
+    ///
+    /// [layer_config]: ../../../layer/struct.LayerConfig.html
+    pub layers: Vec<LayerConfig>,
+
+    /// Defines the names and shapes of the input tensors.
+    pub inputs: Vec<(String, Vec<usize>)>,
+
+    /// Defines the names of the tensors that are the outputs of the container.
+    ///
+    /// If empty, all tensors that are not the input of any layer are outputs.
+    pub outputs: Vec<String>,
+
+    /// Defines if the container will force every layer to do [backpropagation][1].
+    /// [1]: https://en.wikipedia.org/wiki/Backpropagation
+    ///
+    /// Default: `false`
+    pub force_backward: bool,
+}
+
+impl GraphConfig {
+    /// Add a layer to the graph container.
+    pub fn add_layer(&mut self, layer: LayerConfig) {
+        self.layers.push(layer);
+    }
+
+    /// Add a input to the network.
+    pub fn add_input(&mut self, input_name: &str, shape: &[usize]) {
+        self.inputs.push((input_name.to_owned(), shape.to_owned()));
+    }
+
+    /// Add a output to the network.
+    pub fn add_output(&mut self, output_name: &str) {
+        self.outputs.push(output_name.to_owned());
+    }
+
+    /// Returns the indices of the layers in an order where every layer comes after all layers
+    /// producing its inputs.
+    ///
+    /// Layers that don't depend on each other keep the order in which they were added.
+    ///
+    /// Panics if a tensor is produced by multiple layers, an input tensor is unknown
+    /// or the layers contain a cycle.
+    pub fn topological_order(&self) -> Vec<usize> {
+        let mut producers = HashMap::<&str, usize>::new();
+        for (layer_id, layer) in self.layers.iter().enumerate() {
+            for output_name in &layer.outputs {
+                if layer.inputs.contains(output_name) {
+                    panic!("Layer {} of a Graph can not compute in-place on {}.", layer.name, output_name);
+                }
+                if self.inputs.iter().any(|&(ref input_name, _)| input_name == output_name) ||
+                   producers.insert(output_name, layer_id).is_some() {
+                    panic!("Tensor {} of a Graph is produced by multiple sources.", output_name);
+                }
+            }
+        }
+
+        let mut num_dependencies = vec![0; self.layers.len()];
+        let mut dependents = vec![Vec::new(); self.layers.len()];
+        for (layer_id, layer) in self.layers.iter().enumerate() {
+            for input_name in &layer.inputs {
+                match producers.get(&input_name[..]) {
+                    Some(&producer_id) => {
+                        num_dependencies[layer_id] += 1;
+                        dependents[producer_id].push(layer_id);
+                    }
+                    None => if !self.inputs.iter().any(|&(ref name, _)| name == input_name) {
+                        panic!("Unknown input tensor {} of layer {} in a Graph.", input_name, layer.name);
+                    }
+                }
+            }
+        }
+
+        let mut ready = (0..self.layers.len()).filter(|&layer_id| num_dependencies[layer_id] == 0).collect::<BTreeSet<_>>();
+        let mut order = Vec::with_capacity(self.layers.len());
+        while let Some(layer_id) = ready.iter().next().cloned() {
+            ready.remove(&layer_id);
+            order.push(layer_id);
+            for &dependent_id in &dependents[layer_id] {
+                num_dependencies[dependent_id] -= 1;
+                if num_dependencies[dependent_id] == 0 {
+                    ready.insert(dependent_id);
+                }
+            }
+        }
+        if order.len() != self.layers.len() {
+            panic!("The layers of a Graph contain a cycle.");
+        }
+
+        order
+    }
+
+    /// Write a input into a capnp message.
+    fn write_capnp_shaped_input(&self, builder: &mut capnp_shaped_input::Builder, i: usize) {
+        let input = self.inputs.get(i).unwrap();
+        let ref name = input.0;
+        let ref shape = input.1;
+        builder.set_name(name);
+        let mut dimensions = builder.borrow().init_shape(shape.len() as u32);
+        for (i, dim) in shape.iter().enumerate() {
+            dimensions.set(i as u32, *dim as u64);
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for GraphConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the GraphConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        {
+            let mut layers = builder.borrow().init_layers(self.layers.len() as u32);
+            for (i, layer) in self.layers.iter().enumerate() {
+                let mut layer_config = layers.reborrow().get(i as u32);
+                layer.write_capnp(&mut layer_config);
+            }
+        }
+        {
+            let mut inputs = builder.borrow().init_inputs(self.inputs.len() as u32);
+            for (i, _) in self.inputs.iter().enumerate() {
+                let mut shaped_input = inputs.reborrow().get(i as u32);
+                self.write_capnp_shaped_input(&mut shaped_input, i);
+            }
+        }
+        {
+            let mut outputs = builder.borrow().init_outputs(self.outputs.len() as u32);
+            for (i, output) in self.outputs.iter().enumerate() {
+                outputs.set(i as u32, output);
+            }
+        }
+        builder.set_force_backward(self.force_backward);
+    }
+}
+
+impl<'a> CapnpRead<'a> for GraphConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        let read_layers = reader.get_layers().unwrap();
+        let mut layers = Vec::new();
+        for i in 0..read_layers.len() {
+            layers.push(LayerConfig::read_capnp(read_layers.get(i)))
+        }
+
+        let read_inputs = reader.get_inputs().unwrap();
+        let mut inputs = Vec::new();
+        for i in 0..read_inputs.len() {
+            let input = read_inputs.get(i);
+
+            let name = input.get_name().unwrap().to_owned();
+            let mut shape = Vec::new();
+            let read_shape = input.get_shape().unwrap();
+            for j in 0..read_shape.len() {
+                shape.push(read_shape.get(j) as usize)
+            }
+
+            inputs.push((name, shape))
+        }
+
+        let read_outputs = reader.get_outputs().unwrap();
+        let mut outputs = Vec::new();
+        for i in 0..read_outputs.len() {
+            outputs.push(read_outputs.get(i).unwrap().to_owned())
+        }
+        let force_backward = reader.get_force_backward();
+
+        GraphConfig {
+            layers: layers,
+            inputs: inputs,
+            outputs: outputs,
+            force_backward: force_backward,
+        }
+    }
+}
+
+impl Into<LayerType> for GraphConfig {
+    fn into(self) -> LayerType {
+        LayerType::Graph(self)
+    }
+}
+
+impl ::std::default::Default for GraphConfig {
+    fn default() -> GraphConfig {
+        GraphConfig {
+            layers: vec![],
+            inputs: vec![],
+            outputs: vec![],
+            force_backward: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GraphConfig;
+    use crate::layers::*;
+
+    fn layer(name: &str, inputs: &[&str], outputs: &[&str]) -> LayerConfig {
+        let mut config = LayerConfig::new(name, LayerType::ReLU);
+        for input in inputs {
+            config.add_input(input);
+        }
+        for output in outputs {
+            config.add_output(output);
+        }
+        config
+    }
+
+    #[test]
+    fn sorts_layers_topologically() {
+        let mut config = GraphConfig::default();
+        config.add_input("data", &[1]);
+        config.add_layer(layer("sum", &["left", "right"], &["sum"]));
+        config.add_layer(layer("left", &["data"], &["left"]));
+        config.add_layer(layer("right", &["data"], &["right"]));
+
+        assert_eq!(config.topological_order(), vec![1, 2, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle")]
+    fn rejects_cycles() {
+        let mut config = GraphConfig::default();
+        config.add_input("data", &[1]);
+        config.add_layer(layer("a", &["data", "b"], &["a"]));
+        config.add_layer(layer("b", &["a"], &["b"]));
+
+        config.topological_order();
+    }
+}
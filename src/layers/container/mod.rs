@@ -3,6 +3,8 @@
 //! For now layers in container should be described as layers that are used
 //! to connect multiple layers together to create 'networks'.
 
+pub use self::graph::{Graph, GraphConfig};
 pub use self::sequential::{Sequential, SequentialConfig};
 
+pub mod graph;
 pub mod sequential;
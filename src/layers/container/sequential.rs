@@ -234,6 +234,21 @@ impl LayerWorker for Sequential {
         Some(names)
     }
 
+    fn learnable_weights_weight_decay(&self) -> Option<Vec<Option<f32>>> {
+        let decay = self.layers.iter().flat_map(|layer| layer.borrow().learnable_weights_weight_decay()).collect();
+        Some(decay)
+    }
+
+    fn buffers(&self) -> Vec<(String, ArcLockTensor)> {
+        self.layers.iter().flat_map(|layer| layer.borrow().buffers()).collect()
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        for layer in &self.layers {
+            layer.borrow_mut().set_mode(mode);
+        }
+    }
+
     fn resize_shared_workspace(&mut self, backend: Rc<LeafBackend>, workspace: Option<ArcLockTensor<u8>>) -> Option<ArcLockTensor<u8>> {
         debug!("Resizing shared workspace {:?}", workspace.is_some());
         let mut shared_workspace = workspace;
@@ -292,6 +307,10 @@ impl LayerWorker for Sequential {
         for layer in self.layers.iter().rev() {
             layer.borrow_mut().backward_parameters();
         }
+        // the gradients of shared weights are complete once all layers using them are done
+        for layer in &self.layers {
+            layer.borrow().accumulate_shared_weights_gradients(backend);
+        }
         if let Some(first_layer) = self.layers.iter().rev().last() {
             first_layer.borrow_mut().synchronize();
         }
@@ -473,3 +492,55 @@ impl ::std::default::Default for SequentialConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SequentialConfig;
+    use crate::cerealization_protocol::{CapnpRead, CapnpWrite};
+    use crate::cerealization_protocol::sequential_config as capnp_config;
+    use crate::layers::*;
+    use crate::weight::{DimCheckMode, FillerType, WeightConfig};
+
+    #[test]
+    fn round_trips_shared_weights_through_capnp() {
+        let weight = WeightConfig {
+            name: "shared_weight".to_owned(),
+            share_mode: DimCheckMode::Permissive,
+            lr_mult: Some(0.5f32),
+            decay_mult: Some(0f32),
+            filler: Some(FillerType::Glorot { input_size: 4, output_size: 4 }),
+        };
+        let bias = WeightConfig {
+            name: "shared_bias".to_owned(),
+            filler: Some(FillerType::Constant { value: 0.1f32 }),
+            ..WeightConfig::default()
+        };
+
+        let mut config = SequentialConfig::default();
+        config.add_input("data", &[2, 4]);
+        for name in &["linear1", "linear2"] {
+            let mut layer = LayerConfig::new(name, LinearConfig { output_size: 4 });
+            layer.params = vec![weight.clone(), bias.clone()];
+            config.add_layer(layer);
+        }
+        config.add_layer(LayerConfig::new("sigmoid", LayerType::Sigmoid));
+
+        let mut message = ::capnp::message::Builder::new_default();
+        {
+            let mut builder = message.init_root::<capnp_config::Builder>();
+            config.write_capnp(&mut builder);
+        }
+        let mut buffer = Vec::new();
+        ::capnp::serialize_packed::write_message(&mut buffer, &message).unwrap();
+        let message_reader = ::capnp::serialize_packed::read_message(&mut &buffer[..],
+                                                                     ::capnp::message::ReaderOptions::new()).unwrap();
+        let read_config = SequentialConfig::read_capnp(message_reader.get_root::<capnp_config::Reader>().unwrap());
+
+        assert_eq!(read_config.inputs, config.inputs);
+        assert_eq!(read_config.layers.len(), config.layers.len());
+        for (read_layer, layer) in read_config.layers.iter().zip(&config.layers) {
+            assert_eq!(read_layer.name, layer.name);
+            assert_eq!(read_layer.params, layer.params);
+        }
+    }
+}
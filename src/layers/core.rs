@@ -7,9 +7,10 @@ use crate::cerealization_protocol::*;
 use crate::cerealization_protocol::layer as capnp_layer;
 use crate::cerealization_protocol::layer_config as capnp_layer_config;
 use crate::cerealization_protocol::layer_config::layer_type as capnp_layer_type;
+use crate::cerealization_protocol::tensor as capnp_tensor;
 use crate::layers::*;
 use crate::typedefs::{ArcLockTensor, ArcLockTensorBlob, LeafBackend, WeightArcLockTensorBlob};
-use crate::weight::WeightConfig;
+use crate::weight::{FillerType, WeightConfig};
 
 use parenchyma::prelude::SharedTensor;
 use std::{cmp, fmt};
@@ -20,6 +21,26 @@ use std::path::Path;
 use std::rc::Rc;
 use std::sync::{Arc, RwLock};
 
+/// Whether a Layer is used for training or for inference.
+///
+/// Some layers, like [Dropout][1], behave differently during training and evaluation.
+/// The mode is set with [Layer::set_mode][2] and defaults to `Train`.
+/// [1]: ../common/dropout/index.html
+/// [2]: ./struct.Layer.html#method.set_mode
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// The layer is used for training.
+    Train,
+    /// The layer is used for inference, e.g. to validate or deploy a network.
+    Eval,
+}
+
+impl Default for Mode {
+    fn default() -> Mode {
+        Mode::Train
+    }
+}
+
 /// The generic Layer
 #[derive(Debug)]
 pub struct Layer {
@@ -47,14 +68,16 @@ pub struct Layer {
     pub weights_data: Vec<ArcLockTensor>,
     /// The vector that stores shared references to the weights in the form of blobs.
     pub weights_gradient: Vec<ArcLockTensor>,
-    // contains all the learnable weights (does not include bias(?) and shared weights)
-    learnable_weights: Vec<ArcLockTensor>,
+    // the gradient of the owning layer for each weight that is shared from another layer
+    weights_owner_gradient: Vec<Option<ArcLockTensor>>,
     // learning rate for each weight
     weights_lr: Vec<Option<f32>>,
     // weight decay for each weight
     weights_weight_decay: Vec<Option<f32>>,
     // display name for each weight
     weights_display_names: Vec<String>,
+    // the configured filler for each weight
+    weights_fillers: Vec<Option<FillerType>>,
 
     /// Vector indicating whether to compute the diff of each weight blob.
     ///
@@ -113,8 +136,9 @@ impl Layer {
             self.append_output(output_id, registry);
         }
         let config = self.config.clone();
-        for (output_id, _) in self.config.outputs.clone().iter().rev().enumerate() {
-            self.append_weight(&config, weight_registry, 0, output_id);
+        let num_weights = self.worker.exact_num_weight_blobs().unwrap_or(self.config.outputs.len());
+        for weight_id in 0..num_weights {
+            self.append_weight(&config, weight_registry, 0, weight_id);
         }
 
         // If the layer specifies that AutoTopBlobs() -> true and the LayerParameter
@@ -136,7 +160,9 @@ impl Layer {
         }
 
         self.worker.init(self.backend.clone());
+        let shared_weights = self.shared_weights_values();
         self.reshape();
+        self.fill_weights(shared_weights);
         self.worker.resize_shared_workspace(self.backend.clone(), None);
         for t in &self.output_blobs_data {
             debug!("Layer {} - output shape: {:?}", self.name, t.read().unwrap().shape());
@@ -241,21 +267,37 @@ impl Layer {
         self.output_blobs_gradient.push(output_gradient);
     }
 
+    /// Append a weight blob to the Layer.
+    ///
+    /// The weight is configured by the [WeightConfig][1] at `weight_id` of the `layer_config`.
+    /// If the WeightConfig gives the weight a name that was already registered by another layer,
+    /// the weight data is shared with that layer. Only the owning layer exposes the weight as
+    /// learnable, so the [Solver][3] sees it once. Every other layer using it computes its
+    /// own gradient, which is added to the gradient of the owner by
+    /// [accumulate_shared_weights_gradients][4].
+    ///
+    /// The filler of the WeightConfig is remembered and applied once the layer implementation
+    /// has given the weight its shape (see [fill_weights][2]).
+    /// [1]: ../weight/struct.WeightConfig.html
+    /// [2]: #method.fill_weights
+    /// [3]: ../solver/struct.Solver.html
+    /// [4]: #method.accumulate_shared_weights_gradients
     fn append_weight(&mut self, layer_config: &LayerConfig, registry: &mut HashMap<String, WeightArcLockTensorBlob>, layer_id: usize, weight_id: usize) {
         if self.worker.auto_weight_blobs() {
             info!("Layer {} - appending weight", &layer_config.name);
             let weights_len = self.weights_data.len();
-            let weight_name = if weights_len > weight_id {
+            let weight_name = if layer_config.params_len() > weight_id {
                 layer_config.param(weight_id).unwrap().name.clone()
             } else {
                 "".to_owned()
             };
 
-            // use weight_name (or weight_id as a fallback) as display_name
+            // use weight_name (or the name given by the worker or weight_id as a fallback) as display_name
             let display_name = if !weight_name.is_empty() {
                 weight_name.clone()
             } else {
-                format!("{}-{}", self.name, weight_id)
+                let worker_weight_name = self.worker.weight_name(weight_id).unwrap_or_else(|| weight_id.to_string());
+                format!("{}-{}", self.name, worker_weight_name)
             };
             self.weights_display_names.push(display_name.clone());
             // create name for registry
@@ -263,29 +305,28 @@ impl Layer {
 
             // add to tracking vectors
             let net_weight_id = weights_len;
-            let output_data = self.output_blobs_data[weight_id].read().unwrap();
-            debug!("Layer {} - creating weight and gradient of size {:?}", &layer_config.name, output_data.shape());
-            let weight_data = Arc::new(RwLock::new(SharedTensor::from(output_data.shape().clone())));
-            let weight_gradient = Arc::new(RwLock::new(SharedTensor::from(output_data.shape().clone())));
-            self.weights_data.push(weight_data.clone());
+            debug!("Layer {} - creating weight gradient {}", &layer_config.name, weight_id);
+            // the weights are resized by the layer implementation during reshape
+            let weight_gradient = Arc::new(RwLock::new(SharedTensor::from([1,1,1]))); // [1,1,1] for CUDA
             self.weights_gradient.push(weight_gradient.clone());
 
             let mut weight_config = &WeightConfig::default();
             if layer_config.params_len() > weight_id {
                 weight_config = layer_config.param(weight_id).unwrap();
             }
+            self.weights_fillers.push(weight_config.filler);
             // This layer "owns" this weight blob -- it is either anonymous
             // (i.e., not given a weight_name) or explicitly given a name that we
             // haven't already seen.
             if weight_name.is_empty() || !registry.contains_key(&registry_name) {
                 // self.weight_owners.push(None);
+                let weight_data = Arc::new(RwLock::new(SharedTensor::from([1,1,1]))); // [1,1,1] for CUDA
+                self.weights_data.push(weight_data.clone());
                 if !weight_name.is_empty() {
-                    registry.insert(weight_name.clone(),
+                    registry.insert(registry_name.clone(),
                         (weight_data.clone(), weight_gradient.clone(), weight_config.lr_mult, weight_config.decay_mult));
                 }
-                let learnable_weight_id = self.learnable_weights.len();
-                self.learnable_weights.push(weight_data.clone());
-                // self.learnable_weight_ids.push(learnable_weight_id);
+                self.weights_owner_gradient.push(None);
                 self.weights_lr.push(weight_config.lr_mult);
                 self.weights_weight_decay.push(weight_config.decay_mult);
             } else {
@@ -293,6 +334,11 @@ impl Layer {
 
                 let (shared_weight_data, shared_weight_gradient, shared_lr, shared_decay_mult) = registry.get(&registry_name).unwrap().clone();
                 info!("Sharing weight blob '{}'", weight_name.clone());
+                self.weights_data.push(shared_weight_data.clone());
+                self.weights_owner_gradient.push(Some(shared_weight_gradient.clone()));
+                // keep the learning rates and decay multipliers aligned with the weights of this layer
+                self.weights_lr.push(shared_lr.or(weight_config.lr_mult));
+                self.weights_weight_decay.push(shared_decay_mult.or(weight_config.decay_mult));
 
                 // can only share parameters if both have same lr_mult
                 if let Some(lr_mult) = weight_config.lr_mult {
@@ -324,6 +370,39 @@ impl Layer {
         }
     }
 
+    /// Returns the values of the weights that are shared from another layer,
+    /// which were already initialized by the layer that owns them.
+    fn shared_weights_values(&self) -> Vec<Option<Vec<f32>>> {
+        self.weights_data.iter().zip(&self.weights_owner_gradient).map(|(weight, owner_gradient)| {
+            owner_gradient.as_ref().and_then(|_| weight.read().unwrap().as_slice().ok().map(|values| values.to_vec()))
+        }).collect()
+    }
+
+    /// Fill the weights with the filler configured in their [WeightConfig][1].
+    /// [1]: ../weight/struct.WeightConfig.html
+    ///
+    /// The weights only get their shape during [reshape][2], where the layer implementation
+    /// also initializes them with its default filler. A configured filler overrides that
+    /// default, so it has to be applied afterwards.
+    ///
+    /// Weights that are shared from another layer are only initialized by the layer that
+    /// owns them, so they are restored to the `shared_weights` values from before the reshape.
+    /// [2]: ./trait.LayerWorker.html#method.reshape
+    fn fill_weights(&mut self, shared_weights: Vec<Option<Vec<f32>>>) {
+        for ((weight, filler), shared_values) in self.weights_data.iter().zip(&self.weights_fillers).zip(shared_weights) {
+            if let Some(values) = shared_values {
+                let mut weight = weight.write().unwrap();
+                if weight.shape().capacity() != values.len() {
+                    panic!("Layer {} - shared weight of size {} can not be used with size {}.",
+                           self.name, values.len(), weight.shape().capacity());
+                }
+                weight.write_slice(&values[..]).unwrap();
+            } else if let Some(filler) = *filler {
+                filler.fill(&mut weight.write().unwrap());
+            }
+        }
+    }
+
     fn reshape(&mut self) {
         match self.is_using_in_place() {
             false => {
@@ -525,6 +604,27 @@ impl Layer {
                              &mut self.weights_gradient)
     }
 
+    /// Adds the gradients of the weights this layer shares with another layer
+    /// to the gradients of the layer that owns them.
+    ///
+    /// The gradients of the shared weights of this layer are zeroed afterwards, so they
+    /// are not added again during the next backpropagation step.
+    /// Container layers call this for the layers inside them once all of them computed
+    /// their gradients w.r.t. parameters.
+    pub fn accumulate_shared_weights_gradients(&self, backend: &LeafBackend) {
+        let shared_a = SharedTensor::scalar(1f32);
+        for (weight_gradient, owner_gradient) in self.weights_gradient.iter().zip(&self.weights_owner_gradient) {
+            if let Some(ref owner_gradient) = *owner_gradient {
+                let mut weight_gradient = weight_gradient.write().unwrap();
+                backend.axpy(&shared_a, &weight_gradient, &mut owner_gradient.write().unwrap()).unwrap();
+                let filler = ::weight::FillerType::Constant {
+                    value: 0f32
+                };
+                filler.fill(&mut weight_gradient);
+            }
+        }
+    }
+
     /// Synchronize the layers backend.
     pub fn synchronize(&self) {
         self.backend.synchronize().unwrap();
@@ -670,28 +770,24 @@ impl Layer {
         let names = layer.learnable_weights_names();
         let weights_data = layer.learnable_weights_data();
 
-        for (i, (name, weight)) in names.iter().zip(weights_data).enumerate() {
+        for (name, weight) in names.iter().zip(weights_data) {
             for j in 0..read_weights.len() {
-                let capnp_weight = read_weights.get(i as u32);
+                let capnp_weight = read_weights.get(j);
                 if capnp_weight.get_name().unwrap() != name {
                     continue
                 }
+                read_tensor_capnp(capnp_weight.get_tensor().unwrap(), &mut weight.write().unwrap());
+            }
+        }
 
-                let mut weight_lock = weight.write().unwrap();
-
-                let capnp_tensor = capnp_weight.get_tensor().unwrap();
-                let mut shape = Vec::new();
-                let capnp_shape = capnp_tensor.get_shape().unwrap();
-                for k in 0..capnp_shape.len() {
-                    shape.push(capnp_shape.get(k) as usize)
-                }
-                weight_lock.reshape(shape.clone()).unwrap();
-
-                let mut native_slice = weight_lock.as_mut_slice().unwrap();
-                let data = capnp_tensor.get_data().unwrap();
-                for k in 0..data.len() {
-                    native_slice[k as usize] = data.get(k);
+        let read_buffers = read_layer.get_buffers_data().unwrap();
+        for (name, buffer) in layer.buffers() {
+            for j in 0..read_buffers.len() {
+                let capnp_buffer = read_buffers.get(j);
+                if capnp_buffer.get_name().unwrap() != name {
+                    continue
                 }
+                read_tensor_capnp(capnp_buffer.get_tensor().unwrap(), &mut buffer.write().unwrap());
             }
         }
 
@@ -736,11 +832,13 @@ impl Layer {
 
     /// Returns all the learnable weights in the layer.
     ///
+    /// Weights that are shared from another layer are only returned by the layer that owns them.
+    ///
     /// If the layer is a container layer it will return all the weights of the
     /// layers inside it.
     pub fn learnable_weights_data(&self) -> Vec<ArcLockTensor> {
         if let Some(weights) = self.worker.learnable_weights() { weights }
-        else { self.weights_data.clone() }
+        else { self.owned_weights(&self.weights_data) }
     }
 
     /// Returns the gradients for all the learnable weights in the layer.
@@ -749,7 +847,7 @@ impl Layer {
     /// layers inside it.
     pub fn learnable_weights_gradients(&self) -> Vec<ArcLockTensor> {
         if let Some(gradients) = self.worker.learnable_weights_gradients() { gradients }
-        else { self.weights_gradient.clone() }
+        else { self.owned_weights(&self.weights_gradient) }
     }
 
     /// Returns the names of all the learnable weights in the layer.
@@ -758,7 +856,7 @@ impl Layer {
     /// layers inside it.
     pub fn learnable_weights_names(&self) -> Vec<String> {
         if let Some(names) = self.worker.learnable_weights_names() { names }
-        else { self.weights_display_names.clone() }
+        else { self.owned_weights(&self.weights_display_names) }
     }
 
     /// Returns the learning rate for all the learnable weights in the layer.
@@ -771,6 +869,45 @@ impl Layer {
         else {
             self.learnable_weights_data().iter().map(|_| Some(1f32)).collect::<Vec<_>>() }
     }
+
+    /// Returns the weight decay multipliers for all the learnable weights in the layer.
+    ///
+    /// If the layer is a container layer it will return all weight decay multipliers of the
+    /// layers inside it.
+    pub fn learnable_weights_weight_decay(&self) -> Vec<Option<f32>> {
+        if let Some(decay) = self.worker.learnable_weights_weight_decay() { decay }
+        else { self.owned_weights(&self.weights_weight_decay) }
+    }
+
+    /// Selects the entries of `values` that belong to weights owned by this layer.
+    fn owned_weights<T: Clone>(&self, values: &[T]) -> Vec<T> {
+        values.iter().zip(&self.weights_owner_gradient)
+            .filter(|&(_, owner_gradient)| owner_gradient.is_none())
+            .map(|(value, _)| value.clone())
+            .collect()
+    }
+
+    /// Switches the layer between training and evaluation [Mode][1].
+    ///
+    /// If the layer is a container layer the mode is set for all the layers inside it.
+    /// [1]: ./enum.Mode.html
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.worker.set_mode(mode);
+    }
+
+    /// Returns the non-learnable persistent state (buffers) of the layer with their names.
+    ///
+    /// The buffers are named `<layer name>-<buffer name>`.
+    /// If the layer is a container layer it will return all the buffers of the
+    /// layers inside it.
+    pub fn buffers(&self) -> Vec<(String, ArcLockTensor)> {
+        let buffers = self.worker.buffers();
+        if self.worker.is_container() {
+            buffers
+        } else {
+            buffers.into_iter().map(|(name, buffer)| (format!("{}-{}", self.name, name), buffer)).collect()
+        }
+    }
 }
 
 #[allow(unsafe_code)]
@@ -794,28 +931,55 @@ impl<'a> CapnpWrite<'a> for Layer {
             for (i, (name, weight)) in names.iter().zip(weights_data).enumerate() {
                 let mut capnp_weight = weights.reborrow().get(i as u32);
                 capnp_weight.set_name(name);
-
-                let mut weight_lock = weight.write().unwrap();
-
-                let mut tensor = capnp_weight.init_tensor();
-                {
-                    let mut tensor_shape = tensor.borrow().init_shape(weight_lock.shape().capacity() as u32);
-                    for (i, dim) in weight_lock.shape().dimensions().iter().enumerate() {
-                        tensor_shape.set(i as u32, *dim as u64);
-                    }
-                }
-                {
-                    let native_slice = weight_lock.as_slice().unwrap();
-                    let mut tensor_data = tensor.borrow().init_data(native_slice.len() as u32);
-                    for (i, datum) in native_slice.iter().enumerate() {
-                        tensor_data.set(i as u32, *datum);
-                    }
-                }
+                write_tensor_capnp(&weight.write().unwrap(), &mut capnp_weight.init_tensor());
+            }
+        }
+        {
+            let buffers = self.buffers();
+            let mut capnp_buffers = builder.borrow().init_buffers_data(buffers.len() as u32);
+            for (i, &(ref name, ref buffer)) in buffers.iter().enumerate() {
+                let mut capnp_buffer = capnp_buffers.reborrow().get(i as u32);
+                capnp_buffer.set_name(name);
+                write_tensor_capnp(&buffer.write().unwrap(), &mut capnp_buffer.init_tensor());
             }
         }
     }
 }
 
+/// Write the shape and data of `tensor` into a capnp Tensor.
+fn write_tensor_capnp(tensor: &SharedTensor<f32>, builder: &mut capnp_tensor::Builder) {
+    {
+        let dimensions = tensor.shape().dimensions();
+        let mut tensor_shape = builder.borrow().init_shape(dimensions.len() as u32);
+        for (i, dim) in dimensions.iter().enumerate() {
+            tensor_shape.set(i as u32, *dim as u64);
+        }
+    }
+    {
+        let native_slice = tensor.as_slice().unwrap();
+        let mut tensor_data = builder.borrow().init_data(native_slice.len() as u32);
+        for (i, datum) in native_slice.iter().enumerate() {
+            tensor_data.set(i as u32, *datum);
+        }
+    }
+}
+
+/// Read a capnp Tensor into `tensor`, which needs to have the same capacity.
+fn read_tensor_capnp(reader: capnp_tensor::Reader, tensor: &mut SharedTensor<f32>) {
+    let mut shape = Vec::new();
+    let capnp_shape = reader.get_shape().unwrap();
+    for k in 0..capnp_shape.len() {
+        shape.push(capnp_shape.get(k) as usize)
+    }
+    tensor.reshape(shape).unwrap();
+
+    let native_slice = tensor.as_mut_slice().unwrap();
+    let data = reader.get_data().unwrap();
+    for k in 0..data.len() {
+        native_slice[k as usize] = data.get(k);
+    }
+}
+
 impl Layer {
     /// Creates a new Layer from a [LayerConfig][1].
     /// [1]: ./struct.LayerConfig.html
@@ -829,11 +993,12 @@ impl Layer {
 
             weights_data: Vec::new(),
             weights_gradient: Vec::new(),
-            learnable_weights: Vec::new(),
+            weights_owner_gradient: Vec::new(),
             weight_propagate_down: Vec::new(),
             weights_lr: Vec::new(),
             weights_weight_decay: Vec::new(),
             weights_display_names: Vec::new(),
+            weights_fillers: Vec::new(),
 
             input_blobs_data: Vec::new(),
             input_blobs_gradient: Vec::new(),
@@ -864,18 +1029,41 @@ impl Layer {
     /// [3]: ../layers/index.html
     fn worker_from_config(backend: Rc<LeafBackend>, config: &LayerConfig) -> Box<LayerWorker> {
         match config.layer_type.clone() {
-            #[cfg(all(feature="cuda", not(feature="native")))]
             LayerType::Convolution(layer_config) => Box::new(Convolution::from_config(&layer_config)),
             LayerType::Linear(layer_config) => Box::new(Linear::from_config(&layer_config)),
             LayerType::LogSoftmax => Box::new(LogSoftmax::default()),
-            #[cfg(all(feature="cuda", not(feature="native")))]
             LayerType::Pooling(layer_config) => Box::new(Pooling::from_config(&layer_config)),
+            LayerType::Dropout(layer_config) => Box::new(Dropout::from_config(&layer_config)),
+            LayerType::BatchNorm(layer_config) => Box::new(BatchNorm::from_config(&layer_config)),
+            LayerType::LayerNorm(layer_config) => Box::new(LayerNorm::from_config(&layer_config)),
+            LayerType::Embedding(layer_config) => Box::new(Embedding::from_config(&layer_config)),
+            LayerType::RNN(layer_config) => Box::new(Recurrent::from_config(CellType::RNN, &layer_config)),
+            LayerType::LSTM(layer_config) => Box::new(Recurrent::from_config(CellType::LSTM, &layer_config)),
+            LayerType::GRU(layer_config) => Box::new(Recurrent::from_config(CellType::GRU, &layer_config)),
+            LayerType::ScaledDotProductAttention(layer_config) => Box::new(ScaledDotProductAttention::from_config(&layer_config)),
+            LayerType::MultiHeadAttention(layer_config) => Box::new(MultiHeadAttention::from_config(&layer_config)),
+            LayerType::Eltwise(layer_config) => Box::new(Eltwise::from_config(&layer_config)),
             LayerType::Sequential(layer_config) => Box::new(Sequential::from_config(backend, &layer_config)),
+            LayerType::Graph(layer_config) => Box::new(Graph::from_config(backend, &layer_config)),
             LayerType::Softmax => Box::new(Softmax::default()),
             LayerType::ReLU => Box::new(ReLU),
             LayerType::Sigmoid => Box::new(Sigmoid),
+            LayerType::TanH => Box::new(TanH),
+            LayerType::LeakyReLU(layer_config) => Box::new(LeakyReLU::from_config(&layer_config)),
+            LayerType::PReLU(layer_config) => Box::new(PReLU::from_config(&layer_config)),
+            LayerType::ELU(layer_config) => Box::new(ELU::from_config(&layer_config)),
+            LayerType::GELU => Box::new(GELU),
+            LayerType::Softplus => Box::new(Softplus),
+            LayerType::Swish => Box::new(Swish),
             LayerType::NegativeLogLikelihood(layer_config) => Box::new(NegativeLogLikelihood::from_config(&layer_config)),
+            LayerType::MeanSquaredError(layer_config) => Box::new(MeanSquaredError::from_config(&layer_config)),
+            LayerType::Huber(layer_config) => Box::new(Huber::from_config(&layer_config)),
+            LayerType::SoftmaxCrossEntropy(layer_config) => Box::new(SoftmaxCrossEntropy::from_config(&layer_config)),
+            LayerType::BinaryCrossEntropy(layer_config) => Box::new(BinaryCrossEntropy::from_config(&layer_config)),
             LayerType::Reshape(layer_config) => Box::new(Reshape::from_config(&layer_config)),
+            LayerType::Flatten(layer_config) => Box::new(Flatten::from_config(&layer_config)),
+            LayerType::Concat(layer_config) => Box::new(Concat::from_config(&layer_config)),
+            LayerType::Slice(layer_config) => Box::new(Slice::from_config(&layer_config)),
         }
     }
 }
@@ -917,6 +1105,13 @@ pub trait LayerWorker : ComputeOutput<f32> + ComputeInputGradient<f32> + Compute
         workspace
     }
 
+    /// Switch between training and evaluation [Mode][1].
+    ///
+    /// Layers that behave differently during evaluation should override this,
+    /// container layers should pass the mode on to the layers they contain.
+    /// [1]: ./enum.Mode.html
+    fn set_mode(&mut self, mode: Mode) {}
+
     /// Compute the [feedforward][1] layer output using the provided Backend.
     /// [1]: https://en.wikipedia.org/wiki/Feedforward_neural_network
     ///
@@ -1034,10 +1229,26 @@ pub trait LayerWorker : ComputeOutput<f32> + ComputeInputGradient<f32> + Compute
     /// Return whether weight blobs are created automatically for the layer.
     ///
     /// If this method returns true, Network::init will create a weight blob
-    /// for every output blob.
+    /// for every output blob, or as many as specified by [exact_num_weight_blobs][1].
+    /// [1]: #method.exact_num_weight_blobs
     fn auto_weight_blobs(&self) -> bool {
         false
     }
+    /// Returns the exact number of weight blobs that are created automatically for the layer.
+    ///
+    /// Override this method to return `Some(n)` if your layer needs a number of weights
+    /// that differs from the number of its output blobs.
+    fn exact_num_weight_blobs(&self) -> Option<usize> {
+        None
+    }
+    /// Returns the name of the automatically created weight blob `weight_id`.
+    ///
+    /// The name is prefixed with the layer name and shows up in [learnable_weights_names][1].
+    /// If this returns `None` or the weight is named by its WeightConfig, that name is used instead.
+    /// [1]: ./struct.Layer.html#method.learnable_weights_names
+    fn weight_name(&self, weight_id: usize) -> Option<String> {
+        None
+    }
     /// Returns the exact number of input blobs required by the layer,
     /// or `None` if no exact number is required.
     ///
@@ -1153,6 +1364,24 @@ pub trait LayerWorker : ComputeOutput<f32> + ComputeInputGradient<f32> + Compute
     fn learnable_weights_lr(&self) -> Option<Vec<Option<f32>>> {
         None
     }
+
+    /// Return the weight decay multipliers for the learnable weights inside the layer.
+    ///
+    /// This should only be overridden by container layers,
+    /// where the weights are not easily exposable.
+    fn learnable_weights_weight_decay(&self) -> Option<Vec<Option<f32>>> {
+        None
+    }
+
+    /// Return the non-learnable persistent state of the layer, together with a name for each tensor.
+    ///
+    /// Buffers, like running statistics, are saved and loaded together with the weights,
+    /// but they are not part of the learnable weights and therefore not updated by a Solver.
+    ///
+    /// Container layers should return the buffers of all the layers inside them.
+    fn buffers(&self) -> Vec<(String, ArcLockTensor)> {
+        Vec::new()
+    }
 }
 
 /// A Layer that can compute the output for a given input.
@@ -1222,17 +1451,37 @@ pub struct LayerConfig {
 pub enum LayerType {
     // Common layers
     /// Convolution Layer
-    #[cfg(all(feature="cuda", not(feature="native")))]
     Convolution(ConvolutionConfig),
     /// Linear Layer
     Linear(LinearConfig),
     /// LogSoftmax Layer
     LogSoftmax,
     /// Pooling Layer
-    #[cfg(all(feature="cuda", not(feature="native")))]
     Pooling(PoolingConfig),
+    /// Dropout Layer
+    Dropout(DropoutConfig),
+    /// BatchNorm Layer
+    BatchNorm(BatchNormConfig),
+    /// LayerNorm Layer
+    LayerNorm(LayerNormConfig),
+    /// Embedding Layer
+    Embedding(EmbeddingConfig),
+    /// RNN Layer
+    RNN(RecurrentConfig),
+    /// LSTM Layer
+    LSTM(RecurrentConfig),
+    /// GRU Layer
+    GRU(RecurrentConfig),
+    /// ScaledDotProductAttention Layer
+    ScaledDotProductAttention(ScaledDotProductAttentionConfig),
+    /// MultiHeadAttention Layer
+    MultiHeadAttention(MultiHeadAttentionConfig),
+    /// Eltwise Layer
+    Eltwise(EltwiseConfig),
     /// Sequential Layer
     Sequential(SequentialConfig),
+    /// Graph Layer
+    Graph(GraphConfig),
     /// Softmax Layer
     Softmax,
     // Activation layers
@@ -1240,30 +1489,81 @@ pub enum LayerType {
     ReLU,
     /// Sigmoid Layer
     Sigmoid,
+    /// TanH Layer
+    TanH,
+    /// LeakyReLU Layer
+    LeakyReLU(LeakyReLUConfig),
+    /// PReLU Layer
+    PReLU(PReLUConfig),
+    /// ELU Layer
+    ELU(ELUConfig),
+    /// GELU Layer
+    GELU,
+    /// Softplus Layer
+    Softplus,
+    /// Swish Layer
+    Swish,
     // Loss layers
     /// NegativeLogLikelihood Layer
     NegativeLogLikelihood(NegativeLogLikelihoodConfig),
+    /// MeanSquaredError Layer
+    MeanSquaredError(MeanSquaredErrorConfig),
+    /// Huber Layer
+    Huber(HuberConfig),
+    /// SoftmaxCrossEntropy Layer
+    SoftmaxCrossEntropy(SoftmaxCrossEntropyConfig),
+    /// BinaryCrossEntropy Layer
+    BinaryCrossEntropy(BinaryCrossEntropyConfig),
     // Utility layers
     /// Reshape Layer
     Reshape(ReshapeConfig),
+    /// Flatten Layer
+    Flatten(FlattenConfig),
+    /// Concat Layer
+    Concat(ConcatConfig),
+    /// Slice Layer
+    Slice(SliceConfig),
 }
 
 impl LayerType {
     /// Returns wether the LayerType supports in-place operations.
     pub fn supports_in_place(&self) -> bool {
         match *self {
-            #[cfg(all(feature="cuda", not(feature="native")))]
             LayerType::Convolution(_) => false,
             LayerType::Linear(_) => false,
             LayerType::LogSoftmax => false,
-            #[cfg(all(feature="cuda", not(feature="native")))]
             LayerType::Pooling(_) => false,
+            LayerType::Dropout(_) => true,
+            LayerType::BatchNorm(_) => false,
+            LayerType::LayerNorm(_) => false,
+            LayerType::Embedding(_) => false,
+            LayerType::RNN(_) => false,
+            LayerType::LSTM(_) => false,
+            LayerType::GRU(_) => false,
+            LayerType::ScaledDotProductAttention(_) => false,
+            LayerType::MultiHeadAttention(_) => false,
+            LayerType::Eltwise(_) => false,
             LayerType::Sequential(_) => false,
+            LayerType::Graph(_) => false,
             LayerType::Softmax => false,
             LayerType::ReLU => false,
             LayerType::Sigmoid => false,
+            LayerType::TanH => true,
+            LayerType::LeakyReLU(ref config) => config.slope >= 0f32,
+            LayerType::PReLU(_) => false,
+            LayerType::ELU(ref config) => config.alpha > 0f32,
+            LayerType::GELU => false,
+            LayerType::Softplus => true,
+            LayerType::Swish => false,
             LayerType::NegativeLogLikelihood(_) => false,
+            LayerType::MeanSquaredError(_) => false,
+            LayerType::Huber(_) => false,
+            LayerType::SoftmaxCrossEntropy(_) => false,
+            LayerType::BinaryCrossEntropy(_) => false,
             LayerType::Reshape(_) => true,
+            LayerType::Flatten(_) => true,
+            LayerType::Concat(_) => false,
+            LayerType::Slice(_) => false,
         }
     }
 
@@ -1275,18 +1575,41 @@ impl<'a> CapnpWrite<'a> for LayerType {
     /// Write the LayerType into a capnp message.
     fn write_capnp(&self, builder: &mut Self::Builder) {
         match self {
-            #[cfg(all(feature="cuda", not(feature="native")))]
             &LayerType::Convolution(ref cfg) => { let ref mut config = builder.borrow().init_convolution(); cfg.write_capnp(config); },
             &LayerType::Linear(ref cfg) => { let ref mut config = builder.borrow().init_linear(); cfg.write_capnp(config); },
             &LayerType::LogSoftmax => { builder.set_log_softmax(()) },
-            #[cfg(all(feature="cuda", not(feature="native")))]
             &LayerType::Pooling(ref cfg) => { let ref mut config = builder.borrow().init_pooling(); cfg.write_capnp(config); },
+            &LayerType::Dropout(ref cfg) => { let ref mut config = builder.borrow().init_dropout(); cfg.write_capnp(config); },
+            &LayerType::BatchNorm(ref cfg) => { let ref mut config = builder.borrow().init_batch_norm(); cfg.write_capnp(config); },
+            &LayerType::LayerNorm(ref cfg) => { let ref mut config = builder.borrow().init_layer_norm(); cfg.write_capnp(config); },
+            &LayerType::Embedding(ref cfg) => { let ref mut config = builder.borrow().init_embedding(); cfg.write_capnp(config); },
+            &LayerType::RNN(ref cfg) => { let ref mut config = builder.borrow().init_rnn(); cfg.write_capnp(config); },
+            &LayerType::LSTM(ref cfg) => { let ref mut config = builder.borrow().init_lstm(); cfg.write_capnp(config); },
+            &LayerType::GRU(ref cfg) => { let ref mut config = builder.borrow().init_gru(); cfg.write_capnp(config); },
+            &LayerType::ScaledDotProductAttention(ref cfg) => { let ref mut config = builder.borrow().init_scaled_dot_product_attention(); cfg.write_capnp(config); },
+            &LayerType::MultiHeadAttention(ref cfg) => { let ref mut config = builder.borrow().init_multi_head_attention(); cfg.write_capnp(config); },
+            &LayerType::Eltwise(ref cfg) => { let ref mut config = builder.borrow().init_eltwise(); cfg.write_capnp(config); },
             &LayerType::Sequential(ref cfg) => { let ref mut config = builder.borrow().init_sequential(); cfg.write_capnp(config); },
+            &LayerType::Graph(ref cfg) => { let ref mut config = builder.borrow().init_graph(); cfg.write_capnp(config); },
             &LayerType::Softmax => { builder.set_softmax(()) },
             &LayerType::ReLU => { builder.set_relu(()) },
             &LayerType::Sigmoid => { builder.set_sigmoid(()) },
+            &LayerType::TanH => { builder.set_tanh(()) },
+            &LayerType::LeakyReLU(ref cfg) => { let ref mut config = builder.borrow().init_leaky_relu(); cfg.write_capnp(config); },
+            &LayerType::PReLU(ref cfg) => { let ref mut config = builder.borrow().init_prelu(); cfg.write_capnp(config); },
+            &LayerType::ELU(ref cfg) => { let ref mut config = builder.borrow().init_elu(); cfg.write_capnp(config); },
+            &LayerType::GELU => { builder.set_gelu(()) },
+            &LayerType::Softplus => { builder.set_softplus(()) },
+            &LayerType::Swish => { builder.set_swish(()) },
             &LayerType::NegativeLogLikelihood(ref cfg) => { let ref mut config = builder.borrow().init_negative_log_likelihood(); cfg.write_capnp(config); },
+            &LayerType::MeanSquaredError(ref cfg) => { let ref mut config = builder.borrow().init_mean_squared_error(); cfg.write_capnp(config); },
+            &LayerType::Huber(ref cfg) => { let ref mut config = builder.borrow().init_huber(); cfg.write_capnp(config); },
+            &LayerType::SoftmaxCrossEntropy(ref cfg) => { let ref mut config = builder.borrow().init_softmax_cross_entropy(); cfg.write_capnp(config); },
+            &LayerType::BinaryCrossEntropy(ref cfg) => { let ref mut config = builder.borrow().init_binary_cross_entropy(); cfg.write_capnp(config); },
             &LayerType::Reshape(ref cfg) => { let ref mut config = builder.borrow().init_reshape(); cfg.write_capnp(config); },
+            &LayerType::Flatten(ref cfg) => { let ref mut config = builder.borrow().init_flatten(); cfg.write_capnp(config); },
+            &LayerType::Concat(ref cfg) => { let ref mut config = builder.borrow().init_concat(); cfg.write_capnp(config); },
+            &LayerType::Slice(ref cfg) => { let ref mut config = builder.borrow().init_slice(); cfg.write_capnp(config); },
         }
     }
 }
@@ -1296,22 +1619,41 @@ impl<'a> CapnpRead<'a> for LayerType {
 
     fn read_capnp(reader: Self::Reader) -> Self {
         match reader.which().unwrap() {
-            #[cfg(all(feature="cuda", not(feature="native")))]
             capnp_layer_type::Which::Convolution(read_config) => { let config = ConvolutionConfig::read_capnp(read_config.unwrap()); LayerType::Convolution(config) },
-            #[cfg(not(all(feature="cuda", not(feature="native"))))]
-            capnp_layer_type::Which::Convolution(_) => { panic!("Can not load Network because Convolution layer is not supported with the used feature flags.") },
             capnp_layer_type::Which::Linear(read_config) => { let config = LinearConfig::read_capnp(read_config.unwrap()); LayerType::Linear(config) },
             capnp_layer_type::Which::LogSoftmax(read_config) => { LayerType::LogSoftmax },
-            #[cfg(all(feature="cuda", not(feature="native")))]
             capnp_layer_type::Which::Pooling(read_config) => { let config = PoolingConfig::read_capnp(read_config.unwrap()); LayerType::Pooling(config) },
-            #[cfg(not(all(feature="cuda", not(feature="native"))))]
-            capnp_layer_type::Which::Pooling(_) => { panic!("Can not load Network because Pooling layer is not supported with the used feature flags.") },
+            capnp_layer_type::Which::Dropout(read_config) => { let config = DropoutConfig::read_capnp(read_config.unwrap()); LayerType::Dropout(config) },
+            capnp_layer_type::Which::BatchNorm(read_config) => { let config = BatchNormConfig::read_capnp(read_config.unwrap()); LayerType::BatchNorm(config) },
+            capnp_layer_type::Which::LayerNorm(read_config) => { let config = LayerNormConfig::read_capnp(read_config.unwrap()); LayerType::LayerNorm(config) },
+            capnp_layer_type::Which::Embedding(read_config) => { let config = EmbeddingConfig::read_capnp(read_config.unwrap()); LayerType::Embedding(config) },
+            capnp_layer_type::Which::Rnn(read_config) => { let config = RecurrentConfig::read_capnp(read_config.unwrap()); LayerType::RNN(config) },
+            capnp_layer_type::Which::Lstm(read_config) => { let config = RecurrentConfig::read_capnp(read_config.unwrap()); LayerType::LSTM(config) },
+            capnp_layer_type::Which::Gru(read_config) => { let config = RecurrentConfig::read_capnp(read_config.unwrap()); LayerType::GRU(config) },
+            capnp_layer_type::Which::ScaledDotProductAttention(read_config) => { let config = ScaledDotProductAttentionConfig::read_capnp(read_config.unwrap()); LayerType::ScaledDotProductAttention(config) },
+            capnp_layer_type::Which::MultiHeadAttention(read_config) => { let config = MultiHeadAttentionConfig::read_capnp(read_config.unwrap()); LayerType::MultiHeadAttention(config) },
+            capnp_layer_type::Which::Eltwise(read_config) => { let config = EltwiseConfig::read_capnp(read_config.unwrap()); LayerType::Eltwise(config) },
             capnp_layer_type::Which::Sequential(read_config) => { let config = SequentialConfig::read_capnp(read_config.unwrap()); LayerType::Sequential(config) },
+            capnp_layer_type::Which::Graph(read_config) => { let config = GraphConfig::read_capnp(read_config.unwrap()); LayerType::Graph(config) },
             capnp_layer_type::Which::Softmax(_) => { LayerType::Softmax },
             capnp_layer_type::Which::Relu(_) => { LayerType::ReLU },
             capnp_layer_type::Which::Sigmoid(_) => { LayerType::Sigmoid },
+            capnp_layer_type::Which::Tanh(_) => { LayerType::TanH },
+            capnp_layer_type::Which::LeakyRelu(read_config) => { let config = LeakyReLUConfig::read_capnp(read_config.unwrap()); LayerType::LeakyReLU(config) },
+            capnp_layer_type::Which::Prelu(read_config) => { let config = PReLUConfig::read_capnp(read_config.unwrap()); LayerType::PReLU(config) },
+            capnp_layer_type::Which::Elu(read_config) => { let config = ELUConfig::read_capnp(read_config.unwrap()); LayerType::ELU(config) },
+            capnp_layer_type::Which::Gelu(_) => { LayerType::GELU },
+            capnp_layer_type::Which::Softplus(_) => { LayerType::Softplus },
+            capnp_layer_type::Which::Swish(_) => { LayerType::Swish },
             capnp_layer_type::Which::NegativeLogLikelihood(read_config) => { let config = NegativeLogLikelihoodConfig::read_capnp(read_config.unwrap()); LayerType::NegativeLogLikelihood(config) },
+            capnp_layer_type::Which::MeanSquaredError(read_config) => { let config = MeanSquaredErrorConfig::read_capnp(read_config.unwrap()); LayerType::MeanSquaredError(config) },
+            capnp_layer_type::Which::Huber(read_config) => { let config = HuberConfig::read_capnp(read_config.unwrap()); LayerType::Huber(config) },
+            capnp_layer_type::Which::SoftmaxCrossEntropy(read_config) => { let config = SoftmaxCrossEntropyConfig::read_capnp(read_config.unwrap()); LayerType::SoftmaxCrossEntropy(config) },
+            capnp_layer_type::Which::BinaryCrossEntropy(read_config) => { let config = BinaryCrossEntropyConfig::read_capnp(read_config.unwrap()); LayerType::BinaryCrossEntropy(config) },
             capnp_layer_type::Which::Reshape(read_config) => { let config = ReshapeConfig::read_capnp(read_config.unwrap()); LayerType::Reshape(config) },
+            capnp_layer_type::Which::Flatten(read_config) => { let config = FlattenConfig::read_capnp(read_config.unwrap()); LayerType::Flatten(config) },
+            capnp_layer_type::Which::Concat(read_config) => { let config = ConcatConfig::read_capnp(read_config.unwrap()); LayerType::Concat(config) },
+            capnp_layer_type::Which::Slice(read_config) => { let config = SliceConfig::read_capnp(read_config.unwrap()); LayerType::Slice(config) },
         }
     }
 }
@@ -0,0 +1,238 @@
+//! Computes the binary cross-entropy between the input and dense 0/1 targets.
+//!
+//! Takes the prediction as first and the target as second input, both need to
+//! hold the same number of values but can have an arbitrary shape.
+//! Every value is treated as an independent binary classification, which makes
+//! this the loss for multi-label classification, where a sample can belong to
+//! any number of classes.
+//!
+//! For a target `t` and a predicted probability `p` the loss is
+//!
+//! `-(pos_weight * t * ln(p) + (1 - t) * ln(1 - p))`
+//!
+//! and the layer outputs the mean over all values.
+//!
+//! In the default `with_logits` mode the input are raw, unnormalized scores and
+//! `p = sigmoid(x)` is computed inside the layer. This avoids taking the logarithm
+//! of probabilities that are rounded to 0 or 1, so it should be preferred over a
+//! [Sigmoid][sigmoid] layer followed by this loss.
+//!
+//! [sigmoid]: ../../activation/sigmoid/index.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::binary_cross_entropy_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+
+/// Probabilities are clamped to `[EPSILON, 1 - EPSILON]` when not using logits.
+const EPSILON: f32 = 1e-7;
+
+/// BinaryCrossEntropy Loss Layer
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone)]
+pub struct BinaryCrossEntropy {
+    with_logits: bool,
+    pos_weight: f32,
+}
+
+impl BinaryCrossEntropy {
+    /// Create a BinaryCrossEntropy layer from a BinaryCrossEntropyConfig.
+    pub fn from_config(config: &BinaryCrossEntropyConfig) -> BinaryCrossEntropy {
+        BinaryCrossEntropy {
+            with_logits: config.with_logits,
+            pos_weight: config.pos_weight,
+        }
+    }
+
+    /// `ln(1 + exp(x))` without overflowing for large `x`.
+    fn softplus(x: f32) -> f32 {
+        x.max(0f32) + (-x.abs()).exp().ln_1p()
+    }
+
+    fn sigmoid(x: f32) -> f32 {
+        1f32 / (1f32 + (-x).exp())
+    }
+
+    fn loss(&self, value: f32, target: f32) -> f32 {
+        if self.with_logits {
+            // -ln(sigmoid(x)) = softplus(-x), -ln(1 - sigmoid(x)) = softplus(x)
+            self.pos_weight * target * Self::softplus(-value) + (1f32 - target) * Self::softplus(value)
+        } else {
+            let probability = value.max(EPSILON).min(1f32 - EPSILON);
+            -(self.pos_weight * target * probability.ln() + (1f32 - target) * (1f32 - probability).ln())
+        }
+    }
+
+    fn gradient(&self, value: f32, target: f32) -> f32 {
+        if self.with_logits {
+            let probability = Self::sigmoid(value);
+            self.pos_weight * target * (probability - 1f32) + (1f32 - target) * probability
+        } else {
+            let probability = value.max(EPSILON).min(1f32 - EPSILON);
+            -self.pos_weight * target / probability + (1f32 - target) / (1f32 - probability)
+        }
+    }
+}
+
+impl LayerWorker for BinaryCrossEntropy {
+    fn exact_num_output_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn exact_num_input_blobs(&self) -> Option<usize> {
+        Some(2)
+    }
+
+    fn auto_output_blobs(&self) -> bool {
+        true
+    }
+
+    fn loss_weight(&self, output_id: usize) -> Option<f32> {
+        if output_id == 0 {
+            Some(1f32)
+        } else {
+            None
+        }
+    }
+
+    fn sync_native(&self) -> bool {
+        true
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let data = input_data[0].read().unwrap();
+        let target = input_data[1].read().unwrap();
+        if data.shape().capacity() != target.shape().capacity() {
+            panic!("The target of a BinaryCrossEntropy layer needs to have as many values as the input.");
+        }
+
+        input_gradient[0].write().unwrap().resize(data.shape().clone()).unwrap();
+        output_data[0].write().unwrap().resize(&[1][..]).unwrap();
+    }
+}
+
+impl ComputeOutput<f32> for BinaryCrossEntropy {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      _weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let native_data = input_data[0].as_slice().unwrap();
+        let native_target = input_data[1].as_slice().unwrap();
+
+        let sum = native_data.iter().zip(native_target)
+            .fold(0f32, |sum, (&value, &target)| sum + self.loss(value, target));
+        let loss = sum / native_data.len() as f32;
+
+        output_data[0].write_slice(&[loss]).unwrap()
+    }
+}
+
+impl ComputeInputGradient<f32> for BinaryCrossEntropy {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let native_data = input_data[0].as_slice().unwrap();
+        let native_target = input_data[1].as_slice().unwrap();
+
+        let scale = 1f32 / native_data.len() as f32;
+        let writable_gradient = native_data.iter().zip(native_target)
+            .map(|(&value, &target)| scale * self.gradient(value, target))
+            .collect::<Vec<_>>();
+
+        input_gradients[0].write_slice(&writable_gradient[..]).unwrap()
+    }
+}
+
+impl ComputeParametersGradient<f32> for BinaryCrossEntropy { }
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a BinaryCrossEntropy Layer.
+pub struct BinaryCrossEntropyConfig {
+    /// Whether the input are logits (`true`) or probabilities (`false`).
+    ///
+    /// Default: true
+    pub with_logits: bool,
+    /// The weight of the loss of positive targets.
+    ///
+    /// Values larger than `1` increase the recall, values smaller than `1` the precision.
+    ///
+    /// Default: 1.0
+    pub pos_weight: f32,
+}
+
+impl Default for BinaryCrossEntropyConfig {
+    fn default() -> BinaryCrossEntropyConfig {
+        BinaryCrossEntropyConfig {
+            with_logits: true,
+            pos_weight: 1f32,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for BinaryCrossEntropyConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the BinaryCrossEntropyConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_with_logits(self.with_logits);
+        builder.set_pos_weight(self.pos_weight);
+    }
+}
+
+impl<'a> CapnpRead<'a> for BinaryCrossEntropyConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        BinaryCrossEntropyConfig {
+            with_logits: reader.get_with_logits(),
+            pos_weight: reader.get_pos_weight(),
+        }
+    }
+}
+
+impl Into<LayerType> for BinaryCrossEntropyConfig {
+    fn into(self) -> LayerType {
+        LayerType::BinaryCrossEntropy(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BinaryCrossEntropy, BinaryCrossEntropyConfig};
+
+    #[test]
+    fn logits_match_probabilities() {
+        let with_logits = BinaryCrossEntropy::from_config(&BinaryCrossEntropyConfig::default());
+        let with_probabilities = BinaryCrossEntropy::from_config(&BinaryCrossEntropyConfig {
+            with_logits: false,
+            ..BinaryCrossEntropyConfig::default()
+        });
+
+        for &(logit, target) in &[(-2f32, 0f32), (0.5f32, 1f32), (3f32, 1f32)] {
+            let probability = BinaryCrossEntropy::sigmoid(logit);
+            assert!((with_logits.loss(logit, target) - with_probabilities.loss(probability, target)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn logits_are_stable_for_large_values() {
+        let layer = BinaryCrossEntropy::from_config(&BinaryCrossEntropyConfig::default());
+        assert!((layer.loss(-100f32, 1f32) - 100f32).abs() < 1e-4);
+        assert!(layer.loss(100f32, 1f32).abs() < 1e-6);
+        assert!((layer.gradient(-100f32, 1f32) + 1f32).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,224 @@
+//! Computes the Huber loss (also known as SmoothL1 loss) between the input and a target.
+//!
+//! Takes the prediction as first and the target as second input, both need to
+//! hold the same number of values but can have an arbitrary shape.
+//!
+//! For every difference `d = input - target` the loss is
+//!
+//! - `0.5 * d²` if `|d| <= delta`
+//! - `delta * (|d| - 0.5 * delta)` otherwise
+//!
+//! and the layer outputs `loss_weight` times the mean of it.
+//!
+//! Small differences are penalized quadratically like in the [MeanSquaredError][mse],
+//! large ones only linearly, which makes the Huber loss robust against outliers.
+//! With a `delta` of `1` it is the SmoothL1 loss known from object detection.
+//!
+//! [mse]: ../mean_squared_error/index.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::huber_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+
+/// Huber Loss Layer
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone)]
+pub struct Huber {
+    delta: f32,
+    loss_weight: f32,
+}
+
+impl Huber {
+    /// Create a Huber layer from a HuberConfig.
+    pub fn from_config(config: &HuberConfig) -> Huber {
+        if !(config.delta > 0f32) {
+            panic!("The delta of a Huber layer needs to be positive, got {}.", config.delta);
+        }
+        Huber {
+            delta: config.delta,
+            loss_weight: config.loss_weight,
+        }
+    }
+}
+
+impl LayerWorker for Huber {
+    fn exact_num_output_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn exact_num_input_blobs(&self) -> Option<usize> {
+        Some(2)
+    }
+
+    fn auto_output_blobs(&self) -> bool {
+        true
+    }
+
+    fn loss_weight(&self, output_id: usize) -> Option<f32> {
+        if output_id == 0 {
+            Some(self.loss_weight)
+        } else {
+            None
+        }
+    }
+
+    fn sync_native(&self) -> bool {
+        true
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let data = input_data[0].read().unwrap();
+        let target = input_data[1].read().unwrap();
+        if data.shape().capacity() != target.shape().capacity() {
+            panic!("The target of a Huber layer needs to have as many values as the input.");
+        }
+
+        input_gradient[0].write().unwrap().resize(data.shape().clone()).unwrap();
+        output_data[0].write().unwrap().resize(&[1][..]).unwrap();
+    }
+}
+
+impl ComputeOutput<f32> for Huber {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      _weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let native_data = input_data[0].as_slice().unwrap();
+        let native_target = input_data[1].as_slice().unwrap();
+
+        let delta = self.delta;
+        let sum = native_data.iter().zip(native_target).fold(0f32, |sum, (&value, &target)| {
+            let difference = (value - target).abs();
+            if difference <= delta {
+                sum + 0.5f32 * difference * difference
+            } else {
+                sum + delta * (difference - 0.5f32 * delta)
+            }
+        });
+        let loss = self.loss_weight * sum / native_data.len() as f32;
+
+        output_data[0].write_slice(&[loss]).unwrap()
+    }
+}
+
+impl ComputeInputGradient<f32> for Huber {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let native_data = input_data[0].as_slice().unwrap();
+        let native_target = input_data[1].as_slice().unwrap();
+
+        let delta = self.delta;
+        let scale = self.loss_weight / native_data.len() as f32;
+        let writable_gradient = native_data.iter().zip(native_target)
+            .map(|(&value, &target)| scale * (value - target).max(-delta).min(delta))
+            .collect::<Vec<_>>();
+
+        input_gradients[0].write_slice(&writable_gradient[..]).unwrap()
+    }
+}
+
+impl ComputeParametersGradient<f32> for Huber { }
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a Huber Layer.
+pub struct HuberConfig {
+    /// The absolute difference at which the loss changes from quadratic to linear.
+    /// Needs to be positive.
+    ///
+    /// Default: 1.0
+    pub delta: f32,
+    /// The factor the loss (and its gradient) is scaled by.
+    ///
+    /// Default: 1.0
+    pub loss_weight: f32,
+}
+
+impl Default for HuberConfig {
+    fn default() -> HuberConfig {
+        HuberConfig {
+            delta: 1f32,
+            loss_weight: 1f32,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for HuberConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the HuberConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_delta(self.delta);
+        builder.set_loss_weight(self.loss_weight);
+    }
+}
+
+impl<'a> CapnpRead<'a> for HuberConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        HuberConfig {
+            delta: reader.get_delta(),
+            loss_weight: reader.get_loss_weight(),
+        }
+    }
+}
+
+impl Into<LayerType> for HuberConfig {
+    fn into(self) -> LayerType {
+        LayerType::Huber(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Huber, HuberConfig};
+    use crate::cerealization_protocol::huber_config as capnp_config;
+    use crate::cerealization_protocol::CapnpRead;
+
+    #[test]
+    fn unset_delta_reads_back_as_default() {
+        let mut message = ::capnp::message::Builder::new_default();
+        message.init_root::<capnp_config::Builder>();
+        let mut buffer = Vec::new();
+        ::capnp::serialize_packed::write_message(&mut buffer, &message).unwrap();
+        let message_reader = ::capnp::serialize_packed::read_message(&mut &buffer[..],
+                                                                     ::capnp::message::ReaderOptions::new()).unwrap();
+        let config = HuberConfig::read_capnp(message_reader.get_root::<capnp_config::Reader>().unwrap());
+        assert_eq!(config.delta, 1f32);
+        Huber::from_config(&config);
+    }
+
+    #[test]
+    #[should_panic(expected = "needs to be positive, got 0")]
+    fn rejects_zero_delta() {
+        Huber::from_config(&HuberConfig { delta: 0f32, ..HuberConfig::default() });
+    }
+
+    #[test]
+    #[should_panic(expected = "needs to be positive, got -1")]
+    fn rejects_negative_delta() {
+        Huber::from_config(&HuberConfig { delta: -1f32, ..HuberConfig::default() });
+    }
+
+    #[test]
+    #[should_panic(expected = "needs to be positive, got NaN")]
+    fn rejects_nan_delta() {
+        Huber::from_config(&HuberConfig { delta: ::std::f32::NAN, ..HuberConfig::default() });
+    }
+}
@@ -0,0 +1,161 @@
+//! Computes the mean squared error between the input and a target.
+//!
+//! Takes the prediction as first and the target as second input, both need to
+//! hold the same number of values but can have an arbitrary shape.
+//!
+//! loss = loss_weight * mean((input - target)²)
+//!
+//! The Mean Squared Error is the standard loss for regression tasks.
+//! Since large differences are penalized quadratically, it is sensitive to outliers;
+//! consider the [Huber][huber] loss if your targets are noisy.
+//!
+//! [huber]: ../huber/index.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::mean_squared_error_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+
+/// MeanSquaredError Loss Layer
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone)]
+pub struct MeanSquaredError {
+    loss_weight: f32,
+}
+
+impl MeanSquaredError {
+    /// Create a MeanSquaredError layer from a MeanSquaredErrorConfig.
+    pub fn from_config(config: &MeanSquaredErrorConfig) -> MeanSquaredError {
+        MeanSquaredError {
+            loss_weight: config.loss_weight,
+        }
+    }
+}
+
+impl LayerWorker for MeanSquaredError {
+    fn exact_num_output_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn exact_num_input_blobs(&self) -> Option<usize> {
+        Some(2)
+    }
+
+    fn auto_output_blobs(&self) -> bool {
+        true
+    }
+
+    fn loss_weight(&self, output_id: usize) -> Option<f32> {
+        if output_id == 0 {
+            Some(self.loss_weight)
+        } else {
+            None
+        }
+    }
+
+    fn sync_native(&self) -> bool {
+        true
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let data = input_data[0].read().unwrap();
+        let target = input_data[1].read().unwrap();
+        if data.shape().capacity() != target.shape().capacity() {
+            panic!("The target of a MeanSquaredError layer needs to have as many values as the input.");
+        }
+
+        input_gradient[0].write().unwrap().resize(data.shape().clone()).unwrap();
+        output_data[0].write().unwrap().resize(&[1][..]).unwrap();
+    }
+}
+
+impl ComputeOutput<f32> for MeanSquaredError {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      _weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let native_data = input_data[0].as_slice().unwrap();
+        let native_target = input_data[1].as_slice().unwrap();
+
+        let sum = native_data.iter().zip(native_target).fold(0f32, |sum, (&value, &target)| {
+            sum + (value - target) * (value - target)
+        });
+        let loss = self.loss_weight * sum / native_data.len() as f32;
+
+        output_data[0].write_slice(&[loss]).unwrap()
+    }
+}
+
+impl ComputeInputGradient<f32> for MeanSquaredError {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let native_data = input_data[0].as_slice().unwrap();
+        let native_target = input_data[1].as_slice().unwrap();
+
+        let scale = 2f32 * self.loss_weight / native_data.len() as f32;
+        let writable_gradient = native_data.iter().zip(native_target)
+            .map(|(&value, &target)| scale * (value - target))
+            .collect::<Vec<_>>();
+
+        input_gradients[0].write_slice(&writable_gradient[..]).unwrap()
+    }
+}
+
+impl ComputeParametersGradient<f32> for MeanSquaredError { }
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a MeanSquaredError Layer.
+pub struct MeanSquaredErrorConfig {
+    /// The factor the loss (and its gradient) is scaled by.
+    ///
+    /// Default: 1.0
+    pub loss_weight: f32,
+}
+
+impl Default for MeanSquaredErrorConfig {
+    fn default() -> MeanSquaredErrorConfig {
+        MeanSquaredErrorConfig {
+            loss_weight: 1f32,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for MeanSquaredErrorConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the MeanSquaredErrorConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_loss_weight(self.loss_weight);
+    }
+}
+
+impl<'a> CapnpRead<'a> for MeanSquaredErrorConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        MeanSquaredErrorConfig {
+            loss_weight: reader.get_loss_weight(),
+        }
+    }
+}
+
+impl Into<LayerType> for MeanSquaredErrorConfig {
+    fn into(self) -> LayerType {
+        LayerType::MeanSquaredError(self)
+    }
+}
@@ -2,6 +2,14 @@
 //!
 //! A loss function is also sometimes called cost function.
 
+pub use self::binary_cross_entropy::{BinaryCrossEntropy, BinaryCrossEntropyConfig};
+pub use self::huber::{Huber, HuberConfig};
+pub use self::mean_squared_error::{MeanSquaredError, MeanSquaredErrorConfig};
 pub use self::negative_log_likelihood::{NegativeLogLikelihood, NegativeLogLikelihoodConfig};
+pub use self::softmax_cross_entropy::{SoftmaxCrossEntropy, SoftmaxCrossEntropyConfig};
 
+pub mod binary_cross_entropy;
+pub mod huber;
+pub mod mean_squared_error;
 pub mod negative_log_likelihood;
+pub mod softmax_cross_entropy;
@@ -1,5 +1,24 @@
-//! TODO: DOC
+//! Computes the negative log likelihood of the labeled classes.
 //!
+//! Takes the log-probabilities of every class as first and the class labels as second input.
+//! The log-probabilities are usually produced by a [LogSoftmax][log_softmax] layer.
+//!
+//! The classes are laid out along the class `axis` of the input; all axes before it
+//! are treated as batch axes and all axes after it as spatial axes. That way the same
+//! layer handles `[batch_size, num_classes]` outputs of a classifier as well as
+//! `[batch_size, num_classes, height, width]` outputs of a per-pixel classification.
+//! There is one label for every sample and spatial position, so the labels hold
+//! `input capacity / num_classes` values, e.g. `[batch_size]` or `[batch_size, height, width]`.
+//!
+//! The loss is the mean of `-log_probability[label]` over all labels that are not
+//! equal to the `ignore_index`. Labels have to be non-negative class indices.
+//!
+//! Only the labels are read on the host, to build a mask that holds `-1 / number of labels`
+//! at every labeled class and `0` everywhere else. The loss is the dot product of the mask
+//! with the log-probabilities, computed by the backend, and the mask is the gradient.
+//! The mask is built once in the forward pass and reused by the backward pass.
+//!
+//! [log_softmax]: ../../common/log_softmax/index.html
 
 use crate::cerealization_protocol::*;
 use crate::cerealization_protocol::negative_log_likelihood_config as capnp_config;
@@ -7,12 +26,18 @@ use crate::layers::core::*;
 use crate::typedefs::{ArcLockTensor, LeafBackend};
 
 use parenchyma::prelude::SharedTensor;
+use std::sync::{Arc, RwLock};
 
 /// NegativeLogLikelihood Loss Layer
 #[allow(missing_copy_implementations)]
 #[derive(Debug, Clone)]
 pub struct NegativeLogLikelihood {
     num_classes: usize,
+    axis: usize,
+    ignore_index: Option<usize>,
+
+    // the mask of the labels of the last forward pass
+    mask: ArcLockTensor,
 }
 
 impl NegativeLogLikelihood {
@@ -20,22 +45,76 @@ impl NegativeLogLikelihood {
     pub fn from_config(config: &NegativeLogLikelihoodConfig) -> NegativeLogLikelihood {
         NegativeLogLikelihood {
             num_classes: config.num_classes,
+            axis: config.axis,
+            ignore_index: config.ignore_index,
+
+            mask: Arc::new(RwLock::new(SharedTensor::from([1]))),
         }
     }
 
-    fn calculate_outer_num(softmax_axis: usize, input_shape: &[usize]) -> usize {
-        input_shape.iter().take(softmax_axis + 1).fold(1, |prod, i| prod * i)
+    /// The class axis for an input of `input_shape`.
+    ///
+    /// A 1D input holds the classes of a single sample.
+    fn class_axis(&self, input_shape: &[usize]) -> usize {
+        if input_shape.len() == 1 {
+            0
+        } else if self.axis < input_shape.len() {
+            self.axis
+        } else {
+            panic!("NegativeLogLikelihood class axis {} is out of range for a {}D input.", self.axis, input_shape.len())
+        }
+    }
+
+    fn calculate_outer_num(class_axis: usize, input_shape: &[usize]) -> usize {
+        input_shape.iter().take(class_axis).fold(1, |prod, i| prod * i)
     }
 
-    fn calculate_inner_num(softmax_axis: usize, input_shape: &[usize]) -> usize {
-        input_shape.iter().skip(softmax_axis + 1).fold(1, |prod, i| prod * i)
+    fn calculate_inner_num(class_axis: usize, input_shape: &[usize]) -> usize {
+        input_shape.iter().skip(class_axis + 1).fold(1, |prod, i| prod * i)
+    }
+
+    /// Calls `f(input_index)` for every label that is not ignored,
+    /// where `input_index` is the index of the labeled class in the input.
+    fn for_each_label<F: FnMut(usize)>(&self, input_shape: &[usize], labels: &[f32], mut f: F) {
+        let class_axis = self.class_axis(input_shape);
+        let outer_num = Self::calculate_outer_num(class_axis, input_shape);
+        let inner_num = Self::calculate_inner_num(class_axis, input_shape);
+
+        for outer in 0..outer_num {
+            for inner in 0..inner_num {
+                let label_index = outer * inner_num + inner;
+                let label_value = labels[label_index];
+                if label_value < 0f32 || label_value.fract() != 0f32 {
+                    panic!("Label {} is not a valid class index for a NegativeLogLikelihood layer.", label_value);
+                }
+                let label = label_value as usize;
+                if Some(label) == self.ignore_index {
+                    continue;
+                }
+                if label >= self.num_classes {
+                    panic!("Label {} is out of range for a NegativeLogLikelihood layer with {} classes.", label, self.num_classes);
+                }
+                f((outer * self.num_classes + label) * inner_num + inner);
+            }
+        }
     }
 
-    fn batch_size(input_shape: &[usize]) -> usize {
-        match input_shape.len() {
-            1 => 1,
-            2 => input_shape[0],
-            _ => panic!("NegativeLogLikelihood layer only supports 1D/2D inputs")
+    /// Writes the mask that holds `-1 / number of labels` at the labeled class of every label
+    /// that is not ignored and `0` everywhere else into `mask`.
+    ///
+    /// The dot product of the mask with the log-probabilities is the loss
+    /// and the mask itself is the gradient of the loss.
+    fn write_label_mask(&self, input_shape: &[usize], labels: &SharedTensor<f32>, mask: &mut SharedTensor<f32>) {
+        let mut indices = Vec::with_capacity(labels.shape().capacity());
+        self.for_each_label(input_shape, labels.as_slice().unwrap(), |index| indices.push(index));
+
+        let native_mask = mask.as_mut_slice().unwrap();
+        for value in native_mask.iter_mut() {
+            *value = 0f32;
+        }
+        let weight = -1f32 / ::std::cmp::max(indices.len(), 1) as f32;
+        for index in indices {
+            native_mask[index] = weight;
         }
     }
 }
@@ -44,9 +123,9 @@ impl LayerWorker for NegativeLogLikelihood {
     fn exact_num_output_blobs(&self) -> Option<usize> {
         return Some(1);
     }
-    
+
     fn exact_num_input_blobs(&self) -> Option<usize> {
-        return Some(1);
+        return Some(2);
     }
 
     fn auto_output_blobs(&self) -> bool {
@@ -61,10 +140,6 @@ impl LayerWorker for NegativeLogLikelihood {
         }
     }
 
-    fn sync_native(&self) -> bool {
-        true
-    }
-
     fn reshape(&mut self,
                backend: ::std::rc::Rc<LeafBackend>,
                input_data: &mut Vec<ArcLockTensor>,
@@ -74,10 +149,19 @@ impl LayerWorker for NegativeLogLikelihood {
                output_data: &mut Vec<ArcLockTensor>,
                output_gradient: &mut Vec<ArcLockTensor>) {
         let data = input_data[0].read().unwrap();
-        let label = input_data[1].read().unwrap();
+        let labels = input_data[1].read().unwrap();
+
+        let class_axis = self.class_axis(data.shape().dimensions());
+        if data.shape().dimensions()[class_axis] != self.num_classes {
+            panic!("NegativeLogLikelihood layer expects {} classes along axis {}.", self.num_classes, class_axis);
+        }
+        if data.shape().capacity() != labels.shape().capacity() * self.num_classes {
+            panic!("NegativeLogLikelihood layer expects {} log-probabilities for every label.", self.num_classes);
+        }
 
         input_gradient[0].write().unwrap().resize(data.shape().clone()).unwrap();
-        output_data[0].write().unwrap().resize(label.shape().clone()).unwrap();
+        output_data[0].write().unwrap().resize(&[1][..]).unwrap();
+        self.mask.write().unwrap().resize(data.shape().clone()).unwrap();
     }
 }
 
@@ -87,25 +171,9 @@ impl ComputeOutput<f32> for NegativeLogLikelihood {
                       _weights: &[&SharedTensor<f32>],
                       input_data: &[&SharedTensor<f32>],
                       output_data: &mut [&mut SharedTensor<f32>]) {
-        let probabilities = input_data[0];
-        let labels = input_data[1];
-
-        let batch_size = Self::batch_size(labels.shape().dimensions());
-
-        let native_labels = labels.as_slice().unwrap();
-        let native_probabilities = probabilities.as_slice().unwrap();
-
-        let mut writable_loss = Vec::<f32>::new();
-        for &label_value in native_labels {
-            let probability_value = native_probabilities[label_value as usize];
-            writable_loss.push(-probability_value);
-        }
-
-        let mut loss = writable_loss.iter().fold(0f32, |sum, &val| sum + val);
-        loss = loss / (batch_size as f32);
-        writable_loss = vec![loss];
-
-        output_data[0].write_slice(&writable_loss[..]).unwrap()
+        let mut mask = self.mask.write().unwrap();
+        self.write_label_mask(input_data[0].shape().dimensions(), input_data[1], &mut mask);
+        backend.dot(&mask, input_data[0], output_data[0]).unwrap();
     }
 }
 
@@ -117,18 +185,8 @@ impl ComputeInputGradient<f32> for NegativeLogLikelihood {
                               output_gradients: &[&SharedTensor<f32>],
                               input_data: &[&SharedTensor<f32>],
                               input_gradients: &mut [&mut SharedTensor<f32>]) {
-        let labels = input_data[1];
-        let batch_size = Self::batch_size(input_data[0].shape().dimensions());
-        let num_classes = self.num_classes;
-
-        let native_labels = labels.as_slice().unwrap();
-        let mut writable_gradient = vec![0f32; input_gradients[0].shape().capacity()];
-
-        for (batch_n, &label_value) in native_labels.iter().enumerate() {
-            let index = (num_classes * batch_n) + label_value as usize;
-            writable_gradient[index] = -1f32;
-        }
-        input_gradients[0].write_slice(&writable_gradient[..]).unwrap()
+        // the mask of the forward pass is the gradient
+        backend.copy(&self.mask.read().unwrap(), input_gradients[0]).unwrap();
     }
 }
 
@@ -140,6 +198,27 @@ impl ComputeParametersGradient<f32> for NegativeLogLikelihood { }
 pub struct NegativeLogLikelihoodConfig {
     /// How many different classes can be classified.
     pub num_classes: usize,
+    /// The axis of the input that holds the classes.
+    ///
+    /// Default: 1
+    pub axis: usize,
+    /// A label that doesn't contribute to the loss or the gradient,
+    /// e.g. to mask out padding or unlabeled pixels.
+    ///
+    /// Default: None
+    pub ignore_index: Option<usize>,
+}
+
+impl NegativeLogLikelihoodConfig {
+    /// Create a NegativeLogLikelihoodConfig for `num_classes` classes
+    /// along axis 1 without an ignored label.
+    pub fn new(num_classes: usize) -> NegativeLogLikelihoodConfig {
+        NegativeLogLikelihoodConfig {
+            num_classes: num_classes,
+            axis: 1,
+            ignore_index: None,
+        }
+    }
 }
 
 impl<'a> CapnpWrite<'a> for NegativeLogLikelihoodConfig {
@@ -148,6 +227,8 @@ impl<'a> CapnpWrite<'a> for NegativeLogLikelihoodConfig {
     /// Write the NegativeLogLikelihoodConfig into a capnp message.
     fn write_capnp(&self, builder: &mut Self::Builder) {
         builder.set_num_classes(self.num_classes as u64);
+        builder.set_axis(self.axis as u64);
+        builder.set_ignore_index(self.ignore_index.map_or(-1, |index| index as i64));
     }
 }
 
@@ -156,9 +237,16 @@ impl<'a> CapnpRead<'a> for NegativeLogLikelihoodConfig {
 
     fn read_capnp(reader: Self::Reader) -> Self {
         let num_classes = reader.get_num_classes() as usize;
+        let axis = reader.get_axis() as usize;
+        let ignore_index = match reader.get_ignore_index() {
+            index if index >= 0 => Some(index as usize),
+            _ => None,
+        };
 
         NegativeLogLikelihoodConfig {
-            num_classes: num_classes
+            num_classes: num_classes,
+            axis: axis,
+            ignore_index: ignore_index,
         }
     }
 }
@@ -168,3 +256,50 @@ impl Into<LayerType> for NegativeLogLikelihoodConfig {
         LayerType::NegativeLogLikelihood(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{NegativeLogLikelihood, NegativeLogLikelihoodConfig};
+
+    fn labeled_indices(layer: &NegativeLogLikelihood, input_shape: &[usize], labels: &[f32]) -> Vec<usize> {
+        let mut indices = Vec::new();
+        layer.for_each_label(input_shape, labels, |index| indices.push(index));
+        indices
+    }
+
+    #[test]
+    fn every_sample_reads_its_own_row() {
+        let layer = NegativeLogLikelihood::from_config(&NegativeLogLikelihoodConfig::new(3));
+        assert_eq!(labeled_indices(&layer, &[2, 3], &[2f32, 1f32]), vec![2, 4]);
+    }
+
+    #[test]
+    fn labels_per_pixel_along_class_axis() {
+        let layer = NegativeLogLikelihood::from_config(&NegativeLogLikelihoodConfig::new(2));
+        // [batch 1, classes 2, height 1, width 2]: class planes are [0, 1] and [2, 3]
+        assert_eq!(labeled_indices(&layer, &[1, 2, 1, 2], &[1f32, 0f32]), vec![2, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a valid class index")]
+    fn rejects_negative_labels() {
+        let layer = NegativeLogLikelihood::from_config(&NegativeLogLikelihoodConfig::new(2));
+        labeled_indices(&layer, &[2, 2], &[1f32, -1f32]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a valid class index")]
+    fn rejects_fractional_labels() {
+        let layer = NegativeLogLikelihood::from_config(&NegativeLogLikelihoodConfig::new(2));
+        labeled_indices(&layer, &[2, 2], &[0.5f32, 1f32]);
+    }
+
+    #[test]
+    fn skips_ignored_labels() {
+        let layer = NegativeLogLikelihood::from_config(&NegativeLogLikelihoodConfig {
+            ignore_index: Some(255),
+            ..NegativeLogLikelihoodConfig::new(2)
+        });
+        assert_eq!(labeled_indices(&layer, &[3, 2], &[1f32, 255f32, 0f32]), vec![1, 4]);
+    }
+}
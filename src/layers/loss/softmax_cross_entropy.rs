@@ -0,0 +1,312 @@
+//! Computes the cross-entropy between the softmax of the input and integer labels.
+//!
+//! Takes raw, unnormalized scores (logits) of shape `[batch_size, num_classes]` as first
+//! and the class labels of shape `[batch_size]` or `[batch_size, 1]` as second input.
+//!
+//! This fuses a [Softmax][softmax] / [LogSoftmax][log_softmax] layer and a
+//! [NegativeLogLikelihood][nll] loss into one layer. The softmax is computed with the
+//! log-sum-exp trick and the gradient w.r.t. the logits is simply `softmax(x) - target`,
+//! which is both cheaper and numerically more stable than backpropagating through the
+//! separate layers.
+//!
+//! Optionally the one-hot targets can be smoothed ([label smoothing][smoothing]) and every
+//! class can be weighted, e.g. to counteract imbalanced datasets. With class weights the
+//! loss is the weighted mean over the batch.
+//!
+//! [softmax]: ../../common/softmax/index.html
+//! [log_softmax]: ../../common/log_softmax/index.html
+//! [nll]: ../negative_log_likelihood/index.html
+//! [smoothing]: https://arxiv.org/abs/1512.00567
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::softmax_cross_entropy_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+
+/// SoftmaxCrossEntropy Loss Layer
+#[derive(Debug, Clone)]
+pub struct SoftmaxCrossEntropy {
+    num_classes: usize,
+    label_smoothing: f32,
+    class_weights: Option<Vec<f32>>,
+}
+
+impl SoftmaxCrossEntropy {
+    /// Create a SoftmaxCrossEntropy layer from a SoftmaxCrossEntropyConfig.
+    pub fn from_config(config: &SoftmaxCrossEntropyConfig) -> SoftmaxCrossEntropy {
+        if let Some(ref class_weights) = config.class_weights {
+            if class_weights.len() != config.num_classes {
+                panic!("SoftmaxCrossEntropy layer needs one class weight per class.");
+            }
+        }
+
+        SoftmaxCrossEntropy {
+            num_classes: config.num_classes,
+            label_smoothing: config.label_smoothing,
+            class_weights: config.class_weights.clone(),
+        }
+    }
+
+    fn class_weight(&self, class: usize) -> f32 {
+        match self.class_weights {
+            Some(ref class_weights) => class_weights[class],
+            None => 1f32,
+        }
+    }
+
+    /// The target probability of `class` for a sample labeled with `label`.
+    fn target(&self, class: usize, label: usize) -> f32 {
+        let smoothing = self.label_smoothing / self.num_classes as f32;
+        if class == label {
+            1f32 - self.label_smoothing + smoothing
+        } else {
+            smoothing
+        }
+    }
+
+    /// Writes the log-probabilities of the softmax over `logits` into `log_probabilities`.
+    fn log_softmax(logits: &[f32], log_probabilities: &mut [f32]) {
+        let max = logits.iter().fold(::std::f32::NEG_INFINITY, |max, &value| max.max(value));
+        let sum = logits.iter().fold(0f32, |sum, &value| sum + (value - max).exp());
+        let log_sum_exp = max + sum.ln();
+        for (log_probability, &value) in log_probabilities.iter_mut().zip(logits) {
+            *log_probability = value - log_sum_exp;
+        }
+    }
+
+    fn check_label(&self, label: f32) -> usize {
+        if label < 0f32 || label.fract() != 0f32 {
+            panic!("Label {} is not a valid class index for a SoftmaxCrossEntropy layer.", label);
+        }
+        let label = label as usize;
+        if label >= self.num_classes {
+            panic!("Label {} is out of range for a SoftmaxCrossEntropy layer with {} classes.", label, self.num_classes);
+        }
+        label
+    }
+}
+
+impl LayerWorker for SoftmaxCrossEntropy {
+    fn exact_num_output_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn exact_num_input_blobs(&self) -> Option<usize> {
+        Some(2)
+    }
+
+    fn auto_output_blobs(&self) -> bool {
+        true
+    }
+
+    fn loss_weight(&self, output_id: usize) -> Option<f32> {
+        if output_id == 0 {
+            Some(1f32)
+        } else {
+            None
+        }
+    }
+
+    fn sync_native(&self) -> bool {
+        true
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let data = input_data[0].read().unwrap();
+        let labels = input_data[1].read().unwrap();
+        if data.shape().capacity() != labels.shape().capacity() * self.num_classes {
+            panic!("SoftmaxCrossEntropy layer expects {} logits for every label.", self.num_classes);
+        }
+
+        input_gradient[0].write().unwrap().resize(data.shape().clone()).unwrap();
+        output_data[0].write().unwrap().resize(&[1][..]).unwrap();
+    }
+}
+
+impl ComputeOutput<f32> for SoftmaxCrossEntropy {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      _weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let native_logits = input_data[0].as_slice().unwrap();
+        let native_labels = input_data[1].as_slice().unwrap();
+
+        let mut log_probabilities = vec![0f32; self.num_classes];
+        let mut loss = 0f32;
+        let mut total_weight = 0f32;
+        for (logits, &label) in native_logits.chunks(self.num_classes).zip(native_labels) {
+            let label = self.check_label(label);
+            Self::log_softmax(logits, &mut log_probabilities);
+
+            let cross_entropy = log_probabilities.iter().enumerate()
+                .fold(0f32, |sum, (class, &log_probability)| sum - self.target(class, label) * log_probability);
+            let weight = self.class_weight(label);
+            loss += weight * cross_entropy;
+            total_weight += weight;
+        }
+        if total_weight > 0f32 {
+            loss /= total_weight;
+        }
+
+        output_data[0].write_slice(&[loss]).unwrap()
+    }
+}
+
+impl ComputeInputGradient<f32> for SoftmaxCrossEntropy {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let native_logits = input_data[0].as_slice().unwrap();
+        let native_labels = input_data[1].as_slice().unwrap();
+
+        let total_weight = native_labels.iter()
+            .fold(0f32, |sum, &label| sum + self.class_weight(self.check_label(label)));
+
+        let mut writable_gradient = vec![0f32; native_logits.len()];
+        for ((logits, gradient), &label) in native_logits.chunks(self.num_classes)
+                                                         .zip(writable_gradient.chunks_mut(self.num_classes))
+                                                         .zip(native_labels) {
+            let label = self.check_label(label);
+            if total_weight <= 0f32 {
+                continue;
+            }
+
+            Self::log_softmax(logits, gradient);
+            let scale = self.class_weight(label) / total_weight;
+            for (class, value) in gradient.iter_mut().enumerate() {
+                // d(cross_entropy)/d(logit) = softmax - target
+                *value = scale * (value.exp() - self.target(class, label));
+            }
+        }
+
+        input_gradients[0].write_slice(&writable_gradient[..]).unwrap()
+    }
+}
+
+impl ComputeParametersGradient<f32> for SoftmaxCrossEntropy { }
+
+#[derive(Debug, Clone)]
+/// Specifies configuration parameters for a SoftmaxCrossEntropy Layer.
+pub struct SoftmaxCrossEntropyConfig {
+    /// How many different classes can be classified.
+    pub num_classes: usize,
+    /// The amount of probability mass that is moved from the labeled class
+    /// to a uniform distribution over all classes.
+    ///
+    /// Default: 0.0
+    pub label_smoothing: f32,
+    /// The weight of every class, applied to the loss of the samples labeled with it.
+    ///
+    /// Default: None
+    pub class_weights: Option<Vec<f32>>,
+}
+
+impl SoftmaxCrossEntropyConfig {
+    /// Create a SoftmaxCrossEntropyConfig for `num_classes` classes
+    /// without label smoothing and class weights.
+    pub fn new(num_classes: usize) -> SoftmaxCrossEntropyConfig {
+        SoftmaxCrossEntropyConfig {
+            num_classes: num_classes,
+            label_smoothing: 0f32,
+            class_weights: None,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for SoftmaxCrossEntropyConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the SoftmaxCrossEntropyConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_num_classes(self.num_classes as u64);
+        builder.set_label_smoothing(self.label_smoothing);
+        if let Some(ref class_weights) = self.class_weights {
+            let mut weights = builder.borrow().init_class_weights(class_weights.len() as u32);
+            for (i, weight) in class_weights.iter().enumerate() {
+                weights.set(i as u32, *weight);
+            }
+        }
+    }
+}
+
+impl<'a> CapnpRead<'a> for SoftmaxCrossEntropyConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        let read_class_weights = reader.get_class_weights().unwrap();
+        let class_weights = if read_class_weights.len() > 0 {
+            Some((0..read_class_weights.len()).map(|i| read_class_weights.get(i)).collect())
+        } else {
+            None
+        };
+
+        SoftmaxCrossEntropyConfig {
+            num_classes: reader.get_num_classes() as usize,
+            label_smoothing: reader.get_label_smoothing(),
+            class_weights: class_weights,
+        }
+    }
+}
+
+impl Into<LayerType> for SoftmaxCrossEntropyConfig {
+    fn into(self) -> LayerType {
+        LayerType::SoftmaxCrossEntropy(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SoftmaxCrossEntropy, SoftmaxCrossEntropyConfig};
+
+    #[test]
+    fn log_softmax_is_stable_for_large_logits() {
+        let mut log_probabilities = [0f32; 2];
+        SoftmaxCrossEntropy::log_softmax(&[1000f32, 1000f32], &mut log_probabilities);
+        assert!((log_probabilities[0] - 0.5f32.ln()).abs() < 1e-6);
+        assert!((log_probabilities[1] - 0.5f32.ln()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn label_smoothing_keeps_a_distribution() {
+        let layer = SoftmaxCrossEntropy::from_config(&SoftmaxCrossEntropyConfig {
+            label_smoothing: 0.1f32,
+            ..SoftmaxCrossEntropyConfig::new(4)
+        });
+        let total = (0..4).fold(0f32, |sum, class| sum + layer.target(class, 2));
+        assert!((total - 1f32).abs() < 1e-6);
+        assert!((layer.target(2, 2) - 0.925f32).abs() < 1e-6);
+        assert!((layer.target(0, 2) - 0.025f32).abs() < 1e-6);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a valid class index")]
+    fn rejects_negative_labels() {
+        SoftmaxCrossEntropy::from_config(&SoftmaxCrossEntropyConfig::new(4)).check_label(-1f32);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a valid class index")]
+    fn rejects_fractional_labels() {
+        SoftmaxCrossEntropy::from_config(&SoftmaxCrossEntropyConfig::new(4)).check_label(2.7f32);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a valid class index")]
+    fn rejects_nan_labels() {
+        SoftmaxCrossEntropy::from_config(&SoftmaxCrossEntropyConfig::new(4)).check_label(::std::f32::NAN);
+    }
+}
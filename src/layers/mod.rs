@@ -52,31 +52,52 @@
 /// [1]: ./layer/trait.ILayer.html
 /// [2]: ./layers/activation/index.html
 
-pub use self::activation::{ReLU, Sigmoid, TanH};
+pub use self::activation::{
+    ELU, ELUConfig,
+    GELU,
+    LeakyReLU, LeakyReLUConfig,
+    PReLU, PReLUConfig,
+    ReLU,
+    Sigmoid,
+    Softplus,
+    Swish,
+    TanH,
+};
 pub use self::core::*;
 
-#[cfg(all(feature="cuda", not(feature="native")))]
 pub use self::common::{
+    BatchNorm, BatchNormConfig,
     Convolution, ConvolutionConfig,
-    Pooling, PoolingConfig, PoolingMode,
-};
-
-pub use self::common::{
+    Dropout, DropoutConfig,
+    Eltwise, EltwiseConfig, EltwiseOperation,
+    Embedding, EmbeddingConfig,
+    LayerNorm, LayerNormConfig,
     Linear, LinearConfig,
     LogSoftmax,
+    MultiHeadAttention, MultiHeadAttentionConfig,
+    Pooling, PoolingConfig, PoolingMode,
+    CellType, Recurrent, RecurrentConfig,
+    ScaledDotProductAttention, ScaledDotProductAttentionConfig,
     Softmax,
 };
 
 pub use self::loss::{
+    BinaryCrossEntropy, BinaryCrossEntropyConfig,
+    Huber, HuberConfig,
+    MeanSquaredError, MeanSquaredErrorConfig,
     NegativeLogLikelihood, NegativeLogLikelihoodConfig,
+    SoftmaxCrossEntropy, SoftmaxCrossEntropyConfig,
 };
 
 pub use self::utility::{
-    Flatten,
+    Concat, ConcatConfig,
+    Flatten, FlattenConfig,
     Reshape, ReshapeConfig,
+    Slice, SliceConfig,
 };
 
 pub use self::container::{
+    Graph, GraphConfig,
     Sequential, SequentialConfig,
 };
 
@@ -0,0 +1,202 @@
+//! Concatenates any number of inputs along an axis.
+//!
+//! All inputs need to have the same shape, except along the concatenation `axis`.
+//! The output has the sum of their sizes along the `axis`.
+//! The default `axis` of `1` concatenates the channels/features of a batch, e.g. to fuse the
+//! features of several branches of a network.
+//!
+//! This is the inverse of the [Slice][slice] layer.
+//!
+//! [slice]: ../slice/index.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::concat_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+
+/// Returns the number of blocks before `axis` and the number of values per index of `axis`.
+pub(crate) fn outer_inner_sizes(shape: &[usize], axis: usize) -> (usize, usize) {
+    if axis >= shape.len() {
+        panic!("Axis {} does not exist in a {}D tensor.", axis, shape.len());
+    }
+    let outer = shape[..axis].iter().fold(1, |prod, i| prod * i);
+    let inner = shape[(axis + 1)..].iter().fold(1, |prod, i| prod * i);
+    (outer, inner)
+}
+
+/// Concatenates `parts`, which have the sizes `axis_sizes` along the axis.
+pub(crate) fn concat_along_axis(parts: &[&[f32]], axis_sizes: &[usize], outer: usize, inner: usize) -> Vec<f32> {
+    let total = axis_sizes.iter().fold(0, |sum, size| sum + size);
+    let mut output = Vec::with_capacity(outer * total * inner);
+    for o in 0..outer {
+        for (part, &size) in parts.iter().zip(axis_sizes) {
+            let block = size * inner;
+            output.extend_from_slice(&part[(o * block)..((o + 1) * block)]);
+        }
+    }
+    output
+}
+
+/// Splits `input` into parts with the sizes `axis_sizes` along the axis.
+pub(crate) fn split_along_axis(input: &[f32], axis_sizes: &[usize], outer: usize, inner: usize) -> Vec<Vec<f32>> {
+    let mut parts = axis_sizes.iter().map(|size| Vec::with_capacity(outer * size * inner)).collect::<Vec<_>>();
+    let mut offset = 0;
+    for _ in 0..outer {
+        for (part, &size) in parts.iter_mut().zip(axis_sizes) {
+            let block = size * inner;
+            part.extend_from_slice(&input[offset..(offset + block)]);
+            offset += block;
+        }
+    }
+    parts
+}
+
+#[derive(Debug, Clone)]
+/// Concat Utility Layer
+pub struct Concat {
+    axis: usize,
+}
+
+impl Concat {
+    /// Create a Concat layer from a ConcatConfig.
+    pub fn from_config(config: &ConcatConfig) -> Concat {
+        Concat {
+            axis: config.axis,
+        }
+    }
+
+    fn axis_sizes(&self, input_data: &[&SharedTensor<f32>]) -> Vec<usize> {
+        input_data.iter().map(|input| input.shape().dimensions()[self.axis]).collect()
+    }
+}
+
+impl LayerWorker for Concat {
+    fn exact_num_output_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        if input_data.is_empty() {
+            panic!("Concat layer needs at least one input.");
+        }
+        let mut output_shape = input_data[0].read().unwrap().shape().dimensions().to_owned();
+        outer_inner_sizes(&output_shape, self.axis);
+        output_shape[self.axis] = 0;
+        for (input, gradient) in input_data.iter().zip(input_gradient.iter()) {
+            let input_shape = input.read().unwrap().shape().dimensions().to_owned();
+            let matches = input_shape.len() == output_shape.len() &&
+                input_shape.iter().zip(&output_shape).enumerate().all(|(axis, (a, b))| axis == self.axis || a == b);
+            if !matches {
+                panic!("The inputs of a Concat layer need to have the same shape except along axis {}.", self.axis);
+            }
+            output_shape[self.axis] += input_shape[self.axis];
+            gradient.write().unwrap().resize(&input_shape[..]).unwrap();
+        }
+        output_data[0].write().unwrap().resize(&output_shape[..]).unwrap();
+        output_gradient[0].write().unwrap().resize(&output_shape[..]).unwrap();
+    }
+}
+
+impl ComputeOutput<f32> for Concat {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      _weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let (outer, inner) = outer_inner_sizes(input_data[0].shape().dimensions(), self.axis);
+        let inputs = input_data.iter().map(|input| input.as_slice().unwrap()).collect::<Vec<_>>();
+        let output = concat_along_axis(&inputs, &self.axis_sizes(input_data), outer, inner);
+
+        output_data[0].write_slice(&output[..]).unwrap();
+    }
+}
+
+impl ComputeInputGradient<f32> for Concat {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let (outer, inner) = outer_inner_sizes(input_data[0].shape().dimensions(), self.axis);
+        let gradients = split_along_axis(output_gradients[0].as_slice().unwrap(), &self.axis_sizes(input_data), outer, inner);
+
+        for (input_gradient, gradient) in input_gradients.iter_mut().zip(gradients) {
+            input_gradient.write_slice(&gradient[..]).unwrap();
+        }
+    }
+}
+
+impl ComputeParametersGradient<f32> for Concat {}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a Concat Layer.
+pub struct ConcatConfig {
+    /// The axis along which the inputs are concatenated.
+    ///
+    /// Defaults to `1`
+    pub axis: usize,
+}
+
+impl Default for ConcatConfig {
+    fn default() -> ConcatConfig {
+        ConcatConfig {
+            axis: 1,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for ConcatConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the ConcatConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_axis(self.axis as u64);
+    }
+}
+
+impl<'a> CapnpRead<'a> for ConcatConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        ConcatConfig {
+            axis: reader.get_axis() as usize,
+        }
+    }
+}
+
+impl Into<LayerType> for ConcatConfig {
+    fn into(self) -> LayerType {
+        LayerType::Concat(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{concat_along_axis, outer_inner_sizes, split_along_axis};
+
+    #[test]
+    fn concat_and_split_are_inverse() {
+        // [2, 1, 2] and [2, 2, 2] concatenated along axis 1
+        let left = [1f32, 2f32, 7f32, 8f32];
+        let right = [3f32, 4f32, 5f32, 6f32, 9f32, 10f32, 11f32, 12f32];
+        let (outer, inner) = outer_inner_sizes(&[2, 1, 2], 1);
+        assert_eq!((outer, inner), (2, 2));
+
+        let output = concat_along_axis(&[&left, &right], &[1, 2], outer, inner);
+        assert_eq!(output, (1..13).map(|i| i as f32).collect::<Vec<_>>());
+
+        let parts = split_along_axis(&output, &[1, 2], outer, inner);
+        assert_eq!(parts, vec![left.to_vec(), right.to_vec()]);
+    }
+}
@@ -3,8 +3,162 @@
 //! Input of shape n * c * h * w becomes
 //! a simple vector output of shape n * (c*h*w).
 //!
+//! All axes starting at the configured `axis` are collapsed into one, so the
+//! default `axis` of `1` keeps the batch dimension intact.
+//!
+//! This layer should be used as in-place operation,
+//! so the tensor that should be flattened should be specified
+//! as both input and output.
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::flatten_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
 
 /// Flattening Utility Layer
 #[allow(missing_copy_implementations)]
 #[derive(Debug, Clone)]
-pub struct Flatten;
+pub struct Flatten {
+    axis: usize,
+}
+
+impl Flatten {
+    /// Create a Flatten layer from a FlattenConfig.
+    pub fn from_config(config: &FlattenConfig) -> Flatten {
+        Flatten {
+            axis: config.axis,
+        }
+    }
+
+    /// Calculates the flattened shape for an input of shape `input_shape`.
+    fn calculate_output_shape(&self, input_shape: &[usize]) -> Vec<usize> {
+        if self.axis >= input_shape.len() {
+            panic!("Can not flatten a {}D tensor starting at axis {}.", input_shape.len(), self.axis);
+        }
+        let mut output_shape = input_shape[..self.axis].to_vec();
+        output_shape.push(input_shape[self.axis..].iter().fold(1, |prod, i| prod * i));
+        output_shape
+    }
+}
+
+impl LayerWorker for Flatten {
+    fn compute_in_place(&self) -> bool {
+        true
+    }
+
+    fn auto_output_blobs(&self) -> bool {
+        false
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let output_shape = match input_data.get(0) {
+            Some(inp) => {
+                let input_shape = inp.read().unwrap().shape().dimensions().to_owned();
+                input_gradient[0].write().unwrap().resize(&input_shape[..]).unwrap();
+                self.calculate_output_shape(&input_shape)
+            }
+            // in-place: the output still has the shape of the input
+            None => {
+                let input_shape = output_data[0].read().unwrap().shape().dimensions().to_owned();
+                self.calculate_output_shape(&input_shape)
+            }
+        };
+        output_data[0].write().unwrap().resize(&output_shape[..]).unwrap();
+        output_gradient[0].write().unwrap().resize(&output_shape[..]).unwrap();
+    }
+}
+
+impl ComputeOutput<f32> for Flatten {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      _weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        if let Some(input) = input_data.get(0) {
+            backend.copy(input, output_data[0]).unwrap();
+        }
+    }
+}
+
+impl ComputeInputGradient<f32> for Flatten {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        if let Some(output_gradient) = output_gradients.get(0) {
+            backend.copy(output_gradient, input_gradients[0]).unwrap();
+        }
+    }
+}
+
+impl ComputeParametersGradient<f32> for Flatten {}
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for a Flatten Layer.
+pub struct FlattenConfig {
+    /// The first axis that should be collapsed.
+    ///
+    /// All axes before it are kept as they are.
+    ///
+    /// Defaults to `1`
+    pub axis: usize,
+}
+
+impl Default for FlattenConfig {
+    fn default() -> FlattenConfig {
+        FlattenConfig {
+            axis: 1,
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for FlattenConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the FlattenConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_axis(self.axis as u64);
+    }
+}
+
+impl<'a> CapnpRead<'a> for FlattenConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        FlattenConfig {
+            axis: reader.get_axis() as usize,
+        }
+    }
+}
+
+impl Into<LayerType> for FlattenConfig {
+    fn into(self) -> LayerType {
+        LayerType::Flatten(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Flatten, FlattenConfig};
+
+    #[test]
+    fn collapses_trailing_axes() {
+        let layer = Flatten::from_config(&FlattenConfig::default());
+        assert_eq!(layer.calculate_output_shape(&[8, 3, 4, 5]), vec![8, 60]);
+
+        let layer = Flatten::from_config(&FlattenConfig { axis: 2 });
+        assert_eq!(layer.calculate_output_shape(&[8, 3, 4, 5]), vec![8, 3, 20]);
+    }
+}
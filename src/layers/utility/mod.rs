@@ -9,8 +9,12 @@
 //! specific data access layers for e.g. a database like LevelDB.
 //!
 //! [1]: ../../layer/index.html
-pub use self::flatten::Flatten;
+pub use self::concat::{Concat, ConcatConfig};
+pub use self::flatten::{Flatten, FlattenConfig};
 pub use self::reshape::{Reshape, ReshapeConfig};
+pub use self::slice::{Slice, SliceConfig};
 
+pub mod concat;
 pub mod flatten;
 pub mod reshape;
+pub mod slice;
@@ -2,7 +2,8 @@
 //!
 //! This layer should be used as in-place operation,
 //! so the tensor that should be reshaped should be specified
-//! as both input and output.
+//! as both input and output. Otherwise the data is copied
+//! into the output.
 //!
 //! Reshaping a tensor is required so that it becomes
 //! usable for Layers that interpret meaning into the shape of
@@ -55,6 +56,10 @@ impl LayerWorker for Reshape {
                weights_gradient: &mut Vec<ArcLockTensor>,
                output_data: &mut Vec<ArcLockTensor>,
                output_gradient: &mut Vec<ArcLockTensor>) {
+        if let Some(inp) = input_data.get(0) {
+            let input_shape = inp.read().unwrap().shape().dimensions().to_owned();
+            input_gradient[0].write().unwrap().resize(&input_shape[..]).unwrap();
+        }
         output_data[0].write().unwrap().resize(&self.shape[..]).unwrap();
         output_gradient[0].write().unwrap().resize(&self.shape[..]).unwrap();
     }
@@ -66,6 +71,9 @@ impl ComputeOutput<f32> for Reshape {
                       _weights: &[&SharedTensor<f32>],
                       input_data: &[&SharedTensor<f32>],
                       output_data: &mut [&mut SharedTensor<f32>]) {
+        if let Some(input) = input_data.get(0) {
+            backend.copy(input, output_data[0]).unwrap();
+        }
     }
 }
 
@@ -76,7 +84,11 @@ impl ComputeInputGradient<f32> for Reshape {
                               output_data: &[&SharedTensor<f32>],
                               output_gradients: &[&SharedTensor<f32>],
                               input_data: &[&SharedTensor<f32>],
-                              input_gradients: &mut [&mut SharedTensor<f32>]) {}
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        if let Some(output_gradient) = output_gradients.get(0) {
+            backend.copy(output_gradient, input_gradients[0]).unwrap();
+        }
+    }
 }
 
 impl ComputeParametersGradient<f32> for Reshape {}
@@ -0,0 +1,197 @@
+//! Slices the input along an axis into several outputs.
+//!
+//! The input is split at the configured `slice_points` along the `axis`, so `n` slice points
+//! produce `n + 1` outputs. Without slice points the input is split into as many equally
+//! sized parts as the layer has outputs.
+//!
+//! This is the inverse of the [Concat][concat] layer. Since every output is a separate
+//! tensor, the slices are best consumed by layers in a [Graph][graph].
+//!
+//! [concat]: ../concat/index.html
+//! [graph]: ../../container/graph/index.html
+
+use crate::cerealization_protocol::*;
+use crate::cerealization_protocol::slice_config as capnp_config;
+use crate::layers::core::*;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+use super::concat::{concat_along_axis, outer_inner_sizes, split_along_axis};
+
+use parenchyma::prelude::SharedTensor;
+
+#[derive(Debug, Clone)]
+/// Slice Utility Layer
+pub struct Slice {
+    axis: usize,
+    slice_points: Vec<usize>,
+
+    // the sizes of the outputs along the axis
+    axis_sizes: Vec<usize>,
+}
+
+impl Slice {
+    /// Create a Slice layer from a SliceConfig.
+    pub fn from_config(config: &SliceConfig) -> Slice {
+        Slice {
+            axis: config.axis,
+            slice_points: config.slice_points.clone(),
+
+            axis_sizes: Vec::new(),
+        }
+    }
+
+    /// Calculates the sizes of `num_outputs` slices along an axis of size `axis_len`.
+    fn calculate_axis_sizes(&self, axis_len: usize, num_outputs: usize) -> Vec<usize> {
+        if self.slice_points.is_empty() {
+            if num_outputs == 0 || axis_len % num_outputs != 0 {
+                panic!("Slice layer can not split an axis of size {} into {} equal parts.", axis_len, num_outputs);
+            }
+            return vec![axis_len / num_outputs; num_outputs];
+        }
+
+        if self.slice_points.len() + 1 != num_outputs {
+            panic!("Slice layer with {} slice points needs {} outputs.", self.slice_points.len(), self.slice_points.len() + 1);
+        }
+        let mut sizes = Vec::with_capacity(num_outputs);
+        let mut start = 0;
+        for &point in self.slice_points.iter().chain(Some(&axis_len)) {
+            if point <= start || point > axis_len {
+                panic!("The slice points of a Slice layer need to be increasing and inside the axis of size {}.", axis_len);
+            }
+            sizes.push(point - start);
+            start = point;
+        }
+        sizes
+    }
+}
+
+impl LayerWorker for Slice {
+    fn exact_num_input_blobs(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn reshape(&mut self,
+               backend: ::std::rc::Rc<LeafBackend>,
+               input_data: &mut Vec<ArcLockTensor>,
+               input_gradient: &mut Vec<ArcLockTensor>,
+               weights_data: &mut Vec<ArcLockTensor>,
+               weights_gradient: &mut Vec<ArcLockTensor>,
+               output_data: &mut Vec<ArcLockTensor>,
+               output_gradient: &mut Vec<ArcLockTensor>) {
+        let input_shape = input_data[0].read().unwrap().shape().dimensions().to_owned();
+        outer_inner_sizes(&input_shape, self.axis);
+        input_gradient[0].write().unwrap().resize(&input_shape[..]).unwrap();
+
+        self.axis_sizes = self.calculate_axis_sizes(input_shape[self.axis], output_data.len());
+        for ((output, gradient), &size) in output_data.iter().zip(output_gradient.iter()).zip(&self.axis_sizes) {
+            let mut output_shape = input_shape.clone();
+            output_shape[self.axis] = size;
+            output.write().unwrap().resize(&output_shape[..]).unwrap();
+            gradient.write().unwrap().resize(&output_shape[..]).unwrap();
+        }
+    }
+}
+
+impl ComputeOutput<f32> for Slice {
+    fn compute_output(&self,
+                      backend: &LeafBackend,
+                      _weights: &[&SharedTensor<f32>],
+                      input_data: &[&SharedTensor<f32>],
+                      output_data: &mut [&mut SharedTensor<f32>]) {
+        let (outer, inner) = outer_inner_sizes(input_data[0].shape().dimensions(), self.axis);
+        let outputs = split_along_axis(input_data[0].as_slice().unwrap(), &self.axis_sizes, outer, inner);
+
+        for (output_tensor, output) in output_data.iter_mut().zip(outputs) {
+            output_tensor.write_slice(&output[..]).unwrap();
+        }
+    }
+}
+
+impl ComputeInputGradient<f32> for Slice {
+    fn compute_input_gradient(&self,
+                              backend: &LeafBackend,
+                              weights_data: &[&SharedTensor<f32>],
+                              output_data: &[&SharedTensor<f32>],
+                              output_gradients: &[&SharedTensor<f32>],
+                              input_data: &[&SharedTensor<f32>],
+                              input_gradients: &mut [&mut SharedTensor<f32>]) {
+        let (outer, inner) = outer_inner_sizes(input_data[0].shape().dimensions(), self.axis);
+        let gradients = output_gradients.iter().map(|gradient| gradient.as_slice().unwrap()).collect::<Vec<_>>();
+        let input_gradient = concat_along_axis(&gradients, &self.axis_sizes, outer, inner);
+
+        input_gradients[0].write_slice(&input_gradient[..]).unwrap();
+    }
+}
+
+impl ComputeParametersGradient<f32> for Slice {}
+
+#[derive(Debug, Clone)]
+/// Specifies configuration parameters for a Slice Layer.
+pub struct SliceConfig {
+    /// The axis along which the input is sliced.
+    ///
+    /// Defaults to `1`
+    pub axis: usize,
+    /// The indices along the axis at which a new slice starts.
+    ///
+    /// If empty, the input is split into equally sized slices.
+    pub slice_points: Vec<usize>,
+}
+
+impl Default for SliceConfig {
+    fn default() -> SliceConfig {
+        SliceConfig {
+            axis: 1,
+            slice_points: Vec::new(),
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for SliceConfig {
+    type Builder = capnp_config::Builder<'a>;
+
+    /// Write the SliceConfig into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        builder.set_axis(self.axis as u64);
+        let mut slice_points = builder.borrow().init_slice_points(self.slice_points.len() as u32);
+        for (i, point) in self.slice_points.iter().enumerate() {
+            slice_points.set(i as u32, *point as u64);
+        }
+    }
+}
+
+impl<'a> CapnpRead<'a> for SliceConfig {
+    type Reader = capnp_config::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        let read_slice_points = reader.get_slice_points().unwrap();
+        let mut slice_points = Vec::new();
+        for i in 0..read_slice_points.len() {
+            slice_points.push(read_slice_points.get(i) as usize)
+        }
+
+        SliceConfig {
+            axis: reader.get_axis() as usize,
+            slice_points: slice_points,
+        }
+    }
+}
+
+impl Into<LayerType> for SliceConfig {
+    fn into(self) -> LayerType {
+        LayerType::Slice(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Slice, SliceConfig};
+
+    #[test]
+    fn calculates_slice_sizes() {
+        let layer = Slice::from_config(&SliceConfig::default());
+        assert_eq!(layer.calculate_axis_sizes(6, 3), vec![2, 2, 2]);
+
+        let layer = Slice::from_config(&SliceConfig { axis: 1, slice_points: vec![1, 4] });
+        assert_eq!(layer.calculate_axis_sizes(6, 3), vec![1, 3, 2]);
+    }
+}
@@ -123,6 +123,7 @@ extern crate parenchyma_deep;
 extern crate parenchyma_ml;
 
 pub mod cerealization_protocol;
+pub mod gradient_check;
 pub mod layers;
 pub mod solvers;
 pub mod typedefs;
@@ -0,0 +1,106 @@
+//! An [AdaDelta][1] solver.
+//! [1]: https://arxiv.org/abs/1212.5701
+//!
+//! AdaDelta keeps exponentially decaying averages of both the squared gradients
+//! and the squared updates of every weight. The update is the gradient scaled by
+//! the ratio of the two root mean squares, which gives it the same unit as the weight.
+//!
+//! As proposed in the paper, the update does not need a learning rate.
+//! The learning rate of the solver is still applied as a multiplier, so a `base_lr`
+//! of `1.0` corresponds to the original algorithm.
+
+use crate::layers::core::*;
+use crate::solvers::core::*;
+use crate::solvers::SGDSolver;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+/// AdaDelta solver.
+///
+/// See [module description][1] for more information.
+/// [1]: ./index.html
+#[derive(Debug)]
+pub struct AdaDelta {
+    /// The average of the squared gradients and of the squared updates for each blob.
+    history: Vec<ArcLockTensor>,
+    /// The backend used for computing the gradient.
+    backend: Rc<LeafBackend>,
+    /// The factor the gradients were scaled by during the last gradient clipping.
+    clip_scale: Option<f32>,
+
+    config: AdaDeltaConfig,
+}
+
+impl AdaDelta {
+    /// Create a new AdaDelta solver.
+    ///
+    /// Should not be called directly.
+    /// Use [Solver::from_config][2] instead.
+    ///
+    /// [2]: ../../../solver/struct.Solver.html#method.from_config
+    pub fn new(backend: Rc<LeafBackend>, config: AdaDeltaConfig) -> AdaDelta {
+        AdaDelta {
+            history: Vec::new(),
+            backend: backend,
+            clip_scale: None,
+
+            config: config,
+        }
+    }
+}
+
+impl SGDSolver for AdaDelta {
+    fn history_size(&self) -> usize {
+        2
+    }
+
+    fn compute_update_value(&mut self,
+                            config: &SolverConfig,
+                            weight_gradient: &ArcLockTensor,
+                            history_blob_id: usize,
+                            global_lr: &f32,
+                            blob_lr: &f32) {
+        let rate = global_lr * blob_lr;
+        let AdaDeltaConfig { decay, epsilon } = self.config;
+
+        let mut squared_gradients = self.history[2 * history_blob_id].write().unwrap();
+        let mut squared_updates = self.history[2 * history_blob_id + 1].write().unwrap();
+        let mut gradient = weight_gradient.write().unwrap();
+
+        let squared_gradients = squared_gradients.as_mut_slice().unwrap();
+        let squared_updates = squared_updates.as_mut_slice().unwrap();
+        for (i, value) in gradient.as_mut_slice().unwrap().iter_mut().enumerate() {
+            squared_gradients[i] = decay * squared_gradients[i] + (1f32 - decay) * *value * *value;
+            let update = *value * ((squared_updates[i] + epsilon) / (squared_gradients[i] + epsilon)).sqrt();
+            squared_updates[i] = decay * squared_updates[i] + (1f32 - decay) * update * update;
+            *value = rate * update;
+        }
+    }
+}
+
+impl_isolver_sgd!(AdaDelta);
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for an AdaDelta solver.
+pub struct AdaDeltaConfig {
+    /// The decay rate of the averages of the squared gradients and updates.
+    ///
+    /// Default: 0.95
+    pub decay: f32,
+    /// Small constant added to both averages for numerical stability.
+    ///
+    /// Default: 1e-6
+    pub epsilon: f32,
+}
+
+impl Default for AdaDeltaConfig {
+    fn default() -> AdaDeltaConfig {
+        AdaDeltaConfig {
+            decay: 0.95f32,
+            epsilon: 1e-6f32,
+        }
+    }
+}
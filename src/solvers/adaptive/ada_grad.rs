@@ -0,0 +1,93 @@
+//! An [AdaGrad][1] (Adaptive Gradient) solver.
+//! [1]: http://www.jmlr.org/papers/volume12/duchi11a/duchi11a.pdf
+//!
+//! AdaGrad accumulates the squared gradients of every weight over the whole
+//! training and divides the learning rate by the square root of that sum.
+//! Values that received large gradients in the past therefore take smaller steps.
+//!
+//! Since the accumulated sum only grows, the effective learning rate keeps
+//! shrinking during training. See [RMSProp][2] for a variant that forgets old gradients.
+//! [2]: ../rms_prop/index.html
+
+use crate::layers::core::*;
+use crate::solvers::core::*;
+use crate::solvers::SGDSolver;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+/// AdaGrad solver.
+///
+/// See [module description][1] for more information.
+/// [1]: ./index.html
+#[derive(Debug)]
+pub struct AdaGrad {
+    /// The sum of the squared gradients for each blob.
+    history: Vec<ArcLockTensor>,
+    /// The backend used for computing the gradient.
+    backend: Rc<LeafBackend>,
+    /// The factor the gradients were scaled by during the last gradient clipping.
+    clip_scale: Option<f32>,
+
+    config: AdaGradConfig,
+}
+
+impl AdaGrad {
+    /// Create a new AdaGrad solver.
+    ///
+    /// Should not be called directly.
+    /// Use [Solver::from_config][2] instead.
+    ///
+    /// [2]: ../../../solver/struct.Solver.html#method.from_config
+    pub fn new(backend: Rc<LeafBackend>, config: AdaGradConfig) -> AdaGrad {
+        AdaGrad {
+            history: Vec::new(),
+            backend: backend,
+            clip_scale: None,
+
+            config: config,
+        }
+    }
+}
+
+impl SGDSolver for AdaGrad {
+    fn compute_update_value(&mut self,
+                            config: &SolverConfig,
+                            weight_gradient: &ArcLockTensor,
+                            history_blob_id: usize,
+                            global_lr: &f32,
+                            blob_lr: &f32) {
+        let rate = global_lr * blob_lr;
+        let epsilon = self.config.epsilon;
+
+        let mut squared_sum = self.history[history_blob_id].write().unwrap();
+        let mut gradient = weight_gradient.write().unwrap();
+
+        let squared_sum = squared_sum.as_mut_slice().unwrap();
+        for (i, value) in gradient.as_mut_slice().unwrap().iter_mut().enumerate() {
+            squared_sum[i] += *value * *value;
+            *value = rate * *value / (squared_sum[i].sqrt() + epsilon);
+        }
+    }
+}
+
+impl_isolver_sgd!(AdaGrad);
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for an AdaGrad solver.
+pub struct AdaGradConfig {
+    /// Small constant added to the denominator for numerical stability.
+    ///
+    /// Default: 1e-8
+    pub epsilon: f32,
+}
+
+impl Default for AdaGradConfig {
+    fn default() -> AdaGradConfig {
+        AdaGradConfig {
+            epsilon: 1e-8f32,
+        }
+    }
+}
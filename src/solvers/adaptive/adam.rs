@@ -0,0 +1,119 @@
+//! An [Adam][1] (Adaptive Moment Estimation) solver.
+//! [1]: https://arxiv.org/abs/1412.6980
+//!
+//! Adam keeps exponentially decaying averages of both the past gradients
+//! (first moment) and the past squared gradients (second moment) for every weight.
+//! The update divides the first moment by the square root of the second moment,
+//! so every value of a weight effectively gets its own learning rate.
+//!
+//! Both averages are initialized with zero and therefore biased towards zero
+//! during the first iterations, which Adam corrects for.
+
+use crate::layers::core::*;
+use crate::solvers::core::*;
+use crate::solvers::SGDSolver;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+/// Adam solver.
+///
+/// See [module description][1] for more information.
+/// [1]: ./index.html
+#[derive(Debug)]
+pub struct Adam {
+    /// The first and second moment for each blob.
+    history: Vec<ArcLockTensor>,
+    /// The backend used for computing the gradient.
+    backend: Rc<LeafBackend>,
+    /// The factor the gradients were scaled by during the last gradient clipping.
+    clip_scale: Option<f32>,
+
+    config: AdamConfig,
+    /// The number of updates that have been computed so far.
+    timestep: i32,
+}
+
+impl Adam {
+    /// Create a new Adam solver.
+    ///
+    /// Should not be called directly.
+    /// Use [Solver::from_config][2] instead.
+    ///
+    /// [2]: ../../../solver/struct.Solver.html#method.from_config
+    pub fn new(backend: Rc<LeafBackend>, config: AdamConfig) -> Adam {
+        Adam {
+            history: Vec::new(),
+            backend: backend,
+            clip_scale: None,
+
+            config: config,
+            timestep: 0,
+        }
+    }
+}
+
+impl SGDSolver for Adam {
+    fn history_size(&self) -> usize {
+        2
+    }
+
+    fn compute_update_value(&mut self,
+                            config: &SolverConfig,
+                            weight_gradient: &ArcLockTensor,
+                            history_blob_id: usize,
+                            global_lr: &f32,
+                            blob_lr: &f32) {
+        // the first weight marks the start of a new update
+        if history_blob_id == 0 {
+            self.timestep += 1;
+        }
+
+        let AdamConfig { beta1, beta2, epsilon } = self.config;
+        let bias_correction = (1f32 - beta2.powi(self.timestep)).sqrt() / (1f32 - beta1.powi(self.timestep));
+        let rate = global_lr * blob_lr * bias_correction;
+
+        let mut first_moment = self.history[2 * history_blob_id].write().unwrap();
+        let mut second_moment = self.history[2 * history_blob_id + 1].write().unwrap();
+        let mut gradient = weight_gradient.write().unwrap();
+
+        let first_moment = first_moment.as_mut_slice().unwrap();
+        let second_moment = second_moment.as_mut_slice().unwrap();
+        for (i, value) in gradient.as_mut_slice().unwrap().iter_mut().enumerate() {
+            first_moment[i] = beta1 * first_moment[i] + (1f32 - beta1) * *value;
+            second_moment[i] = beta2 * second_moment[i] + (1f32 - beta2) * *value * *value;
+            *value = rate * first_moment[i] / (second_moment[i].sqrt() + epsilon);
+        }
+    }
+}
+
+impl_isolver_sgd!(Adam);
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for an Adam solver.
+pub struct AdamConfig {
+    /// The decay rate of the first moment.
+    ///
+    /// Default: 0.9
+    pub beta1: f32,
+    /// The decay rate of the second moment.
+    ///
+    /// Default: 0.999
+    pub beta2: f32,
+    /// Small constant added to the denominator for numerical stability.
+    ///
+    /// Default: 1e-8
+    pub epsilon: f32,
+}
+
+impl Default for AdamConfig {
+    fn default() -> AdamConfig {
+        AdamConfig {
+            beta1: 0.9f32,
+            beta2: 0.999f32,
+            epsilon: 1e-8f32,
+        }
+    }
+}
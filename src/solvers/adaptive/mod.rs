@@ -0,0 +1,26 @@
+//! Provides SolverWorker implementations with adaptive learning rates.
+//!
+//! While [SGD solvers][sgd] apply the same learning rate to every value of
+//! every weight, adaptive solvers keep a history of the gradients for each
+//! weight and use it to scale the learning rate individually for every value.
+//! Values with large or frequent gradients take smaller steps, values with
+//! small or rare gradients take larger ones.
+//!
+//! This makes training deeper networks a lot less sensitive to the choice of
+//! the learning rate.
+//!
+//! All adaptive solvers are configured through their own config struct, which
+//! is passed as part of the [SolverKind][solver_kind].
+//!
+//! [sgd]: ../sgd/index.html
+//! [solver_kind]: ../../solver/enum.SolverKind.html
+
+pub use self::ada_delta::{AdaDelta, AdaDeltaConfig};
+pub use self::ada_grad::{AdaGrad, AdaGradConfig};
+pub use self::adam::{Adam, AdamConfig};
+pub use self::rms_prop::{RMSProp, RMSPropConfig};
+
+pub mod ada_delta;
+pub mod ada_grad;
+pub mod adam;
+pub mod rms_prop;
@@ -0,0 +1,97 @@
+//! An [RMSProp][1] solver.
+//! [1]: http://www.cs.toronto.edu/~tijmen/csc321/slides/lecture_slides_lec6.pdf
+//!
+//! RMSProp keeps an exponentially decaying average of the squared gradients
+//! of every weight and divides the learning rate by its square root.
+//!
+//! Unlike [AdaGrad][2], old gradients are forgotten over time, so the effective
+//! learning rate does not shrink towards zero during long trainings.
+//! [2]: ../ada_grad/index.html
+
+use crate::layers::core::*;
+use crate::solvers::core::*;
+use crate::solvers::SGDSolver;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+/// RMSProp solver.
+///
+/// See [module description][1] for more information.
+/// [1]: ./index.html
+#[derive(Debug)]
+pub struct RMSProp {
+    /// The average of the squared gradients for each blob.
+    history: Vec<ArcLockTensor>,
+    /// The backend used for computing the gradient.
+    backend: Rc<LeafBackend>,
+    /// The factor the gradients were scaled by during the last gradient clipping.
+    clip_scale: Option<f32>,
+
+    config: RMSPropConfig,
+}
+
+impl RMSProp {
+    /// Create a new RMSProp solver.
+    ///
+    /// Should not be called directly.
+    /// Use [Solver::from_config][2] instead.
+    ///
+    /// [2]: ../../../solver/struct.Solver.html#method.from_config
+    pub fn new(backend: Rc<LeafBackend>, config: RMSPropConfig) -> RMSProp {
+        RMSProp {
+            history: Vec::new(),
+            backend: backend,
+            clip_scale: None,
+
+            config: config,
+        }
+    }
+}
+
+impl SGDSolver for RMSProp {
+    fn compute_update_value(&mut self,
+                            config: &SolverConfig,
+                            weight_gradient: &ArcLockTensor,
+                            history_blob_id: usize,
+                            global_lr: &f32,
+                            blob_lr: &f32) {
+        let rate = global_lr * blob_lr;
+        let RMSPropConfig { decay, epsilon } = self.config;
+
+        let mut squared_average = self.history[history_blob_id].write().unwrap();
+        let mut gradient = weight_gradient.write().unwrap();
+
+        let squared_average = squared_average.as_mut_slice().unwrap();
+        for (i, value) in gradient.as_mut_slice().unwrap().iter_mut().enumerate() {
+            squared_average[i] = decay * squared_average[i] + (1f32 - decay) * *value * *value;
+            *value = rate * *value / (squared_average[i].sqrt() + epsilon);
+        }
+    }
+}
+
+impl_isolver_sgd!(RMSProp);
+
+#[derive(Debug, Copy, Clone)]
+/// Specifies configuration parameters for an RMSProp solver.
+pub struct RMSPropConfig {
+    /// The decay rate of the average of the squared gradients.
+    ///
+    /// Default: 0.99
+    pub decay: f32,
+    /// Small constant added to the denominator for numerical stability.
+    ///
+    /// Default: 1e-8
+    pub epsilon: f32,
+}
+
+impl Default for RMSPropConfig {
+    fn default() -> RMSPropConfig {
+        RMSPropConfig {
+            decay: 0.99f32,
+            epsilon: 1e-8f32,
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Provides the learning rate schedules that drive a [Solver][solver].
+//!
+//! A [LearningRateSchedule][trait] returns the global learning rate for every
+//! iteration of the Solver. The built-in [LRPolicy][policy] variants are
+//! schedules themselves, so a [SolverConfig][config] can keep describing the
+//! schedule on its own. Schedules that depend on the training progress, like
+//! [ReduceOnPlateau][plateau], can be set with [Solver::set_lr_schedule][set].
+//!
+//! [solver]: ../struct.Solver.html
+//! [trait]: ./trait.LearningRateSchedule.html
+//! [policy]: ../enum.LRPolicy.html
+//! [config]: ../struct.SolverConfig.html
+//! [plateau]: ./struct.ReduceOnPlateau.html
+//! [set]: ../struct.Solver.html#method.set_lr_schedule
+
+use super::{LRPolicy, SolverConfig};
+
+use std::fmt;
+
+/// Determines the global learning rate of a [Solver][1] for each iteration.
+/// [1]: ../struct.Solver.html
+pub trait LearningRateSchedule : fmt::Debug {
+    /// Return the learning rate for iteration `iter`.
+    ///
+    /// Called once per iteration by the Solver, with increasing `iter`.
+    fn learning_rate(&mut self, config: &SolverConfig, iter: usize) -> f32;
+
+    /// Inform the schedule about a loss, e.g. the loss on a validation set.
+    ///
+    /// Called through [Solver::observe_loss][1]. Schedules that don't depend
+    /// on the loss can ignore it, which is the default.
+    /// [1]: ../struct.Solver.html#method.observe_loss
+    fn observe_loss(&mut self, loss: f32) {}
+}
+
+impl LearningRateSchedule for LRPolicy {
+    fn learning_rate(&mut self, config: &SolverConfig, iter: usize) -> f32 {
+        config.policy_learning_rate(*self, iter)
+    }
+}
+
+/// Reduces the learning rate when the observed loss stops improving.
+///
+/// Starts with the `base_lr` of the [SolverConfig][1]. Whenever the loss reported via
+/// [observe_loss][2] did not improve by more than `threshold` for more than `patience`
+/// observations in a row, the learning rate is multiplied by `factor`, but never drops
+/// below `min_lr`. Losses can be observed before the learning rate is requested for the
+/// first time.
+/// [1]: ../struct.SolverConfig.html
+/// [2]: ./trait.LearningRateSchedule.html#method.observe_loss
+#[derive(Debug, Clone)]
+pub struct ReduceOnPlateau {
+    /// The factor the learning rate is multiplied by on a plateau.
+    pub factor: f32,
+    /// The number of observations without improvement that are tolerated.
+    pub patience: usize,
+    /// The minimal decrease of the loss that counts as an improvement.
+    pub threshold: f32,
+    /// The lower bound for the learning rate.
+    pub min_lr: f32,
+
+    // the product of all reductions so far, which is applied to the `base_lr`
+    scale: f32,
+    best_loss: f32,
+    observations_without_improvement: usize,
+}
+
+impl ReduceOnPlateau {
+    /// Create a new ReduceOnPlateau schedule.
+    pub fn new(factor: f32, patience: usize) -> ReduceOnPlateau {
+        ReduceOnPlateau {
+            factor: factor,
+            patience: patience,
+            threshold: 0f32,
+            min_lr: 0f32,
+
+            scale: 1f32,
+            best_loss: ::std::f32::INFINITY,
+            observations_without_improvement: 0,
+        }
+    }
+}
+
+impl LearningRateSchedule for ReduceOnPlateau {
+    fn learning_rate(&mut self, config: &SolverConfig, iter: usize) -> f32 {
+        (config.base_lr * self.scale).max(self.min_lr)
+    }
+
+    fn observe_loss(&mut self, loss: f32) {
+        if loss < self.best_loss - self.threshold {
+            self.best_loss = loss;
+            self.observations_without_improvement = 0;
+            return;
+        }
+
+        self.observations_without_improvement += 1;
+        if self.observations_without_improvement > self.patience {
+            self.scale = self.scale * self.factor;
+            info!("Loss did not improve for {} observations, reducing learning rate by {}",
+                  self.observations_without_improvement,
+                  self.factor);
+            self.observations_without_improvement = 0;
+        }
+    }
+}
+
+/// Reads the learning rate from a table of `(iteration, learning rate)` pairs.
+///
+/// The learning rate of an entry is used from its iteration on, until the iteration of the
+/// next entry. Before the first entry the `base_lr` of the [SolverConfig][1] is used.
+/// [1]: ../struct.SolverConfig.html
+#[derive(Debug, Clone)]
+pub struct PiecewiseConstant {
+    table: Vec<(usize, f32)>,
+}
+
+impl PiecewiseConstant {
+    /// Create a new PiecewiseConstant schedule from a table of `(iteration, learning rate)` pairs.
+    pub fn new(table: &[(usize, f32)]) -> PiecewiseConstant {
+        let mut table = table.to_vec();
+        table.sort_by_key(|&(iter, _)| iter);
+
+        PiecewiseConstant {
+            table: table,
+        }
+    }
+}
+
+impl LearningRateSchedule for PiecewiseConstant {
+    fn learning_rate(&mut self, config: &SolverConfig, iter: usize) -> f32 {
+        self.table.iter()
+            .take_while(|&&(start, _)| start <= iter)
+            .last()
+            .map_or(config.base_lr, |&(_, learning_rate)| learning_rate)
+    }
+}
@@ -4,13 +4,16 @@
 //! [solvers]: ../solvers/index.html
 
 pub mod confusion_matrix;
+pub mod lr_schedule;
 
 pub use self::confusion_matrix::ConfusionMatrix;
+pub use self::lr_schedule::{LearningRateSchedule, PiecewiseConstant, ReduceOnPlateau};
 
 use crate::layers::core::*;
 use crate::solvers::*;
 use crate::typedefs::{ArcLockTensor, LeafBackend};
 
+use std::cmp;
 use std::rc::Rc;
 
 /// Solver that optimizes a [Layer][1] with a given objective.
@@ -23,6 +26,8 @@ pub struct Solver {
     pub worker: Box<SolverWorker>,
 
     config: SolverConfig,
+    /// The schedule that determines the learning rate for every iteration.
+    lr_schedule: Box<LearningRateSchedule>,
 
     /// The current iteration / number of times weights have been updated
     iter: usize,
@@ -42,6 +47,7 @@ impl Solver {
             worker: worker,
             net: network,
             objective: Layer::from_config(obj_backend, &config.objective),
+            lr_schedule: Box::new(config.lr_policy),
             iter: 0,
 
             config: config.clone(),
@@ -72,13 +78,41 @@ impl Solver {
         let classifier_gradient = self.objective.backward(&[]);
         self.net.backward(&classifier_gradient[0 .. 1]);
 
-        self.worker.compute_update(&self.config, &mut self.net, self.iter);
+        let learning_rate = self.lr_schedule.learning_rate(&self.config, self.iter);
+        self.worker.compute_update(&self.config, &mut self.net, learning_rate);
         self.net.update_weights(self.worker.backend());
         self.iter += 1;
 
         network_out
     }
 
+    /// Replace the [learning rate schedule][1] of the solver.
+    /// [1]: ./lr_schedule/trait.LearningRateSchedule.html
+    ///
+    /// By default the solver uses the [LRPolicy][2] of its [SolverConfig][3].
+    /// [2]: ./enum.LRPolicy.html
+    /// [3]: ./struct.SolverConfig.html
+    pub fn set_lr_schedule(&mut self, lr_schedule: Box<LearningRateSchedule>) {
+        self.lr_schedule = lr_schedule;
+    }
+
+    /// Report a loss, e.g. the loss on a validation set, to the learning rate schedule.
+    ///
+    /// See [LearningRateSchedule.observe_loss][1].
+    /// [1]: ./lr_schedule/trait.LearningRateSchedule.html#method.observe_loss
+    pub fn observe_loss(&mut self, loss: f32) {
+        self.lr_schedule.observe_loss(loss);
+    }
+
+    /// Returns the factor the gradients have been scaled by during gradient clipping
+    /// in the last iteration.
+    ///
+    /// Useful for monitoring how often and how strongly clipping kicks in.
+    /// Returns `None` if gradient clipping is disabled.
+    pub fn clip_scale(&self) -> Option<f32> {
+        self.worker.clip_scale()
+    }
+
     /// Returns the network trained by the solver.
     ///
     /// This is the recommended method to get a usable trained network.
@@ -116,7 +150,19 @@ pub trait SolverWorker {
     /// Used by [step][2] to optimize the network.
     ///
     /// [2]: ./struct.Solver.html#method.step
-    fn compute_update(&mut self, param: &SolverConfig, network: &mut Layer, iter: usize);
+    ///
+    /// `learning_rate` is the global learning rate for the current iteration,
+    /// as determined by the [learning rate schedule][3] of the Solver.
+    /// [3]: ./lr_schedule/trait.LearningRateSchedule.html
+    fn compute_update(&mut self, param: &SolverConfig, network: &mut Layer, learning_rate: f32);
+
+    /// Returns the factor the gradients have been scaled by during the last gradient clipping.
+    ///
+    /// Returns `None` if gradients are not clipped, see [SolverConfig.clip_gradients][1].
+    /// [1]: ./struct.SolverConfig.html
+    fn clip_scale(&self) -> Option<f32> {
+        None
+    }
 
     /// Returns the backend used by the solver.
     fn backend(&self) -> &LeafBackend;
@@ -160,8 +206,41 @@ pub struct SolverConfig {
     pub gamma: f32,
     /// The stepsize used in Step and Sigmoid learning policies.
     ///
+    /// Also used as the length of the first cycle of the CosineAnnealing learning policy.
+    ///
     /// Default: 10
     pub stepsize: usize,
+    /// The iterations at which the learning rate is decayed in the Multistep learning policy.
+    ///
+    /// Has to be sorted in ascending order.
+    ///
+    /// Default: []
+    pub stepvalue: Vec<usize>,
+    /// The power used in Inv and Poly learning policies.
+    ///
+    /// Default: 1
+    pub power: f32,
+    /// The iteration at which the learning rate reaches zero in the Poly learning policy.
+    ///
+    /// Default: 10000
+    pub max_iter: usize,
+    /// The learning rate at the end of every cycle of the CosineAnnealing learning policy.
+    ///
+    /// Default: 0
+    pub min_lr: f32,
+    /// The factor by which every cycle of the CosineAnnealing learning policy is
+    /// longer than the previous one.
+    ///
+    /// Default: 1
+    pub restart_mult: usize,
+    /// The number of iterations during which the learning rate is linearly increased
+    /// towards the rate given by the learning policy.
+    ///
+    /// During warmup the learning rate of the policy is scaled by `(iter + 1) / warmup_iter`.
+    /// Warmup works with every learning rate policy.
+    ///
+    /// Default: 0
+    pub warmup_iter: usize,
     /// The threshold for clipping gradients.
     ///
     /// Gradient values will be scaled to their [L2 norm][1] of length `clip_gradients`
@@ -172,6 +251,13 @@ pub struct SolverConfig {
     ///
     /// Default: None
     pub clip_gradients: Option<f32>,
+    /// The way gradients are clipped when `clip_gradients` is set.
+    ///
+    /// See [ClipMode][1] for all available modes.
+    /// [1]: ./enum.ClipMode.html
+    ///
+    /// Default: ClipMode::GlobalNorm
+    pub clip_mode: ClipMode,
     /// The global [weight decay][1] multiplier for [regularization][2].
     /// [1]: http://www.alglib.net/dataanalysis/improvinggeneralization.php#header3
     /// [2]: https://cs231n.github.io/neural-networks-2/#reg
@@ -191,9 +277,6 @@ pub struct SolverConfig {
     /// See [RegularizationMethod][2] for all implemented methods.
     ///
     /// [2]: ./enum.RegularizationMethod.html
-    ///
-    /// Currently only L2 regularization is implemented.
-    /// See [Issue #23](https://github.com/autumnai/leaf/issues/23).
     pub regularization_method: Option<RegularizationMethod>,
     /// The [momentum][1] multiplier for [SGD solvers][2].
     /// [1]: https://en.wikipedia.org/wiki/Stochastic_gradient_descent#Momentum
@@ -223,8 +306,15 @@ impl Default for SolverConfig {
             base_lr: 0.01f32,
             gamma: 0.1f32,
             stepsize: 10,
+            stepvalue: Vec::new(),
+            power: 1f32,
+            max_iter: 10_000,
+            min_lr: 0f32,
+            restart_mult: 1,
+            warmup_iter: 0,
 
             clip_gradients: None,
+            clip_mode: ClipMode::GlobalNorm,
 
             weight_decay: None,
             regularization_method: None,
@@ -248,7 +338,14 @@ impl SolverConfig {
     /// [2]: ./struct.Solver.html
     /// [3]: ../solvers/index.html
     pub fn get_learning_rate(&self, iter: usize) -> f32 {
-        match self.lr_policy() {
+        self.policy_learning_rate(self.lr_policy(), iter)
+    }
+
+    /// Return the learning rate of the supplied [LRPolicy][1] for iteration `iter`,
+    /// using the hyperparameters of this config.
+    /// [1]: ./enum.LRPolicy.html
+    fn policy_learning_rate(&self, lr_policy: LRPolicy, iter: usize) -> f32 {
+        let rate = match lr_policy {
             LRPolicy::Fixed => {
                 self.base_lr()
             }
@@ -256,40 +353,52 @@ impl SolverConfig {
                 let current_step = self.step(iter);
                 self.base_lr() * self.gamma().powf(current_step as f32)
             }
-            // LRPolicy::Multistep => {
-            //     // TODO: the current step can be calculated on-demand
-            //     //   if (this->current_step_ < this->param_.stepvalue_size() &&
-            //     //         this->iter_ >= this->param_.stepvalue(this->current_step_)) {
-            //     //     this->current_step_++;
-            //     //     LOG(INFO) << "MultiStep Status: Iteration " <<
-            //     //     this->iter_ << ", step = " << this->current_step_;
-            //     //   }
-            //     //   rate = this->param_.base_lr() *
-            //     //       pow(this->param_.gamma(), this->current_step_);
-            //     unimplemented!();
-            // }
+            LRPolicy::Multistep => {
+                let current_step = self.stepvalue.iter().take_while(|&&stepvalue| stepvalue <= iter).count();
+                self.base_lr() * self.gamma().powf(current_step as f32)
+            }
             LRPolicy::Exp => {
                 self.base_lr() * self.gamma().powf(iter as f32)
             }
-            // LRPolicy::Inv => {
-            //     //   rate = this->param_.base_lr() *
-            //     //       pow(Dtype(1) + this->param_.gamma() * this->iter_,
-            //     //           - this->param_.power());
-            //     unimplemented!();
-            // }
-            // LRPolicy::Poly => {
-            //     //   rate = this->param_.base_lr() * pow(Dtype(1.) -
-            //     //       (Dtype(this->iter_) / Dtype(this->param_.max_iter())),
-            //     //       this->param_.power());
-            //     unimplemented!();
-            // }
-            // LRPolicy::Sigmoid => {
-            //     //   rate = this->param_.base_lr() * (Dtype(1.) /
-            //     //       (Dtype(1.) + exp(-this->param_.gamma() * (Dtype(this->iter_) -
-            //     //         Dtype(this->param_.stepsize())))));
-            //     unimplemented!();
-            // }
+            LRPolicy::Inv => {
+                self.base_lr() * (1f32 + self.gamma() * iter as f32).powf(-self.power)
+            }
+            LRPolicy::Poly => {
+                let progress = (iter as f32 / self.max_iter as f32).min(1f32);
+                self.base_lr() * (1f32 - progress).powf(self.power)
+            }
+            LRPolicy::Sigmoid => {
+                let exponent = -self.gamma() * (iter as f32 - self.stepsize() as f32);
+                self.base_lr() * (1f32 / (1f32 + exponent.exp()))
+            }
+            LRPolicy::CosineAnnealing => {
+                let (cycle_iter, cycle_length) = self.cosine_cycle(iter);
+                let cosine = (::std::f32::consts::PI * cycle_iter as f32 / cycle_length as f32).cos();
+                self.min_lr + (self.base_lr() - self.min_lr) * (1f32 + cosine) / 2f32
+            }
+        };
+
+        if iter < self.warmup_iter {
+            rate * (iter + 1) as f32 / self.warmup_iter as f32
+        } else {
+            rate
+        }
+    }
+
+    /// Return the iteration inside the current cycle and the length of the current cycle
+    /// for the CosineAnnealing learning policy at iteration `iter`.
+    ///
+    /// Panics if the `stepsize` is `0`, since the cycles would never advance.
+    fn cosine_cycle(&self, iter: usize) -> (usize, usize) {
+        assert!(self.stepsize() > 0, "The CosineAnnealing learning policy requires a stepsize greater than 0.");
+        let mut cycle_iter = iter;
+        let mut cycle_length = self.stepsize();
+        while cycle_iter >= cycle_length {
+            cycle_iter -= cycle_length;
+            // a cycle that is longer than any iteration count is the last one
+            cycle_length = cycle_length.checked_mul(cmp::max(self.restart_mult, 1)).unwrap_or(usize::max_value());
         }
+        (cycle_iter, cycle_length)
     }
 
     /// Return current step at iteration `iter`.
@@ -327,6 +436,18 @@ pub enum SolverKind {
     /// See [SGDKind][1] for all available SGD solvers.
     /// [1]: ./enum.SGDKind.html
     SGD(SGDKind),
+    /// Adam, see [implementation][2]
+    /// [2]: ../solvers/adaptive/adam/index.html
+    Adam(AdamConfig),
+    /// AdaGrad, see [implementation][3]
+    /// [3]: ../solvers/adaptive/ada_grad/index.html
+    AdaGrad(AdaGradConfig),
+    /// RMSProp, see [implementation][4]
+    /// [4]: ../solvers/adaptive/rms_prop/index.html
+    RMSProp(RMSPropConfig),
+    /// AdaDelta, see [implementation][5]
+    /// [5]: ../solvers/adaptive/ada_delta/index.html
+    AdaDelta(AdaDeltaConfig),
 }
 
 impl SolverKind {
@@ -336,6 +457,18 @@ impl SolverKind {
             SolverKind::SGD(sgd) => {
                 sgd.with_config(backend, config)
             }
+            SolverKind::Adam(adam_config) => {
+                Box::new(Adam::new(backend, adam_config))
+            }
+            SolverKind::AdaGrad(ada_grad_config) => {
+                Box::new(AdaGrad::new(backend, ada_grad_config))
+            }
+            SolverKind::RMSProp(rms_prop_config) => {
+                Box::new(RMSProp::new(backend, rms_prop_config))
+            }
+            SolverKind::AdaDelta(ada_delta_config) => {
+                Box::new(AdaDelta::new(backend, ada_delta_config))
+            }
         }
     }
 }
@@ -346,6 +479,9 @@ pub enum SGDKind {
     /// Stochastic Gradient Descent with Momentum. See [implementation][1]
     /// [1] ../solvers/
     Momentum,
+    /// Stochastic Gradient Descent with Nesterov Momentum. See [implementation][2]
+    /// [2] ../solvers/sgd/nesterov/index.html
+    Nesterov,
 }
 
 impl SGDKind {
@@ -355,6 +491,9 @@ impl SGDKind {
             SGDKind::Momentum => {
                 Box::new(Momentum::new(backend))
             }
+            SGDKind::Nesterov => {
+                Box::new(Nesterov::new(backend))
+            }
         }
     }
 }
@@ -374,20 +513,42 @@ pub enum LRPolicy {
     /// learning rate decays every `step` iterations.
     /// return base_lr * gamma ^ (floor(iter / step))
     Step,
-    // /// similar to step but it allows non uniform steps defined by
-    // /// stepvalue
-    // Multistep,
+    /// similar to step but it allows non uniform steps defined by
+    /// stepvalue
+    Multistep,
     /// return base_lr * gamma ^ iter
     Exp,
-    // /// return base_lr * (1 + gamma * iter) ^ (- power)
-    // Inv,
-    // /// the effective learning rate follows a polynomial decay, to be
-    // /// zero by the max_iter.
-    // /// return base_lr (1 - iter/max_iter) ^ (power)
-    // Poly,
-    // /// the effective learning rate follows a sigmod decay
-    // /// return base_lr ( 1/(1 + exp(-gamma * (iter - stepsize))))
-    // Sigmoid,
+    /// return base_lr * (1 + gamma * iter) ^ (- power)
+    Inv,
+    /// the effective learning rate follows a polynomial decay, to be
+    /// zero by the max_iter.
+    /// return base_lr (1 - iter/max_iter) ^ (power)
+    Poly,
+    /// the effective learning rate follows a sigmod decay
+    /// return base_lr ( 1/(1 + exp(-gamma * (iter - stepsize))))
+    Sigmoid,
+    /// the effective learning rate follows a cosine from base_lr down to min_lr
+    /// and restarts at base_lr at the end of every cycle ([SGDR][3]).
+    /// The first cycle is stepsize iterations long, every following cycle
+    /// restart_mult times longer than the previous one.
+    /// return min_lr + (base_lr - min_lr) * (1 + cos(pi * cycle_iter / cycle_length)) / 2
+    /// [3]: https://arxiv.org/abs/1608.03983
+    CosineAnnealing,
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Gradient clipping mode for a [Solver][1].
+/// [1]: ./struct.Solver.html
+///
+/// All modes use [SolverConfig.clip_gradients][2] as threshold.
+/// [2]: ./struct.SolverConfig.html
+pub enum ClipMode {
+    /// Rescale all gradients together when the L2 norm over all of them exceeds the threshold.
+    GlobalNorm,
+    /// Rescale the gradient of each weight separately when its L2 norm exceeds the threshold.
+    PerWeightNorm,
+    /// Clip every single gradient value to the range `[-threshold, threshold]`.
+    Value,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -396,5 +557,20 @@ pub enum LRPolicy {
 /// [2]: ./struct.Solver.html
 pub enum RegularizationMethod {
     /// L2 regularization
+    ///
+    /// Adds `decay * w` to the gradient, pulling weights towards zero
+    /// proportionally to their magnitude.
     L2,
+    /// L1 regularization
+    ///
+    /// Adds `decay * sign(w)` to the gradient, which drives small weights to
+    /// exactly zero and leads to sparse weights.
+    L1,
+    /// ElasticNet regularization, a weighted combination of L1 and L2 regularization.
+    ///
+    /// Adds `decay * (l1_ratio * sign(w) + (1 - l1_ratio) * w)` to the gradient.
+    ElasticNet {
+        /// The share of L1 regularization, between 0 (pure L2) and 1 (pure L1).
+        l1_ratio: f32,
+    },
 }
@@ -29,17 +29,28 @@
 
 #[allow(unused_import_braces)]
 pub use crate::solvers::core::*;
-pub use self::sgd::Momentum;
+pub use self::sgd::{Momentum, Nesterov};
+pub use self::adaptive::{AdaDelta, AdaDeltaConfig, AdaGrad, AdaGradConfig, Adam, AdamConfig, RMSProp, RMSPropConfig};
 pub mod core;
+#[macro_use]
 pub mod sgd;
+pub mod adaptive;
 
 use crate::layers::core::*;
 use crate::layers::SequentialConfig;
-use crate::typedefs::ArcLockTensor;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
 
 use parenchyma::prelude::SharedTensor;
 
 trait SGDSolver : SolverWorker {
+    /// Returns the number of history tensors the solver keeps for each weight.
+    ///
+    /// The history tensors of the weight with id `weight_id` can be found at
+    /// `weight_id * history_size()` up to (excluding) `(weight_id + 1) * history_size()`.
+    fn history_size(&self) -> usize {
+        1
+    }
+
     fn compute_update_value(&mut self,
                             config: &SolverConfig,
                             weight_blob: &ArcLockTensor,
@@ -57,41 +68,90 @@ trait SGDSolver : SolverWorker {
     /// to that threshold. The naming can be misleading since the gradients are not
     /// actually clipped (as in cut off), but rescaled to the threshold.
     ///
+    /// How the norm is measured is determined by [SolverConfig.clip_mode][5].
+    ///
+    /// Returns the factor the gradients have been scaled by, or `None` if clipping is disabled.
+    /// For [ClipMode::PerWeightNorm][5] this is the smallest factor applied to any weight,
+    /// for [ClipMode::Value][5] it is the ratio between the L2 norms of the gradients after and
+    /// before clipping.
+    ///
     /// [3]: https://en.wikipedia.org/wiki/Recurrent_neural_network
     /// [4]: https://en.wikipedia.org/wiki/Norm_(mathematics)#Euclidean_norm
-    #[allow(unused_must_use)]
-    fn clip_gradients(&self, config: &SolverConfig, net: &mut Layer) {
+    /// [5]: ../solver/enum.ClipMode.html
+    fn clip_gradients(&self, config: &SolverConfig, net: &mut Layer) -> Option<f32> {
         // skip clipping gradients if SolverConfig.clip_gradients is set to None
-        if let Some(clip_threshold) = config.clip_gradients {
-
-            let net_gradients = net.learnable_weights_gradients();
-            let mut sumsq_diff = 0f32;
-            let backend = self.backend();
-            for net_gradient in net_gradients.clone() {
-                let gradient = net_gradient.read().unwrap();
-                let mut result = SharedTensor::<f32>::from(1);
-                // gradient.sumsq_diff(self.backend(), &mut result);
-                self.backend().dot(&gradient, &gradient, &mut result);
-
-                let mut result = SharedTensor::<f32>::from(1);
-                let sumsq_diff_slice = result.as_slice().unwrap();
-                sumsq_diff += sumsq_diff_slice[0];
-            }
-            let l2norm_diff = sumsq_diff.sqrt();
-            if l2norm_diff > clip_threshold {
+        let clip_threshold = match config.clip_gradients {
+            Some(clip_threshold) => clip_threshold,
+            None => return None,
+        };
+
+        let net_gradients = net.learnable_weights_gradients();
+        let backend = self.backend();
+        match config.clip_mode {
+            ClipMode::GlobalNorm => {
+                let mut sumsq_diff = 0f32;
+                for net_gradient in &net_gradients {
+                    sumsq_diff += sum_of_squares(backend, &net_gradient.read().unwrap());
+                }
+                let l2norm_diff = sumsq_diff.sqrt();
+                if l2norm_diff <= clip_threshold {
+                    return Some(1f32);
+                }
+
                 let scale_factor = clip_threshold / l2norm_diff;
-                info!("Gradient clipping: scaling down gradients (L2 norm {} > {})
-                        by scale factor {}",
+                info!("Gradient clipping: scaling down gradients (L2 norm {} > {}) by scale factor {}",
                       l2norm_diff,
                       clip_threshold,
                       scale_factor);
 
                 let mut scale_shared = SharedTensor::scalar(scale_factor);
+                for net_gradient in &net_gradients {
+                    backend.scal(&mut scale_shared, &mut net_gradient.write().unwrap()).unwrap();
+                }
+                Some(scale_factor)
+            }
+            ClipMode::PerWeightNorm => {
+                let mut min_scale_factor = 1f32;
+                for (weight_id, net_gradient) in net_gradients.iter().enumerate() {
+                    let mut gradient = net_gradient.write().unwrap();
+                    let l2norm_diff = sum_of_squares(backend, &gradient).sqrt();
+                    if l2norm_diff > clip_threshold {
+                        let scale_factor = clip_threshold / l2norm_diff;
+                        debug!("Gradient clipping: scaling down gradient of weight {} (L2 norm {} > {}) by scale factor {}",
+                               weight_id,
+                               l2norm_diff,
+                               clip_threshold,
+                               scale_factor);
 
-                for weight_gradient in net_gradients {
-                    let mut gradient = weight_gradient.write().unwrap();
-                    backend.scal(&mut scale_shared, &mut gradient);
+                        let mut scale_shared = SharedTensor::scalar(scale_factor);
+                        backend.scal(&mut scale_shared, &mut gradient).unwrap();
+                        min_scale_factor = min_scale_factor.min(scale_factor);
+                    }
+                }
+                if min_scale_factor < 1f32 {
+                    info!("Gradient clipping: smallest per-weight scale factor {}", min_scale_factor);
                 }
+                Some(min_scale_factor)
+            }
+            ClipMode::Value => {
+                let mut sumsq_before = 0f32;
+                let mut sumsq_after = 0f32;
+                for net_gradient in &net_gradients {
+                    let mut gradient = net_gradient.write().unwrap();
+                    for value in gradient.as_mut_slice().unwrap().iter_mut() {
+                        sumsq_before += *value * *value;
+                        *value = value.max(-clip_threshold).min(clip_threshold);
+                        sumsq_after += *value * *value;
+                    }
+                }
+                let scale_factor = if sumsq_before > 0f32 { (sumsq_after / sumsq_before).sqrt() } else { 1f32 };
+                if scale_factor < 1f32 {
+                    info!("Gradient clipping: clipped gradient values to [-{}, {}], L2 norm scaled by {}",
+                          clip_threshold,
+                          clip_threshold,
+                          scale_factor);
+                }
+                Some(scale_factor)
             }
         }
     }
@@ -116,28 +176,62 @@ trait SGDSolver : SolverWorker {
     /// [Regularize][1] the gradient according to the configured [RegularizationMethod][2].
     /// [1]: https://cs231n.github.io/neural-networks-2/#reg
     /// [2]: ../solver/enum.RegularizationMethod.html
-    fn regularize(&self, config: &SolverConfig, weight_gradient: &ArcLockTensor, blob_weight_decay: Option<f32>) {
+    ///
+    /// The global [SolverConfig.weight_decay][3] is scaled by the `decay_mult` of the weight.
+    /// A weight without a `decay_mult` uses the default multiplier of `1.0`.
+    /// [3]: ../solver/struct.SolverConfig.html
+    fn regularize(&self,
+                  config: &SolverConfig,
+                  weight_data: &ArcLockTensor,
+                  weight_gradient: &ArcLockTensor,
+                  blob_weight_decay: Option<f32>) {
         if let Some(global_weight_decay) = config.weight_decay {
             if let Some(regularization_method) = config.regularization_method {
-                match blob_weight_decay {
-                    Some(weight_decay_mult) => {
-                        let local_decay = global_weight_decay * weight_decay_mult;
-                        match regularization_method {
-                            RegularizationMethod::L2 => {
-                                let decay_shared = SharedTensor::scalar(local_decay);
-                                let gradient = &mut weight_gradient.write().unwrap();
-                                // gradient.regularize_l2(self.backend(), &decay_shared);
-                                // backend.axpy_plain(&decay_shared, &self.data, &mut self.diff).unwrap();
-                                // TODO: solver
-                                unimplemented!();
-                            }
-                        }
+                let local_decay = global_weight_decay * blob_weight_decay.unwrap_or(1f32);
+                if local_decay == 0f32 {
+                    return;
+                }
+
+                let backend = self.backend();
+                let weight = weight_data.read().unwrap();
+                let mut gradient = weight_gradient.write().unwrap();
+                match regularization_method {
+                    RegularizationMethod::L2 => {
+                        let decay_shared = SharedTensor::scalar(local_decay);
+                        backend.axpy(&decay_shared, &weight, &mut gradient).unwrap();
                     }
-                    None => {
-                        error!("Weight decay multiplier for gradient missing.");
+                    RegularizationMethod::L1 => {
+                        let decay_shared = SharedTensor::scalar(local_decay);
+                        backend.axpy(&decay_shared, &sign(&weight), &mut gradient).unwrap();
+                    }
+                    RegularizationMethod::ElasticNet { l1_ratio } => {
+                        let l1_decay_shared = SharedTensor::scalar(local_decay * l1_ratio);
+                        let l2_decay_shared = SharedTensor::scalar(local_decay * (1f32 - l1_ratio));
+                        backend.axpy(&l1_decay_shared, &sign(&weight), &mut gradient).unwrap();
+                        backend.axpy(&l2_decay_shared, &weight, &mut gradient).unwrap();
                     }
                 }
             }
         }
     }
 }
+
+/// Returns the sum of the squared values of `tensor`.
+fn sum_of_squares(backend: &LeafBackend, tensor: &SharedTensor<f32>) -> f32 {
+    let mut result = SharedTensor::<f32>::from(1);
+    backend.dot(tensor, tensor, &mut result).unwrap();
+    result.as_slice().unwrap()[0]
+}
+
+/// Returns a tensor holding the sign (`-1`, `0` or `1`) of every value in `tensor`.
+///
+/// Used as the subgradient of the L1 norm.
+fn sign(tensor: &SharedTensor<f32>) -> SharedTensor<f32> {
+    let signs = tensor.as_slice().unwrap().iter().map(|&value| {
+        if value > 0f32 { 1f32 } else if value < 0f32 { -1f32 } else { 0f32 }
+    }).collect::<Vec<_>>();
+
+    let mut sign = SharedTensor::from(tensor.shape().clone());
+    sign.write_slice(&signs[..]).unwrap();
+    sign
+}
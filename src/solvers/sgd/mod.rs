@@ -25,29 +25,40 @@
 macro_rules! impl_isolver_sgd {
     ($t:ty) => (
         impl SolverWorker for $t {
-            /// Initialize the SGD Momentum solver, allocating memory for its history.
+            /// Initialize the SGD solver, allocating memory for its history.
+            ///
+            /// Allocates [history_size][1] history tensors per weight, the history tensors
+            /// of a weight are stored next to each other.
+            /// [1]: ./solvers/trait.SGDSolver.html#method.history_size
             fn init(&mut self, net: &Layer) {
-                self.history = Vec::with_capacity(net.learnable_weights_gradients().len());
+                let history_size = SGDSolver::history_size(self);
+                self.history = Vec::with_capacity(net.learnable_weights_gradients().len() * history_size);
 
                 for weight_gradient in net.learnable_weights_gradients() {
-                    let shape = weight_gradient.read().unwrap().shape().clone();
-                    let mut tensor = SharedTensor::from(shape);
+                    for _ in 0..history_size {
+                        let shape = weight_gradient.read().unwrap().shape().clone();
+                        let mut tensor = SharedTensor::from(shape);
 
-                    let filler = ::weight::FillerType::Constant { value: 0f32 };
-                    filler.fill(&mut tensor);
+                        let filler = ::weight::FillerType::Constant { value: 0f32 };
+                        filler.fill(&mut tensor);
 
-                    let history_tensor = Arc::new(RwLock::new(tensor));
-                    self.history.push(history_tensor);
+                        let history_tensor = Arc::new(RwLock::new(tensor));
+                        self.history.push(history_tensor);
+                    }
                 }
             }
 
-            fn compute_update(&mut self, config: &SolverConfig, net: &mut Layer, iter: usize) {
-                let rate = config.get_learning_rate(iter);
+            fn compute_update(&mut self, config: &SolverConfig, net: &mut Layer, rate: f32) {
 
-                SGDSolver::clip_gradients(self, config, net);
+                self.clip_scale = SGDSolver::clip_gradients(self, config, net);
+                let weights_data = net.learnable_weights_data();
+                let weights_weight_decay = net.learnable_weights_weight_decay();
                 for (weight_id, weight_gradient) in net.learnable_weights_gradients().iter().enumerate() {
                     SGDSolver::normalize(self, config, weight_gradient);
-                    // SGDSolver::<SolverB, NetB>::regularize(self, config, weight_gradient, net.weights_weight_decay()[weight_id]);
+                    SGDSolver::regularize(self, config,
+                                          &weights_data[weight_id],
+                                          weight_gradient,
+                                          weights_weight_decay.get(weight_id).cloned().unwrap_or(None));
 
                     SGDSolver::compute_update_value(self, config,
                                               weight_gradient,
@@ -57,6 +68,10 @@ macro_rules! impl_isolver_sgd {
                 }
             }
 
+            fn clip_scale(&self) -> Option<f32> {
+                self.clip_scale
+            }
+
             fn backend(&self) -> &LeafBackend {
                 &self.backend
             }
@@ -65,5 +80,7 @@ macro_rules! impl_isolver_sgd {
 }
 
 pub use self::momentum::Momentum;
+pub use self::nesterov::Nesterov;
 
 pub mod momentum;
+pub mod nesterov;
@@ -32,6 +32,8 @@ pub struct Momentum {
     history: Vec<ArcLockTensor>,
     /// The backend used for computing the gradient.
     backend: Rc<LeafBackend>,
+    /// The factor the gradients were scaled by during the last gradient clipping.
+    clip_scale: Option<f32>,
 
     /// Scalar that temporarily holds learing rate for weight update computations
     lr: SharedTensor<f32>,
@@ -55,6 +57,7 @@ impl Momentum {
         Momentum {
             history: Vec::new(),
             backend: backend,
+            clip_scale: None,
 
             lr: lr,
             momentum: momentum,
@@ -0,0 +1,123 @@
+//! A [Stochastic Gradient Descent with Nesterov Momentum][1]
+//! [1]: http://www.cs.toronto.edu/~fritz/absps/momentum.pdf
+//!
+//! Nesterov momentum is a variant of [SGD with Momentum][2] that evaluates
+//! the gradient at a "look-ahead" position.
+//! Instead of applying the momentum after the gradient step, it first makes
+//! the step dictated by the momentum and then corrects it with the gradient,
+//! which makes it react faster when the direction of the gradient changes.
+//!
+//! As in Caffe, the look-ahead is folded into the update so the gradient can
+//! still be computed at the current weights:
+//!
+//! ```text
+//! history = momentum * history + lr * gradient
+//! update  = (1 + momentum) * history - momentum * previous_history
+//! ```
+//!
+//! [2]: ../momentum/index.html
+
+use crate::layers::core::*;
+use crate::solvers::core::*;
+use crate::solvers::SGDSolver;
+use crate::typedefs::{ArcLockTensor, LeafBackend};
+
+use parenchyma::prelude::SharedTensor;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+/// Stochastic Gradient Descent with Nesterov Momentum.
+///
+/// See [module description][1] for more information.
+/// [1]: ./index.html
+#[derive(Debug)]
+pub struct Nesterov {
+    /// The gradient update from the previous iteration for each blob.
+    history: Vec<ArcLockTensor>,
+    /// The backend used for computing the gradient.
+    backend: Rc<LeafBackend>,
+    /// The factor the gradients were scaled by during the last gradient clipping.
+    clip_scale: Option<f32>,
+
+    /// Scalar that temporarily holds learing rate for weight update computations
+    lr: SharedTensor<f32>,
+    /// Scalar that temporarily holds momentum for weight update computations
+    momentum: SharedTensor<f32>,
+}
+
+impl Nesterov {
+    /// Create a new SGD Nesterov solver.
+    ///
+    /// Should not be called directly.
+    /// Use [Solver::from_config][2] instead.
+    ///
+    /// [2]: ../../../solver/struct.Solver.html#method.from_config
+    pub fn new(backend: Rc<LeafBackend>) -> Nesterov {
+        let (lr, momentum) = {
+            (SharedTensor::<f32>::from(1),
+             SharedTensor::<f32>::from(1))
+        };
+
+        Nesterov {
+            history: Vec::new(),
+            backend: backend,
+            clip_scale: None,
+
+            lr: lr,
+            momentum: momentum,
+        }
+    }
+
+}
+
+impl SGDSolver for Nesterov {
+    fn compute_update_value(&mut self,
+                            config: &SolverConfig,
+                            weight_gradient: &ArcLockTensor,
+                            history_blob_id: usize,
+                            global_lr: &f32,
+                            blob_lr: &f32) {
+        ::weight::FillerType::Constant {
+            value: global_lr * blob_lr
+        }.fill(&mut self.lr);
+
+        ::weight::FillerType::Constant {
+            value: config.momentum
+        }.fill(&mut self.momentum);
+
+        let backend = SolverWorker::backend(self);
+
+        let history_blob = &self.history[history_blob_id];
+
+        // keep the history of the previous iteration for the look-ahead correction
+        let mut previous_history = SharedTensor::<f32>::from(history_blob.read().unwrap().shape().clone());
+        backend.copy(
+            &history_blob.read().unwrap(),
+            &mut previous_history
+        ).unwrap();
+
+        backend.axpby(
+            &self.lr,
+            &weight_gradient.read().unwrap(),
+            &self.momentum,
+            &mut history_blob.write().unwrap()
+        ).unwrap();
+
+        // update = (1 + momentum) * history - momentum * previous_history
+        let look_ahead = SharedTensor::scalar(1f32 + config.momentum);
+        let correction = SharedTensor::scalar(-config.momentum);
+        backend.axpby(
+            &look_ahead,
+            &history_blob.read().unwrap(),
+            &correction,
+            &mut previous_history
+        ).unwrap();
+
+        backend.copy(
+            &previous_history,
+            &mut weight_gradient.write().unwrap()
+        ).unwrap();
+    }
+}
+
+impl_isolver_sgd!(Nesterov);
@@ -2,11 +2,13 @@
 
 use crate::cerealization_protocol::*;
 use crate::cerealization_protocol::weight_config as capnp_config;
+use crate::cerealization_protocol::weight_config::filler as capnp_filler;
+use crate::cerealization_protocol::DimCheckMode as CapnpDimCheckMode;
 use parenchyma::prelude::SharedTensor;
 use rand;
-use rand::distributions::{Distribution, Range};
+use rand::distributions::{Distribution, Normal, Range};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 /// Specifies training configuration for a weight blob.
 pub struct WeightConfig {
     /// The name of the weight blob -- useful for sharing weights among
@@ -33,6 +35,8 @@ pub struct WeightConfig {
 
     /// The filler that initializes the weights in the weight blob.
     ///
+    /// If `None`, the layer initializes the weights with its own default filler.
+    ///
     /// Default: None
     pub filler: Option<FillerType>,
 }
@@ -117,8 +121,19 @@ impl<'a> CapnpWrite<'a> for WeightConfig {
 
     /// Write the WeightConfig into a capnp message.
     fn write_capnp(&self, builder: &mut Self::Builder) {
-        // TODO: incomplete since WeightConfig isn't really used internally in Leaf at the moment.
         builder.borrow().set_name(&self.name);
+        builder.borrow().set_share_mode(self.share_mode.to_capnp());
+        builder.borrow().set_has_lr_mult(self.lr_mult.is_some());
+        builder.borrow().set_lr_mult(self.lr_mult.unwrap_or(1f32));
+        builder.borrow().set_has_decay_mult(self.decay_mult.is_some());
+        builder.borrow().set_decay_mult(self.decay_mult.unwrap_or(1f32));
+        {
+            let mut filler = builder.borrow().init_filler();
+            match self.filler {
+                Some(ref filler_type) => filler_type.write_capnp(&mut filler),
+                None => filler.set_none(()),
+            }
+        }
     }
 }
 
@@ -126,16 +141,33 @@ impl<'a> CapnpRead<'a> for WeightConfig {
     type Reader = capnp_config::Reader<'a>;
 
     fn read_capnp(reader: Self::Reader) -> Self {
-        // TODO: incomplete since WeightConfig isn't really used internally in Leaf at the moment.
         let name = reader.get_name().unwrap().to_owned();
+        let lr_mult = if reader.get_has_lr_mult() {
+            Some(reader.get_lr_mult())
+        } else {
+            None
+        };
+        let decay_mult = if reader.get_has_decay_mult() {
+            Some(reader.get_decay_mult())
+        } else {
+            None
+        };
+        let filler = match reader.get_filler().which().unwrap() {
+            capnp_filler::Which::None(()) => None,
+            _ => Some(FillerType::read_capnp(reader.get_filler())),
+        };
+
         WeightConfig {
             name: name,
-            ..Self::default()
+            share_mode: DimCheckMode::from_capnp(reader.get_share_mode().unwrap()),
+            lr_mult: lr_mult,
+            decay_mult: decay_mult,
+            filler: filler,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 /// Enum for specifing the shared weights behaviour
 pub enum DimCheckMode {
     /// Strict requires that shapes match.
@@ -144,8 +176,31 @@ pub enum DimCheckMode {
     Permissive,
 }
 
-#[derive(Debug, Copy, Clone)]
+impl DimCheckMode {
+    /// Return the corresponding Cap'n Proto value.
+    fn to_capnp(&self) -> CapnpDimCheckMode {
+        match *self {
+            DimCheckMode::Strict => CapnpDimCheckMode::Strict,
+            DimCheckMode::Permissive => CapnpDimCheckMode::Permissive,
+        }
+    }
+
+    /// Return the enum value for a Cap'n Proto value.
+    fn from_capnp(value: CapnpDimCheckMode) -> Self {
+        match value {
+            CapnpDimCheckMode::Strict => DimCheckMode::Strict,
+            CapnpDimCheckMode::Permissive => DimCheckMode::Permissive,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 /// Enum for specifing the type of Filler.
+///
+/// The fillers that scale with the size of the weight blob read the number of inputs
+/// (`fan_in`) from its shape. The first dimension is the number of outputs and all
+/// other dimensions make up the inputs, e.g. `[output_size, input_size]` for a Linear layer or
+/// `[num_output, channels, height, width]` for a Convolution layer.
 pub enum FillerType {
     /// Fills the weight blob with a constant `value` (all values are the same).
     Constant {
@@ -163,6 +218,45 @@ pub enum FillerType {
         /// Number of output nodes for each input.
         output_size: usize,
     },
+    /// Fills the weight blob uniformly from `[-sqrt(6 / fan_in), sqrt(6 / fan_in)]` based on the paper:
+    ///
+    /// `[He et al. 2015]: Delving Deep into Rectifiers: Surpassing Human-Level Performance on ImageNet Classification.`
+    ///
+    /// Also known as Kaiming filler. Suited for layers followed by a ReLU.
+    HeUniform,
+    /// Fills the weight blob from a normal distribution with a standard deviation of `sqrt(2 / fan_in)`.
+    ///
+    /// The normal variant of the [He filler](#variant.HeUniform).
+    HeNormal,
+    /// Fills the weight blob from a normal distribution with a standard deviation of `sqrt(1 / fan_in)`
+    /// based on the paper:
+    ///
+    /// `[LeCun et al. 1998]: Efficient BackProp.`
+    LeCun,
+    /// Fills the weight blob from a normal distribution whose values are redrawn
+    /// until they lie within two standard deviations of the mean.
+    TruncatedGaussian {
+        /// The mean of the normal distribution.
+        mean: f32,
+        /// The standard deviation of the normal distribution.
+        std: f32,
+    },
+    /// Fills the weight blob with a (semi-)orthogonal matrix based on the paper:
+    ///
+    /// `[Saxe et al. 2013]: Exact solutions to the nonlinear dynamics of learning in deep linear neural networks.`
+    ///
+    /// The weight blob is treated as matrix of `outputs x fan_in`, whose rows or columns
+    /// (whichever are fewer) are orthonormal.
+    Orthogonal {
+        /// The factor the orthogonal matrix is scaled by.
+        gain: f32,
+    },
+    /// Fills the weight blob so that the layer passes its inputs through unchanged.
+    ///
+    /// For a weight of shape `[outputs, inputs, ...]` the value at the center of the spatial
+    /// dimensions is `1` where the output matches the input; all other values are `0`.
+    /// Blobs with a single dimension, like scales, are filled with `1`.
+    Identity,
 }
 
 impl FillerType {
@@ -178,6 +272,43 @@ impl FillerType {
             FillerType::Glorot { input_size, output_size } => {
                 Self::fill_glorot(weight, input_size, output_size)
             }
+
+            FillerType::HeUniform => {
+                let fan_in = Self::fan_in(weight);
+                Self::fill_uniform(weight, (6f32 / fan_in as f32).sqrt())
+            }
+
+            FillerType::HeNormal => {
+                let fan_in = Self::fan_in(weight);
+                Self::fill_gaussian(weight, 0f32, (2f32 / fan_in as f32).sqrt())
+            }
+
+            FillerType::LeCun => {
+                let fan_in = Self::fan_in(weight);
+                Self::fill_gaussian(weight, 0f32, (1f32 / fan_in as f32).sqrt())
+            }
+
+            FillerType::TruncatedGaussian { mean, std } => {
+                Self::fill_truncated_gaussian(weight, mean, std)
+            }
+
+            FillerType::Orthogonal { gain } => {
+                Self::fill_orthogonal(weight, gain)
+            }
+
+            FillerType::Identity => {
+                Self::fill_identity(weight)
+            }
+        }
+    }
+
+    /// The number of inputs for each output of a weight blob.
+    fn fan_in(weight: &SharedTensor<f32>) -> usize {
+        let dims = weight.shape().dimensions();
+        match dims.len() {
+            0 => 1,
+            1 => dims[0],
+            _ => dims.iter().skip(1).fold(1, |prod, i| prod * i),
         }
     }
 
@@ -192,12 +323,232 @@ impl FillerType {
 
     /// Directly use the [Glorot Filler](#variant.Glorot).
     pub fn fill_glorot(weight: &mut SharedTensor<f32>, num_inputs: usize, num_outputs: usize) {
-        let weight_data = weight.as_mut_slice().unwrap();
         let init_range = (6.0f32 / (num_inputs as f32 + num_outputs as f32)).sqrt();
+        Self::fill_uniform(weight, init_range)
+    }
+
+    /// Fills the weight blob uniformly from `[-init_range, init_range]`.
+    pub fn fill_uniform(weight: &mut SharedTensor<f32>, init_range: f32) {
+        let weight_data = weight.as_mut_slice().unwrap();
         let between = Range::new(-init_range, init_range);
         let mut rng = rand::thread_rng();
         for e in weight_data {
             *e = between.sample(&mut rng);
         }
     }
+
+    /// Fills the weight blob from a normal distribution.
+    pub fn fill_gaussian(weight: &mut SharedTensor<f32>, mean: f32, std: f32) {
+        let weight_data = weight.as_mut_slice().unwrap();
+        let normal = Normal::new(mean as f64, std as f64);
+        let mut rng = rand::thread_rng();
+        for e in weight_data {
+            *e = normal.sample(&mut rng) as f32;
+        }
+    }
+
+    /// Directly use the [TruncatedGaussian Filler](#variant.TruncatedGaussian).
+    pub fn fill_truncated_gaussian(weight: &mut SharedTensor<f32>, mean: f32, std: f32) {
+        let weight_data = weight.as_mut_slice().unwrap();
+        let normal = Normal::new(mean as f64, std as f64);
+        let mut rng = rand::thread_rng();
+        for e in weight_data {
+            let mut value = normal.sample(&mut rng);
+            while (value - mean as f64).abs() > 2f64 * std as f64 {
+                value = normal.sample(&mut rng);
+            }
+            *e = value as f32;
+        }
+    }
+
+    /// Directly use the [Orthogonal Filler](#variant.Orthogonal).
+    pub fn fill_orthogonal(weight: &mut SharedTensor<f32>, gain: f32) {
+        let fan_in = Self::fan_in(weight);
+        let weight_data = weight.as_mut_slice().unwrap();
+        let rows = weight_data.len() / fan_in;
+        let cols = fan_in;
+
+        // orthonormalize the shorter side of the matrix, i.e. as many vectors
+        // as fit into the space spanned by the longer side.
+        let (num_vectors, vector_len) = if rows <= cols { (rows, cols) } else { (cols, rows) };
+        let normal = Normal::new(0f64, 1f64);
+        let mut rng = rand::thread_rng();
+        let mut vectors: Vec<Vec<f64>> = Vec::with_capacity(num_vectors);
+        while vectors.len() < num_vectors {
+            // modified Gram-Schmidt
+            let mut vector = (0..vector_len).map(|_| normal.sample(&mut rng)).collect::<Vec<_>>();
+            for other in &vectors {
+                let dot = vector.iter().zip(other).fold(0f64, |sum, (a, b)| sum + a * b);
+                for (value, other_value) in vector.iter_mut().zip(other) {
+                    *value -= dot * other_value;
+                }
+            }
+            let norm = vector.iter().fold(0f64, |sum, value| sum + value * value).sqrt();
+            // a (nearly) linearly dependent vector is drawn again
+            if norm > 1e-6 {
+                vectors.push(vector.iter().map(|value| value / norm).collect());
+            }
+        }
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let value = if rows <= cols { vectors[row][col] } else { vectors[col][row] };
+                weight_data[row * cols + col] = gain * value as f32;
+            }
+        }
+    }
+
+    /// Directly use the [Identity Filler](#variant.Identity).
+    pub fn fill_identity(weight: &mut SharedTensor<f32>) {
+        let dims = weight.shape().dimensions().to_owned();
+        let weight_data = weight.as_mut_slice().unwrap();
+        if dims.len() < 2 {
+            for e in weight_data {
+                *e = 1f32;
+            }
+            return;
+        }
+
+        for e in weight_data.iter_mut() {
+            *e = 0f32;
+        }
+        let spatial_size = dims.iter().skip(2).fold(1, |prod, i| prod * i);
+        // index of the center within the spatial dimensions
+        let center = dims.iter().skip(2).fold(0, |index, &dim| index * dim + dim / 2);
+        for i in 0..::std::cmp::min(dims[0], dims[1]) {
+            weight_data[(i * dims[1] + i) * spatial_size + center] = 1f32;
+        }
+    }
+}
+
+impl<'a> CapnpWrite<'a> for FillerType {
+    type Builder = capnp_filler::Builder<'a>;
+
+    /// Write the FillerType into a capnp message.
+    fn write_capnp(&self, builder: &mut Self::Builder) {
+        match *self {
+            FillerType::Constant { value } => {
+                let mut config = builder.borrow().init_constant();
+                config.set_value(value);
+            }
+            FillerType::Glorot { input_size, output_size } => {
+                let mut config = builder.borrow().init_glorot();
+                config.set_input_size(input_size as u64);
+                config.set_output_size(output_size as u64);
+            }
+            FillerType::HeUniform => builder.set_he_uniform(()),
+            FillerType::HeNormal => builder.set_he_normal(()),
+            FillerType::LeCun => builder.set_le_cun(()),
+            FillerType::TruncatedGaussian { mean, std } => {
+                let mut config = builder.borrow().init_truncated_gaussian();
+                config.set_mean(mean);
+                config.set_std(std);
+            }
+            FillerType::Orthogonal { gain } => {
+                let mut config = builder.borrow().init_orthogonal();
+                config.set_gain(gain);
+            }
+            FillerType::Identity => builder.set_identity(()),
+        }
+    }
+}
+
+impl<'a> CapnpRead<'a> for FillerType {
+    type Reader = capnp_filler::Reader<'a>;
+
+    fn read_capnp(reader: Self::Reader) -> Self {
+        match reader.which().unwrap() {
+            capnp_filler::Which::None(()) => panic!("A FillerType can not be read from an empty filler."),
+            capnp_filler::Which::Constant(read_config) => {
+                let config = read_config.unwrap();
+                FillerType::Constant { value: config.get_value() }
+            }
+            capnp_filler::Which::Glorot(read_config) => {
+                let config = read_config.unwrap();
+                FillerType::Glorot {
+                    input_size: config.get_input_size() as usize,
+                    output_size: config.get_output_size() as usize,
+                }
+            }
+            capnp_filler::Which::HeUniform(()) => FillerType::HeUniform,
+            capnp_filler::Which::HeNormal(()) => FillerType::HeNormal,
+            capnp_filler::Which::LeCun(()) => FillerType::LeCun,
+            capnp_filler::Which::TruncatedGaussian(read_config) => {
+                let config = read_config.unwrap();
+                FillerType::TruncatedGaussian {
+                    mean: config.get_mean(),
+                    std: config.get_std(),
+                }
+            }
+            capnp_filler::Which::Orthogonal(read_config) => {
+                let config = read_config.unwrap();
+                FillerType::Orthogonal { gain: config.get_gain() }
+            }
+            capnp_filler::Which::Identity(()) => FillerType::Identity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FillerType;
+    use parenchyma::prelude::SharedTensor;
+
+    fn filled(filler: FillerType, shape: &[usize]) -> Vec<f32> {
+        let mut weight = SharedTensor::<f32>::from(shape);
+        filler.fill(&mut weight);
+        weight.as_slice().unwrap().to_vec()
+    }
+
+    #[test]
+    fn he_uniform_scales_with_fan_in() {
+        let init_range = (6f32 / 12f32).sqrt();
+        for value in filled(FillerType::HeUniform, &[5, 3, 2, 2]) {
+            assert!(value.abs() <= init_range);
+        }
+    }
+
+    #[test]
+    fn truncated_gaussian_stays_within_two_standard_deviations() {
+        for value in filled(FillerType::TruncatedGaussian { mean: 1f32, std: 0.5f32 }, &[100]) {
+            assert!(value >= 0f32 && value <= 2f32);
+        }
+    }
+
+    #[test]
+    fn orthogonal_has_orthonormal_rows() {
+        let (rows, cols) = (3, 5);
+        let values = filled(FillerType::Orthogonal { gain: 2f32 }, &[rows, cols]);
+        for i in 0..rows {
+            for j in 0..rows {
+                let dot = (0..cols).fold(0f32, |sum, k| sum + values[i * cols + k] * values[j * cols + k]);
+                let expected = if i == j { 4f32 } else { 0f32 };
+                assert!((dot - expected).abs() < 1e-4, "row {} . row {} = {}", i, j, dot);
+            }
+        }
+    }
+
+    #[test]
+    fn orthogonal_has_orthonormal_columns_for_tall_matrices() {
+        let (rows, cols) = (5, 2);
+        let values = filled(FillerType::Orthogonal { gain: 1f32 }, &[rows, cols]);
+        for i in 0..cols {
+            for j in 0..cols {
+                let dot = (0..rows).fold(0f32, |sum, k| sum + values[k * cols + i] * values[k * cols + j]);
+                let expected = if i == j { 1f32 } else { 0f32 };
+                assert!((dot - expected).abs() < 1e-4, "column {} . column {} = {}", i, j, dot);
+            }
+        }
+    }
+
+    #[test]
+    fn identity_passes_inputs_through() {
+        assert_eq!(filled(FillerType::Identity, &[2, 3]), vec![1f32, 0f32, 0f32, 0f32, 1f32, 0f32]);
+
+        let convolution = filled(FillerType::Identity, &[2, 2, 3, 3]);
+        let ones = convolution.iter().enumerate().filter(|&(_, &value)| value == 1f32).map(|(i, _)| i).collect::<Vec<_>>();
+        // the centers of the filters [0, 0] and [1, 1]
+        assert_eq!(ones, vec![4, 3 * 9 + 4]);
+        assert_eq!(convolution.iter().sum::<f32>(), 2f32);
+    }
 }
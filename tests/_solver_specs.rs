@@ -1,49 +1,545 @@
-// extern crate leaf;
-// extern crate collenchyma as co;
-
-// #[cfg(all(test, whatever))]
-// // #[cfg(test)]
-// mod solver_specs {
-//     use leaf::solver::*;
-//     use co::backend::Backend;
-//     use co::frameworks::Native;
-
-//     #[test]
-//     // fixed: always return base_lr.
-//     fn lr_fixed() {
-//         let cfg = SolverConfig{ lr_policy: LRPolicy::Fixed, base_lr: 5f32, gamma: 0.5f32, ..SolverConfig::default()};
-//         assert!(cfg.get_learning_rate(0) == 5f32);
-//         assert!(cfg.get_learning_rate(100) == 5f32);
-//         assert!(cfg.get_learning_rate(1000) == 5f32);
-//     }
-
-//     #[test]
-//     // step: return base_lr * gamma ^ (floor(iter / step))
-//     fn lr_step() {
-//         let cfg = SolverConfig{ lr_policy: LRPolicy::Step, base_lr: 5f32, gamma: 0.5f32, stepsize: 10, ..SolverConfig::default()};
-//         assert!(cfg.get_learning_rate(0) == 5f32);
-//         assert!(cfg.get_learning_rate(10) == 2.5f32);
-//         assert!(cfg.get_learning_rate(20) == 1.25f32);
-//     }
-
-//     #[test]
-//     // exp: return base_lr * gamma ^ iter
-//     fn lr_exp() {
-//         let cfg = SolverConfig{ lr_policy: LRPolicy::Exp, base_lr: 5f32, gamma: 0.5f32, ..SolverConfig::default()};
-//         assert!(cfg.get_learning_rate(0) == 5f32);
-//         assert!(cfg.get_learning_rate(1) == 2.5f32);
-//         assert!(cfg.get_learning_rate(2) == 1.25f32);
-//         assert!(cfg.get_learning_rate(3) == 0.625f32);
-
-//         let cfg2 = SolverConfig{ lr_policy: LRPolicy::Exp, base_lr: 5f32, gamma: 0.25f32, ..SolverConfig::default()};
-//         assert!(cfg2.get_learning_rate(0) == 5f32);
-//         assert!(cfg2.get_learning_rate(1) == 1.25f32);
-//         assert!(cfg2.get_learning_rate(2) == 0.3125f32);
-//     }
-
-//     #[test]
-//     fn instantiate_solver_sgd_momentum() {
-//         let cfg = SolverConfig{ solver: SolverKind::SGD(SGDKind::Momentum), ..SolverConfig::default()};
-//         Solver::<Box<ISolver<Backend<Native>>>, Backend<Native>>::from_config(&cfg);
-//     }
-// }
+extern crate leaf;
+extern crate parenchyma;
+extern crate parenchyma_ml;
+
+#[cfg(test)]
+mod sgd_specs {
+    use leaf::layers::*;
+    use leaf::solvers::*;
+    use leaf::weight::WeightConfig;
+    use parenchyma::frameworks::Native;
+    use parenchyma::prelude::{Backend, SharedTensor};
+    use parenchyma_ml::Package as MachLrnPackage;
+    use std::rc::Rc;
+    use std::sync::{Arc, RwLock};
+
+    pub fn native_backend() -> Rc<Backend<MachLrnPackage>> {
+        Rc::new(Backend::new::<Native<MachLrnPackage>>().unwrap())
+    }
+
+    /// A single `Linear` layer mapping two inputs to one output.
+    fn tiny_linear_network(backend: Rc<Backend<MachLrnPackage>>) -> Layer {
+        let mut cfg = SequentialConfig::default();
+        cfg.add_input("data", &[1, 2]);
+        cfg.force_backward = true;
+        cfg.add_layer(LayerConfig::new("linear", LinearConfig { output_size: 1 }));
+
+        Layer::from_config(backend, &LayerConfig::new("network", cfg))
+    }
+
+    fn assert_weights(network: &Layer, expected: &[f32]) {
+        let weights = network.learnable_weights_data();
+        let weight = weights[0].read().unwrap();
+        for (value, expected_value) in weight.as_slice().unwrap().iter().zip(expected) {
+            assert!((value - expected_value).abs() < 1e-5, "{:?} != {:?}", weight.as_slice().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn nesterov_matches_hand_computed_update() {
+        let backend = native_backend();
+        let mut network = tiny_linear_network(backend.clone());
+        network.learnable_weights_data()[0].write().unwrap().write_slice(&[0.5f32, -0.5f32]).unwrap();
+
+        let cfg = SolverConfig {
+            solver: SolverKind::SGD(SGDKind::Nesterov),
+            base_lr: 0.1f32,
+            momentum: 0.9f32,
+            ..SolverConfig::default()
+        };
+        let mut worker = cfg.solver.with_config(backend.clone(), &cfg);
+        worker.init(&network);
+
+        let mut input = SharedTensor::<f32>::from([1, 2]);
+        input.write_slice(&[1f32, 2f32]).unwrap();
+        let input_lock = Arc::new(RwLock::new(input));
+        let mut output_gradient = SharedTensor::<f32>::from([1, 1]);
+        output_gradient.write_slice(&[1f32]).unwrap();
+        let output_gradient_lock = Arc::new(RwLock::new(output_gradient));
+
+        // the gradient of the weights is the input: [1, 2]
+        //
+        // iteration 0:
+        //   history = 0.1 * [1, 2] = [0.1, 0.2]
+        //   update  = 1.9 * [0.1, 0.2] - 0.9 * [0, 0] = [0.19, 0.38]
+        // iteration 1:
+        //   history = 0.9 * [0.1, 0.2] + 0.1 * [1, 2] = [0.19, 0.38]
+        //   update  = 1.9 * [0.19, 0.38] - 0.9 * [0.1, 0.2] = [0.271, 0.542]
+        let expected_weights = [[0.31f32, -0.88f32], [0.039f32, -1.422f32]];
+        for (iter, expected) in expected_weights.iter().enumerate() {
+            network.forward(&[input_lock.clone()]);
+            network.backward(&[output_gradient_lock.clone()]);
+            worker.compute_update(&cfg, &mut network, cfg.get_learning_rate(iter));
+            network.update_weights(worker.backend());
+
+            assert_weights(&network, expected);
+        }
+    }
+
+    /// Runs a step of `solver` on `tiny_linear_network` for each of the `expected_weights`,
+    /// with the weights starting at `[0.5, -0.5]` and a constant weight gradient of `[1, 2]`.
+    fn assert_hand_computed_updates(solver: SolverKind, base_lr: f32, expected_weights: &[[f32; 2]]) {
+        let backend = native_backend();
+        let mut network = tiny_linear_network(backend.clone());
+        network.learnable_weights_data()[0].write().unwrap().write_slice(&[0.5f32, -0.5f32]).unwrap();
+
+        let cfg = SolverConfig {
+            solver: solver,
+            base_lr: base_lr,
+            ..SolverConfig::default()
+        };
+        let mut worker = cfg.solver.with_config(backend.clone(), &cfg);
+        worker.init(&network);
+
+        let mut input = SharedTensor::<f32>::from([1, 2]);
+        input.write_slice(&[1f32, 2f32]).unwrap();
+        let input_lock = Arc::new(RwLock::new(input));
+        let mut output_gradient = SharedTensor::<f32>::from([1, 1]);
+        output_gradient.write_slice(&[1f32]).unwrap();
+        let output_gradient_lock = Arc::new(RwLock::new(output_gradient));
+
+        for (iter, expected) in expected_weights.iter().enumerate() {
+            network.forward(&[input_lock.clone()]);
+            network.backward(&[output_gradient_lock.clone()]);
+            worker.compute_update(&cfg, &mut network, cfg.get_learning_rate(iter));
+            network.update_weights(worker.backend());
+
+            assert_weights(&network, expected);
+        }
+    }
+
+    #[test]
+    fn adam_matches_hand_computed_update() {
+        // iteration 1:
+        //   m = 0.5 * g, v = 0.25 * g^2
+        //   correction = sqrt(1 - 0.75) / (1 - 0.5) = 1
+        //   update = 0.1 * 1 * 0.5 * g / (0.5 * |g|) = 0.1
+        // iteration 2:
+        //   m = 0.75 * g, v = 0.4375 * g^2
+        //   correction = sqrt(1 - 0.5625) / (1 - 0.25) = sqrt(0.4375) / 0.75
+        //   update = 0.1 * correction * 0.75 * g / (sqrt(0.4375) * |g|) = 0.1
+        let adam = AdamConfig { beta1: 0.5f32, beta2: 0.75f32, epsilon: 0f32 };
+        assert_hand_computed_updates(SolverKind::Adam(adam), 0.1f32,
+                                     &[[0.4f32, -0.6f32], [0.3f32, -0.7f32]]);
+    }
+
+    #[test]
+    fn ada_grad_matches_hand_computed_update() {
+        // iteration 1: sum = g^2,     update = 0.1 * g / |g| = 0.1
+        // iteration 2: sum = 2 * g^2, update = 0.1 * g / (sqrt(2) * |g|) = 0.0707107
+        let ada_grad = AdaGradConfig { epsilon: 0f32 };
+        assert_hand_computed_updates(SolverKind::AdaGrad(ada_grad), 0.1f32,
+                                     &[[0.4f32, -0.6f32], [0.3292893f32, -0.6707107f32]]);
+    }
+
+    #[test]
+    fn rms_prop_matches_hand_computed_update() {
+        // iteration 1: average = 0.5 * g^2,  update = 0.1 * g / (sqrt(0.5) * |g|) = 0.1414214
+        // iteration 2: average = 0.75 * g^2, update = 0.1 * g / (sqrt(0.75) * |g|) = 0.1154701
+        let rms_prop = RMSPropConfig { decay: 0.5f32, epsilon: 0f32 };
+        assert_hand_computed_updates(SolverKind::RMSProp(rms_prop), 0.1f32,
+                                     &[[0.3585786f32, -0.6414214f32], [0.2431085f32, -0.7568915f32]]);
+    }
+
+    #[test]
+    fn ada_delta_matches_hand_computed_update() {
+        // g = [1, 2]
+        // iteration 1:
+        //   E[g^2] = 0.5 * g^2 = [0.5, 2]
+        //   update = g * sqrt((0 + 1) / (E[g^2] + 1)) = [sqrt(1 / 1.5), 2 * sqrt(1 / 3)] = [0.8164966, 1.1547005]
+        //   E[u^2] = 0.5 * update^2 = [1 / 3, 2 / 3]
+        // iteration 2:
+        //   E[g^2] = 0.5 * E[g^2] + 0.5 * g^2 = [0.75, 3]
+        //   update = g * sqrt((E[u^2] + 1) / (E[g^2] + 1)) = [sqrt(4 / 3 / 1.75), 2 * sqrt(5 / 3 / 4)] = [0.8728716, 1.2909944]
+        let ada_delta = AdaDeltaConfig { decay: 0.5f32, epsilon: 1f32 };
+        assert_hand_computed_updates(SolverKind::AdaDelta(ada_delta), 1f32,
+                                     &[[-0.3164966f32, -1.6547005f32], [-1.1893682f32, -2.9456949f32]]);
+    }
+
+    /// Two `Linear` layers with the weights `[1, 2]` and `[1, 1]`.
+    ///
+    /// The weight of the second layer uses `decay_mult`.
+    fn two_weight_network(backend: Rc<Backend<MachLrnPackage>>, decay_mult: Option<f32>) -> Layer {
+        let mut cfg = SequentialConfig::default();
+        cfg.add_input("data", &[1, 2]);
+        cfg.add_layer(LayerConfig::new("linear1", LinearConfig { output_size: 1 }));
+        let mut linear2 = LayerConfig::new("linear2", LinearConfig { output_size: 1 });
+        linear2.params.push(WeightConfig { decay_mult: decay_mult, ..WeightConfig::default() });
+        cfg.add_layer(linear2);
+
+        Layer::from_config(backend, &LayerConfig::new("network", cfg))
+    }
+
+    /// Runs `compute_update` of SGD without momentum and with a learning rate of `1`
+    /// on `two_weight_network`, which leaves the clipped and regularized gradients in the network.
+    ///
+    /// Returns the gradients and the reported clip scale.
+    fn sgd_gradients(cfg: SolverConfig, weights: [&[f32]; 2], gradients: [&[f32]; 2], decay_mult: Option<f32>) -> (Vec<Vec<f32>>, Option<f32>) {
+        let backend = native_backend();
+        let mut network = two_weight_network(backend.clone(), decay_mult);
+        for (weight, values) in network.learnable_weights_data().iter().zip(&weights) {
+            weight.write().unwrap().write_slice(values).unwrap();
+        }
+        for (gradient, values) in network.learnable_weights_gradients().iter().zip(&gradients) {
+            gradient.write().unwrap().write_slice(values).unwrap();
+        }
+
+        let cfg = SolverConfig {
+            solver: SolverKind::SGD(SGDKind::Momentum),
+            base_lr: 1f32,
+            momentum: 0f32,
+            ..cfg
+        };
+        let mut worker = cfg.solver.with_config(backend.clone(), &cfg);
+        worker.init(&network);
+        worker.compute_update(&cfg, &mut network, cfg.get_learning_rate(0));
+
+        let gradients = network.learnable_weights_gradients().iter()
+            .map(|gradient| gradient.read().unwrap().as_slice().unwrap().to_vec())
+            .collect::<Vec<_>>();
+        (gradients, worker.clip_scale())
+    }
+
+    fn assert_values(values: &[Vec<f32>], expected: &[&[f32]]) {
+        assert_eq!(values.len(), expected.len());
+        for (value, expected_value) in values.iter().zip(expected) {
+            assert_eq!(value.len(), expected_value.len());
+            for (v, e) in value.iter().zip(expected_value.iter()) {
+                assert!((v - e).abs() < 1e-5, "{:?} != {:?}", values, expected);
+            }
+        }
+    }
+
+    fn clip_cfg(clip_mode: ClipMode, threshold: f32) -> SolverConfig {
+        SolverConfig {
+            clip_gradients: Some(threshold),
+            clip_mode: clip_mode,
+            ..SolverConfig::default()
+        }
+    }
+
+    // the gradients [3, 4] and [12] have the L2 norms 5 and 12, and a global L2 norm of 13
+    const CLIP_WEIGHTS: [&'static [f32]; 2] = [&[0f32, 0f32], &[0f32]];
+    const CLIP_GRADIENTS: [&'static [f32]; 2] = [&[3f32, 4f32], &[12f32]];
+
+    #[test]
+    fn clip_gradients_disabled() {
+        let (gradients, scale) = sgd_gradients(SolverConfig::default(), CLIP_WEIGHTS, CLIP_GRADIENTS, None);
+        assert_values(&gradients, &CLIP_GRADIENTS);
+        assert_eq!(scale, None);
+    }
+
+    #[test]
+    fn clip_gradients_global_norm() {
+        let (gradients, scale) = sgd_gradients(clip_cfg(ClipMode::GlobalNorm, 6.5f32), CLIP_WEIGHTS, CLIP_GRADIENTS, None);
+        assert_values(&gradients, &[&[1.5f32, 2f32], &[6f32]]);
+        assert!((scale.unwrap() - 0.5f32).abs() < 1e-6, "{:?}", scale);
+    }
+
+    #[test]
+    fn clip_gradients_global_norm_below_threshold() {
+        let (gradients, scale) = sgd_gradients(clip_cfg(ClipMode::GlobalNorm, 13.5f32), CLIP_WEIGHTS, CLIP_GRADIENTS, None);
+        assert_values(&gradients, &CLIP_GRADIENTS);
+        assert_eq!(scale, Some(1f32));
+    }
+
+    #[test]
+    fn clip_gradients_per_weight_norm() {
+        // [3, 4] is scaled by 4 / 5, [12] by 4 / 12
+        let (gradients, scale) = sgd_gradients(clip_cfg(ClipMode::PerWeightNorm, 4f32), CLIP_WEIGHTS, CLIP_GRADIENTS, None);
+        assert_values(&gradients, &[&[2.4f32, 3.2f32], &[4f32]]);
+        assert!((scale.unwrap() - 1f32 / 3f32).abs() < 1e-6, "{:?}", scale);
+    }
+
+    #[test]
+    fn clip_gradients_value() {
+        // the L2 norm drops from sqrt(169) to sqrt(9 + 12.25 + 12.25)
+        let (gradients, scale) = sgd_gradients(clip_cfg(ClipMode::Value, 3.5f32), CLIP_WEIGHTS, CLIP_GRADIENTS, None);
+        assert_values(&gradients, &[&[3f32, 3.5f32], &[3.5f32]]);
+        assert!((scale.unwrap() - (33.5f32 / 169f32).sqrt()).abs() < 1e-6, "{:?}", scale);
+    }
+
+    fn regularization_cfg(method: RegularizationMethod) -> SolverConfig {
+        SolverConfig {
+            weight_decay: Some(0.1f32),
+            regularization_method: Some(method),
+            ..SolverConfig::default()
+        }
+    }
+
+    // the second weight has a decay_mult of 0 and is not regularized
+    const REGULARIZATION_WEIGHTS: [&'static [f32]; 2] = [&[0.5f32, -2f32], &[3f32]];
+    const REGULARIZATION_GRADIENTS: [&'static [f32]; 2] = [&[1f32, 1f32], &[1f32]];
+
+    #[test]
+    fn regularize_l2() {
+        // [1, 1] + 0.1 * [0.5, -2]
+        let (gradients, _) = sgd_gradients(regularization_cfg(RegularizationMethod::L2),
+                                           REGULARIZATION_WEIGHTS, REGULARIZATION_GRADIENTS, Some(0f32));
+        assert_values(&gradients, &[&[1.05f32, 0.8f32], &[1f32]]);
+    }
+
+    #[test]
+    fn regularize_l1() {
+        // [1, 1] + 0.1 * sign([0.5, -2])
+        let (gradients, _) = sgd_gradients(regularization_cfg(RegularizationMethod::L1),
+                                           REGULARIZATION_WEIGHTS, REGULARIZATION_GRADIENTS, Some(0f32));
+        assert_values(&gradients, &[&[1.1f32, 0.9f32], &[1f32]]);
+    }
+
+    #[test]
+    fn regularize_elastic_net() {
+        // [1, 1] + 0.25 * 0.1 * sign([0.5, -2]) + 0.75 * 0.1 * [0.5, -2]
+        let (gradients, _) = sgd_gradients(regularization_cfg(RegularizationMethod::ElasticNet { l1_ratio: 0.25f32 }),
+                                           REGULARIZATION_WEIGHTS, REGULARIZATION_GRADIENTS, Some(0f32));
+        assert_values(&gradients, &[&[1.0625f32, 0.825f32], &[1f32]]);
+    }
+
+    #[test]
+    fn regularize_scales_decay_by_decay_mult() {
+        // [1] + 2 * 0.1 * [3]
+        let (gradients, _) = sgd_gradients(regularization_cfg(RegularizationMethod::L2),
+                                           REGULARIZATION_WEIGHTS, REGULARIZATION_GRADIENTS, Some(2f32));
+        assert_values(&gradients, &[&[1.05f32, 0.8f32], &[1.6f32]]);
+    }
+
+    #[test]
+    fn shared_weights_are_updated_once_with_summed_gradient() {
+        let backend = native_backend();
+        let mut cfg = SequentialConfig::default();
+        cfg.add_input("data", &[1, 2]);
+        cfg.force_backward = true;
+        for name in &["linear1", "linear2"] {
+            let mut layer_cfg = LayerConfig::new(name, LinearConfig { output_size: 2 });
+            layer_cfg.params.push(WeightConfig { name: "shared".to_owned(), ..WeightConfig::default() });
+            cfg.add_layer(layer_cfg);
+        }
+        let mut network = Layer::from_config(backend.clone(), &LayerConfig::new("network", cfg));
+
+        let weights = network.learnable_weights_data();
+        assert_eq!(weights.len(), 1);
+        assert_eq!(network.learnable_weights_gradients().len(), 1);
+        assert_eq!(network.learnable_weights_names(), vec!["shared".to_owned()]);
+        weights[0].write().unwrap().write_slice(&[0.5f32, 0f32, 0f32, 1f32]).unwrap();
+
+        let solver_cfg = SolverConfig {
+            solver: SolverKind::SGD(SGDKind::Momentum),
+            base_lr: 0.1f32,
+            momentum: 0f32,
+            ..SolverConfig::default()
+        };
+        let mut worker = solver_cfg.solver.with_config(backend.clone(), &solver_cfg);
+        worker.init(&network);
+
+        let mut input = SharedTensor::<f32>::from([1, 2]);
+        input.write_slice(&[1f32, 2f32]).unwrap();
+        let input_lock = Arc::new(RwLock::new(input));
+        let mut output_gradient = SharedTensor::<f32>::from([1, 2]);
+        output_gradient.write_slice(&[1f32, 1f32]).unwrap();
+
+        // y1 = W x = [0.5, 2]
+        // gradient of linear2: g^T y1 = [[0.5, 2], [0.5, 2]]
+        // gradient of linear1: (W^T g)^T x = [0.5, 1]^T [1, 2] = [[0.5, 1], [1, 2]]
+        // W = W - 0.1 * ([[0.5, 2], [0.5, 2]] + [[0.5, 1], [1, 2]])
+        network.forward(&[input_lock.clone()]);
+        network.backward(&[Arc::new(RwLock::new(output_gradient))]);
+        worker.compute_update(&solver_cfg, &mut network, solver_cfg.get_learning_rate(0));
+        network.update_weights(worker.backend());
+
+        assert_weights(&network, &[0.4f32, -0.3f32, -0.15f32, 0.6f32]);
+
+        // both layers compute their output with the updated weight
+        let output = network.forward(&[input_lock])[0].read().unwrap().as_slice().unwrap().to_vec();
+        for (value, expected) in output.iter().zip(&[-0.395f32, 0.66f32]) {
+            assert!((value - expected).abs() < 1e-5, "{:?}", output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod lr_specs {
+    use leaf::solvers::*;
+
+    fn assert_rate(cfg: &SolverConfig, iter: usize, expected: f32) {
+        let rate = cfg.get_learning_rate(iter);
+        assert!((rate - expected).abs() < 1e-5, "iter {}: {} != {}", iter, rate, expected);
+    }
+
+    #[test]
+    // fixed: always return base_lr.
+    fn lr_fixed() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::Fixed, base_lr: 5f32, gamma: 0.5f32, ..SolverConfig::default()};
+        assert!(cfg.get_learning_rate(0) == 5f32);
+        assert!(cfg.get_learning_rate(100) == 5f32);
+        assert!(cfg.get_learning_rate(1000) == 5f32);
+    }
+
+    #[test]
+    // step: return base_lr * gamma ^ (floor(iter / step))
+    fn lr_step() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::Step, base_lr: 5f32, gamma: 0.5f32, stepsize: 10, ..SolverConfig::default()};
+        assert!(cfg.get_learning_rate(0) == 5f32);
+        assert!(cfg.get_learning_rate(10) == 2.5f32);
+        assert!(cfg.get_learning_rate(20) == 1.25f32);
+    }
+
+    #[test]
+    // multistep: return base_lr * gamma ^ (number of stepvalues <= iter)
+    fn lr_multistep() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::Multistep, base_lr: 5f32, gamma: 0.5f32, stepvalue: vec![5, 20], ..SolverConfig::default()};
+        assert!(cfg.get_learning_rate(0) == 5f32);
+        assert!(cfg.get_learning_rate(4) == 5f32);
+        assert!(cfg.get_learning_rate(5) == 2.5f32);
+        assert!(cfg.get_learning_rate(19) == 2.5f32);
+        assert!(cfg.get_learning_rate(20) == 1.25f32);
+        assert!(cfg.get_learning_rate(1000) == 1.25f32);
+    }
+
+    #[test]
+    // exp: return base_lr * gamma ^ iter
+    fn lr_exp() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::Exp, base_lr: 5f32, gamma: 0.5f32, ..SolverConfig::default()};
+        assert!(cfg.get_learning_rate(0) == 5f32);
+        assert!(cfg.get_learning_rate(1) == 2.5f32);
+        assert!(cfg.get_learning_rate(2) == 1.25f32);
+        assert!(cfg.get_learning_rate(3) == 0.625f32);
+
+        let cfg2 = SolverConfig{ lr_policy: LRPolicy::Exp, base_lr: 5f32, gamma: 0.25f32, ..SolverConfig::default()};
+        assert!(cfg2.get_learning_rate(0) == 5f32);
+        assert!(cfg2.get_learning_rate(1) == 1.25f32);
+        assert!(cfg2.get_learning_rate(2) == 0.3125f32);
+    }
+
+    #[test]
+    // inv: return base_lr * (1 + gamma * iter) ^ (- power)
+    fn lr_inv() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::Inv, base_lr: 4f32, gamma: 1f32, power: 2f32, ..SolverConfig::default()};
+        assert_rate(&cfg, 0, 4f32);
+        assert_rate(&cfg, 1, 1f32);
+        assert_rate(&cfg, 3, 0.25f32);
+    }
+
+    #[test]
+    // poly: return base_lr (1 - iter/max_iter) ^ (power)
+    fn lr_poly() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::Poly, base_lr: 4f32, power: 2f32, max_iter: 100, ..SolverConfig::default()};
+        assert_rate(&cfg, 0, 4f32);
+        assert_rate(&cfg, 50, 1f32);
+        assert_rate(&cfg, 100, 0f32);
+        assert_rate(&cfg, 200, 0f32);
+    }
+
+    #[test]
+    // sigmoid: return base_lr ( 1/(1 + exp(-gamma * (iter - stepsize))))
+    fn lr_sigmoid() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::Sigmoid, base_lr: 4f32, gamma: 1f32, stepsize: 10, ..SolverConfig::default()};
+        assert_rate(&cfg, 10, 2f32);
+        assert_rate(&cfg, 11, 4f32 / (1f32 + (-1f32).exp()));
+        assert_rate(&cfg, 9, 4f32 / (1f32 + 1f32.exp()));
+    }
+
+    #[test]
+    // cosine annealing: follow a cosine from base_lr to min_lr and restart after each cycle
+    fn lr_cosine_annealing() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::CosineAnnealing, base_lr: 3f32, min_lr: 1f32, stepsize: 10, ..SolverConfig::default()};
+        assert_rate(&cfg, 0, 3f32);
+        assert_rate(&cfg, 5, 2f32);
+        assert_rate(&cfg, 10, 3f32);
+        assert_rate(&cfg, 15, 2f32);
+
+        // every cycle is twice as long as the previous one: [0, 10), [10, 30), [30, 70)
+        let cfg2 = SolverConfig{ restart_mult: 2, ..cfg };
+        assert_rate(&cfg2, 5, 2f32);
+        assert_rate(&cfg2, 10, 3f32);
+        assert_rate(&cfg2, 20, 2f32);
+        assert_rate(&cfg2, 30, 3f32);
+        assert_rate(&cfg2, 50, 2f32);
+    }
+
+    #[test]
+    #[should_panic(expected = "stepsize greater than 0")]
+    fn lr_cosine_annealing_rejects_zero_stepsize() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::CosineAnnealing, stepsize: 0, ..SolverConfig::default()};
+        cfg.get_learning_rate(0);
+    }
+
+    #[test]
+    fn lr_cosine_annealing_does_not_overflow_long_cycles() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::CosineAnnealing, base_lr: 3f32, min_lr: 1f32, stepsize: 10, restart_mult: 1000, ..SolverConfig::default()};
+        // cycles: [0, 10), [10, 10010), [10010, 10010010), ... until the length saturates
+        let rate = cfg.get_learning_rate(usize::max_value() - 1);
+        assert!(rate >= 1f32 && rate <= 3f32);
+    }
+
+    #[test]
+    // warmup: scale the rate of the policy by (iter + 1) / warmup_iter during warmup
+    fn lr_warmup() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::Fixed, base_lr: 4f32, warmup_iter: 4, ..SolverConfig::default()};
+        assert_rate(&cfg, 0, 1f32);
+        assert_rate(&cfg, 1, 2f32);
+        assert_rate(&cfg, 3, 4f32);
+        assert_rate(&cfg, 10, 4f32);
+
+        let cfg2 = SolverConfig{ lr_policy: LRPolicy::Exp, base_lr: 4f32, gamma: 0.5f32, warmup_iter: 2, ..SolverConfig::default()};
+        assert_rate(&cfg2, 0, 2f32);
+        assert_rate(&cfg2, 1, 2f32);
+        assert_rate(&cfg2, 2, 1f32);
+    }
+
+    #[test]
+    fn lr_policy_is_a_schedule() {
+        let cfg = SolverConfig{ lr_policy: LRPolicy::Step, base_lr: 5f32, gamma: 0.5f32, stepsize: 10, ..SolverConfig::default()};
+        let mut schedule = cfg.lr_policy;
+        assert!(schedule.learning_rate(&cfg, 0) == 5f32);
+        assert!(schedule.learning_rate(&cfg, 10) == 2.5f32);
+    }
+
+    #[test]
+    fn lr_reduce_on_plateau() {
+        let cfg = SolverConfig{ base_lr: 4f32, ..SolverConfig::default()};
+        let mut schedule = ReduceOnPlateau::new(0.5f32, 1);
+        assert!(schedule.learning_rate(&cfg, 0) == 4f32);
+
+        schedule.observe_loss(1f32);
+        schedule.observe_loss(0.5f32);
+        // one observation without improvement is tolerated
+        schedule.observe_loss(0.6f32);
+        assert!(schedule.learning_rate(&cfg, 1) == 4f32);
+        schedule.observe_loss(0.5f32);
+        assert!(schedule.learning_rate(&cfg, 2) == 2f32);
+        // improving again keeps the learning rate
+        schedule.observe_loss(0.1f32);
+        assert!(schedule.learning_rate(&cfg, 3) == 2f32);
+    }
+
+    #[test]
+    fn lr_reduce_on_plateau_before_first_learning_rate() {
+        let cfg = SolverConfig{ base_lr: 4f32, ..SolverConfig::default()};
+        let mut schedule = ReduceOnPlateau::new(0.5f32, 0);
+        schedule.min_lr = 1.5f32;
+
+        schedule.observe_loss(1f32);
+        schedule.observe_loss(1f32);
+        assert!(schedule.learning_rate(&cfg, 0) == 2f32);
+        schedule.observe_loss(1f32);
+        assert!(schedule.learning_rate(&cfg, 1) == 1.5f32);
+    }
+
+    #[test]
+    fn lr_piecewise_constant() {
+        let cfg = SolverConfig{ base_lr: 4f32, ..SolverConfig::default()};
+        let mut schedule = PiecewiseConstant::new(&[(20, 0.5f32), (10, 1f32)]);
+        assert!(schedule.learning_rate(&cfg, 0) == 4f32);
+        assert!(schedule.learning_rate(&cfg, 10) == 1f32);
+        assert!(schedule.learning_rate(&cfg, 19) == 1f32);
+        assert!(schedule.learning_rate(&cfg, 20) == 0.5f32);
+        assert!(schedule.learning_rate(&cfg, 100) == 0.5f32);
+    }
+
+    #[test]
+    fn instantiate_solver_sgd_momentum() {
+        let backend = super::sgd_specs::native_backend();
+        let cfg = SolverConfig{ solver: SolverKind::SGD(SGDKind::Momentum), ..SolverConfig::default()};
+        Solver::from_config(backend.clone(), backend, &cfg);
+    }
+}
@@ -0,0 +1,428 @@
+extern crate leaf;
+extern crate parenchyma;
+extern crate parenchyma_ml;
+
+#[cfg(test)]
+mod gradient_check_specs {
+    use leaf::gradient_check::{CheckedInput, GradientChecker};
+    use leaf::layers::*;
+    use parenchyma::frameworks::Native;
+    use parenchyma::prelude::Backend;
+    use parenchyma_ml::Package as MachLrnPackage;
+    use std::rc::Rc;
+
+    pub fn native_backend() -> Rc<Backend<MachLrnPackage>> {
+        Rc::new(Backend::new::<Native<MachLrnPackage>>().unwrap())
+    }
+
+    fn assert_gradients_with(checker: &GradientChecker, layer_type: LayerType, inputs: &[CheckedInput]) {
+        let config = LayerConfig::new("checked", layer_type);
+        if let Err(mismatch) = checker.check_inputs(native_backend(), &config, inputs) {
+            panic!("{:?}: {}", config.layer_type, mismatch);
+        }
+    }
+
+    fn assert_gradients(layer_type: LayerType, inputs: &[CheckedInput]) {
+        assert_gradients_with(&GradientChecker::default(), layer_type, inputs)
+    }
+
+    fn assert_gradients_for_shapes(layer_type: LayerType, input_shapes: &[&[usize]]) {
+        let inputs = input_shapes.iter().map(|shape| CheckedInput::random(shape)).collect::<Vec<_>>();
+        assert_gradients(layer_type, &inputs)
+    }
+
+    fn assert_gradients_in_place(layer_type: LayerType, input_shape: &[usize]) {
+        let checker = GradientChecker { in_place: true, ..GradientChecker::default() };
+        assert_gradients_with(&checker, layer_type, &[CheckedInput::random(input_shape)]);
+    }
+
+    /// `len` distinct values that are at least `0.1` apart, in a scrambled order.
+    fn distinct_values(len: usize) -> Vec<f32> {
+        (0..len).map(|i| ((i * 7) % len) as f32 * 0.1f32 - 0.5f32).collect()
+    }
+
+    #[test]
+    fn checker_reports_mismatching_gradients() {
+        // with a large step the central difference of the sigmoid is far off
+        let checker = GradientChecker { step: 0.5f32, tolerance: 1e-6f32, ..GradientChecker::default() };
+        let config = LayerConfig::new("checked", LayerType::Sigmoid);
+        let mismatch = checker.check(native_backend(), &config, &[&[2, 3]]).unwrap_err();
+        assert_eq!(mismatch.tensor, "input 0");
+        assert!(mismatch.analytical != mismatch.numerical);
+    }
+
+    // activation layers
+
+    #[test]
+    fn relu() {
+        assert_gradients_for_shapes(LayerType::ReLU, &[&[2, 5]]);
+    }
+
+    #[test]
+    fn sigmoid() {
+        assert_gradients_for_shapes(LayerType::Sigmoid, &[&[2, 5]]);
+    }
+
+    #[test]
+    fn tanh() {
+        assert_gradients_for_shapes(LayerType::TanH, &[&[2, 5]]);
+    }
+
+    #[test]
+    fn tanh_in_place() {
+        assert_gradients_in_place(LayerType::TanH, &[2, 5]);
+    }
+
+    #[test]
+    fn leaky_relu() {
+        assert_gradients_for_shapes(LayerType::LeakyReLU(LeakyReLUConfig::default()), &[&[2, 5]]);
+    }
+
+    #[test]
+    fn leaky_relu_in_place() {
+        assert_gradients_in_place(LayerType::LeakyReLU(LeakyReLUConfig { slope: 0.2f32 }), &[2, 5]);
+    }
+
+    #[test]
+    fn prelu() {
+        assert_gradients_for_shapes(LayerType::PReLU(PReLUConfig::default()), &[&[2, 3, 2, 2]]);
+        assert_gradients_for_shapes(LayerType::PReLU(PReLUConfig { shared: true, ..PReLUConfig::default() }), &[&[2, 3]]);
+    }
+
+    #[test]
+    fn elu() {
+        assert_gradients_for_shapes(LayerType::ELU(ELUConfig::default()), &[&[2, 5]]);
+    }
+
+    #[test]
+    fn elu_in_place() {
+        assert_gradients_in_place(LayerType::ELU(ELUConfig::default()), &[2, 5]);
+    }
+
+    #[test]
+    fn gelu() {
+        assert_gradients_for_shapes(LayerType::GELU, &[&[2, 5]]);
+    }
+
+    #[test]
+    fn softplus() {
+        assert_gradients_for_shapes(LayerType::Softplus, &[&[2, 5]]);
+    }
+
+    #[test]
+    fn softplus_in_place() {
+        assert_gradients_in_place(LayerType::Softplus, &[2, 5]);
+    }
+
+    #[test]
+    fn swish() {
+        assert_gradients_for_shapes(LayerType::Swish, &[&[2, 5]]);
+    }
+
+    // common layers
+
+    #[test]
+    fn linear_with_batch() {
+        assert_gradients_for_shapes(LayerType::Linear(LinearConfig { output_size: 3 }), &[&[4, 5]]);
+    }
+
+    #[test]
+    fn convolution() {
+        let config = ConvolutionConfig {
+            num_output: 3,
+            filter_shape: vec![3],
+            stride: vec![1],
+            padding: vec![1],
+        };
+        assert_gradients_for_shapes(LayerType::Convolution(config), &[&[2, 2, 4, 4]]);
+    }
+
+    #[test]
+    fn max_pooling() {
+        let config = PoolingConfig {
+            mode: PoolingMode::Max,
+            filter_shape: vec![2],
+            stride: vec![2],
+            padding: vec![0],
+        };
+        assert_gradients(LayerType::Pooling(config), &[CheckedInput::values(&[1, 2, 4, 4], &distinct_values(32))]);
+    }
+
+    #[test]
+    fn average_pooling() {
+        let config = PoolingConfig {
+            mode: PoolingMode::Average,
+            filter_shape: vec![3],
+            stride: vec![1],
+            padding: vec![1],
+        };
+        assert_gradients_for_shapes(LayerType::Pooling(config), &[&[1, 2, 4, 4]]);
+    }
+
+    #[test]
+    fn softmax() {
+        assert_gradients_for_shapes(LayerType::Softmax, &[&[3, 4]]);
+    }
+
+    #[test]
+    fn log_softmax() {
+        assert_gradients_for_shapes(LayerType::LogSoftmax, &[&[3, 4]]);
+    }
+
+    #[test]
+    fn dropout_in_eval_mode() {
+        let checker = GradientChecker { mode: Mode::Eval, ..GradientChecker::default() };
+        let inputs = [CheckedInput::random(&[2, 5])];
+        assert_gradients_with(&checker, LayerType::Dropout(DropoutConfig::default()), &inputs);
+    }
+
+    #[test]
+    fn dropout_in_train_mode() {
+        // the recreated layers draw the same mask from the seeded generator
+        let dropout = DropoutConfig { probability: 0.5f32, seed: Some(3) };
+        let checker = GradientChecker { recreate_layer: true, ..GradientChecker::default() };
+        assert_gradients_with(&checker, LayerType::Dropout(dropout), &[CheckedInput::random(&[2, 5])]);
+
+        let checker = GradientChecker { recreate_layer: true, in_place: true, ..GradientChecker::default() };
+        assert_gradients_with(&checker, LayerType::Dropout(dropout), &[CheckedInput::random(&[2, 5])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can not be checked in-place")]
+    fn checker_rejects_in_place_for_layers_without_support() {
+        assert_gradients_in_place(LayerType::Sigmoid, &[2, 5]);
+    }
+
+    #[test]
+    fn batch_norm() {
+        assert_gradients_for_shapes(LayerType::BatchNorm(BatchNormConfig::default()), &[&[4, 3]]);
+        assert_gradients_for_shapes(LayerType::BatchNorm(BatchNormConfig::default()), &[&[2, 3, 2, 2]]);
+    }
+
+    #[test]
+    fn batch_norm_in_eval_mode() {
+        let checker = GradientChecker { mode: Mode::Eval, ..GradientChecker::default() };
+        let inputs = [CheckedInput::random(&[4, 3])];
+        assert_gradients_with(&checker, LayerType::BatchNorm(BatchNormConfig::default()), &inputs);
+    }
+
+    #[test]
+    fn layer_norm() {
+        assert_gradients_for_shapes(LayerType::LayerNorm(LayerNormConfig::default()), &[&[3, 5]]);
+    }
+
+    #[test]
+    fn embedding() {
+        let indices = CheckedInput::constant(&[2, 3], &[0f32, 4f32, 2f32, 2f32, 1f32, 4f32]);
+        assert_gradients(LayerType::Embedding(EmbeddingConfig::new(5, 4)), &[indices]);
+    }
+
+    #[test]
+    fn rnn() {
+        assert_gradients_for_shapes(LayerType::RNN(RecurrentConfig::new(3)), &[&[3, 2, 4]]);
+    }
+
+    #[test]
+    fn lstm() {
+        assert_gradients_for_shapes(LayerType::LSTM(RecurrentConfig::new(3)), &[&[3, 2, 4]]);
+    }
+
+    #[test]
+    fn bidirectional_lstm_with_initial_states() {
+        let config = RecurrentConfig { bidirectional: true, ..RecurrentConfig::new(3) };
+        assert_gradients_for_shapes(LayerType::LSTM(config), &[&[3, 2, 4], &[2, 2, 3], &[2, 2, 3]]);
+    }
+
+    #[test]
+    fn gru() {
+        let config = RecurrentConfig { bidirectional: true, ..RecurrentConfig::new(3) };
+        assert_gradients_for_shapes(LayerType::GRU(RecurrentConfig::new(3)), &[&[3, 2, 4]]);
+        assert_gradients_for_shapes(LayerType::GRU(config), &[&[3, 2, 4], &[2, 2, 3]]);
+    }
+
+    #[test]
+    fn scaled_dot_product_attention() {
+        assert_gradients_for_shapes(LayerType::ScaledDotProductAttention(ScaledDotProductAttentionConfig::default()),
+                                    &[&[2, 3, 4], &[2, 5, 4], &[2, 5, 4]]);
+    }
+
+    #[test]
+    fn causal_scaled_dot_product_attention_with_padding_mask() {
+        let config = ScaledDotProductAttentionConfig { causal: true };
+        let inputs = [CheckedInput::random(&[2, 3, 4]),
+                      CheckedInput::random(&[2, 3, 4]),
+                      CheckedInput::random(&[2, 3, 4]),
+                      CheckedInput::constant(&[2, 3], &[1f32, 1f32, 0f32, 1f32, 1f32, 1f32])];
+        assert_gradients(LayerType::ScaledDotProductAttention(config), &inputs);
+    }
+
+    #[test]
+    fn multi_head_attention() {
+        let config = MultiHeadAttentionConfig { causal: true, ..MultiHeadAttentionConfig::new(2) };
+        assert_gradients_for_shapes(LayerType::MultiHeadAttention(MultiHeadAttentionConfig::new(2)), &[&[2, 3, 4]]);
+        assert_gradients_for_shapes(LayerType::MultiHeadAttention(config), &[&[2, 3, 4]]);
+    }
+
+    #[test]
+    fn eltwise_sum_with_coefficients() {
+        let config = EltwiseConfig { coefficients: vec![0.5f32, -2f32], ..EltwiseConfig::default() };
+        assert_gradients_for_shapes(LayerType::Eltwise(config), &[&[2, 3], &[2, 3]]);
+    }
+
+    #[test]
+    fn eltwise_product() {
+        let config = EltwiseConfig { operation: EltwiseOperation::Product, ..EltwiseConfig::default() };
+        assert_gradients_for_shapes(LayerType::Eltwise(config), &[&[2, 3], &[2, 3], &[2, 3]]);
+    }
+
+    #[test]
+    fn eltwise_max() {
+        let config = EltwiseConfig { operation: EltwiseOperation::Max, ..EltwiseConfig::default() };
+        let values = distinct_values(12);
+        let inputs = [CheckedInput::values(&[2, 3], &values[..6]), CheckedInput::values(&[2, 3], &values[6..])];
+        assert_gradients(LayerType::Eltwise(config), &inputs);
+    }
+
+    // utility layers
+
+    #[test]
+    fn reshape() {
+        assert_gradients_for_shapes(LayerType::Reshape(ReshapeConfig::of_shape(&[3, 2])), &[&[2, 3]]);
+    }
+
+    #[test]
+    fn flatten() {
+        assert_gradients_for_shapes(LayerType::Flatten(FlattenConfig::default()), &[&[2, 3, 2]]);
+    }
+
+    #[test]
+    fn concat() {
+        assert_gradients_for_shapes(LayerType::Concat(ConcatConfig::default()), &[&[2, 1, 3], &[2, 2, 3]]);
+    }
+
+    #[test]
+    fn slice() {
+        let mut config = LayerConfig::new("checked", LayerType::Slice(SliceConfig { axis: 1, slice_points: vec![1, 3] }));
+        config.add_output("first");
+        config.add_output("second");
+        config.add_output("third");
+        GradientChecker::default().check(native_backend(), &config, &[&[2, 4, 2]]).unwrap();
+    }
+
+    // loss layers
+
+    #[test]
+    fn negative_log_likelihood() {
+        let inputs = [CheckedInput::random(&[3, 4]), CheckedInput::constant(&[3], &[0f32, 2f32, 3f32])];
+        assert_gradients(LayerType::NegativeLogLikelihood(NegativeLogLikelihoodConfig::new(4)), &inputs);
+    }
+
+    #[test]
+    fn negative_log_likelihood_with_ignored_labels() {
+        let config = NegativeLogLikelihoodConfig { ignore_index: Some(1), ..NegativeLogLikelihoodConfig::new(3) };
+        let inputs = [CheckedInput::random(&[2, 3, 2]), CheckedInput::constant(&[2, 2], &[0f32, 1f32, 2f32, 2f32])];
+        assert_gradients(LayerType::NegativeLogLikelihood(config), &inputs);
+    }
+
+    #[test]
+    fn mean_squared_error() {
+        let inputs = [CheckedInput::random(&[2, 3]), CheckedInput::constant(&[2, 3], &[0.5f32, -0.5f32, 1f32, 0f32, 0.2f32, -1f32])];
+        assert_gradients(LayerType::MeanSquaredError(MeanSquaredErrorConfig::default()), &inputs);
+    }
+
+    #[test]
+    fn huber() {
+        // the differences to the targets are in the quadratic as well as in the linear part
+        let inputs = [CheckedInput::values(&[2, 3], &[2.5f32, -3f32, 0.5f32, -0.2f32, 1.8f32, 0.3f32]),
+                      CheckedInput::constant(&[2, 3], &[0f32; 6])];
+        assert_gradients(LayerType::Huber(HuberConfig::default()), &inputs);
+    }
+
+    #[test]
+    fn softmax_cross_entropy() {
+        let inputs = [CheckedInput::random(&[3, 4]), CheckedInput::constant(&[3], &[1f32, 0f32, 3f32])];
+        assert_gradients(LayerType::SoftmaxCrossEntropy(SoftmaxCrossEntropyConfig::new(4)), &inputs);
+    }
+
+    #[test]
+    fn softmax_cross_entropy_with_label_smoothing() {
+        let config = SoftmaxCrossEntropyConfig { label_smoothing: 0.1f32, ..SoftmaxCrossEntropyConfig::new(4) };
+        let inputs = [CheckedInput::random(&[3, 4]), CheckedInput::constant(&[3, 1], &[1f32, 0f32, 3f32])];
+        assert_gradients(LayerType::SoftmaxCrossEntropy(config), &inputs);
+    }
+
+    #[test]
+    fn binary_cross_entropy_with_logits() {
+        let inputs = [CheckedInput::random(&[2, 3]), CheckedInput::constant(&[2, 3], &[1f32, 0f32, 1f32, 0f32, 0f32, 1f32])];
+        assert_gradients(LayerType::BinaryCrossEntropy(BinaryCrossEntropyConfig::default()), &inputs);
+    }
+
+    #[test]
+    fn binary_cross_entropy_with_probabilities() {
+        let config = BinaryCrossEntropyConfig { with_logits: false, ..BinaryCrossEntropyConfig::default() };
+        let inputs = [CheckedInput::values(&[2, 3], &[0.2f32, 0.7f32, 0.5f32, 0.35f32, 0.8f32, 0.6f32]),
+                      CheckedInput::constant(&[2, 3], &[1f32, 0f32, 1f32, 0f32, 0f32, 1f32])];
+        assert_gradients(LayerType::BinaryCrossEntropy(config), &inputs);
+    }
+
+    // container layers
+
+    #[test]
+    fn sequential() {
+        let mut cfg = SequentialConfig::default();
+        cfg.add_input("data", &[2, 3]);
+        cfg.force_backward = true;
+        cfg.add_layer(LayerConfig::new("linear1", LinearConfig { output_size: 4 }));
+        cfg.add_layer(LayerConfig::new("sigmoid", LayerType::Sigmoid));
+        cfg.add_layer(LayerConfig::new("linear2", LinearConfig { output_size: 2 }));
+
+        assert_gradients_for_shapes(LayerType::Sequential(cfg), &[&[2, 3]]);
+    }
+
+    #[test]
+    fn graph_with_residual_connection() {
+        let mut cfg = GraphConfig::default();
+        cfg.add_input("x", &[2, 3]);
+        cfg.force_backward = true;
+        let mut linear = LayerConfig::new("linear", LinearConfig { output_size: 3 });
+        linear.add_input("x");
+        linear.add_output("hidden");
+        cfg.add_layer(linear);
+        let mut tanh = LayerConfig::new("tanh", LayerType::TanH);
+        tanh.add_input("hidden");
+        tanh.add_output("activation");
+        cfg.add_layer(tanh);
+        let mut sum = LayerConfig::new("sum", EltwiseConfig::default());
+        sum.add_input("x");
+        sum.add_input("activation");
+        sum.add_output("y");
+        cfg.add_layer(sum);
+        cfg.add_output("y");
+
+        assert_gradients_for_shapes(LayerType::Graph(cfg), &[&[2, 3]]);
+    }
+
+    #[test]
+    fn graph_with_two_heads_and_consumed_output() {
+        // `hidden` is an output of the Graph and the input of the second head
+        let mut cfg = GraphConfig::default();
+        cfg.add_input("x", &[2, 3]);
+        cfg.force_backward = true;
+        let mut linear = LayerConfig::new("linear", LinearConfig { output_size: 3 });
+        linear.add_input("x");
+        linear.add_output("hidden");
+        cfg.add_layer(linear);
+        let mut tanh = LayerConfig::new("tanh", LayerType::TanH);
+        tanh.add_input("hidden");
+        tanh.add_output("activation");
+        cfg.add_layer(tanh);
+        let mut head = LayerConfig::new("head", LinearConfig { output_size: 2 });
+        head.add_input("activation");
+        head.add_output("z");
+        cfg.add_layer(head);
+        cfg.add_output("hidden");
+        cfg.add_output("z");
+
+        assert_gradients_for_shapes(LayerType::Graph(cfg), &[&[2, 3]]);
+    }
+}
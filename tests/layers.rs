@@ -1,9 +1,18 @@
 extern crate leaf;
+extern crate parenchyma;
+extern crate parenchyma_ml;
 
 #[cfg(test)]
 mod layers_spec {
-    use leaf::layers::{ReLU, Sigmoid, TanH};
-    use leaf::layer::LayerWorker;
+    use leaf::layers::{BatchNorm, BatchNormConfig, Layer, LayerConfig, LayerType, LayerWorker, LeakyReLUConfig, LinearConfig,
+                       Mode, MultiHeadAttention, MultiHeadAttentionConfig, ReLU, SequentialConfig, Sigmoid, TanH};
+    use leaf::weight::{FillerType, WeightConfig};
+    use parenchyma::frameworks::Native;
+    use parenchyma::prelude::{Backend, SharedTensor};
+    use parenchyma_ml::Package as MachLrnPackage;
+    use std::env;
+    use std::rc::Rc;
+    use std::sync::{Arc, RwLock};
 
     #[test]
     fn test_exact_num_input_and_output_blobs_for_a_relu_layer() {
@@ -22,4 +31,140 @@ mod layers_spec {
         assert_eq!(TanH.exact_num_output_blobs(), Some(1));
         assert_eq!(TanH.exact_num_input_blobs(), Some(1));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_tanh_layer_computes_in_place() {
+        assert!(TanH.compute_in_place());
+        assert!(LayerType::TanH.supports_in_place());
+    }
+
+    #[test]
+    fn test_leaky_relu_layer_computes_in_place_for_non_negative_slopes() {
+        assert!(LayerType::LeakyReLU(LeakyReLUConfig::default()).supports_in_place());
+        assert!(!LayerType::LeakyReLU(LeakyReLUConfig { slope: -0.5f32 }).supports_in_place());
+        assert!(LayerType::Softplus.supports_in_place());
+        assert!(!LayerType::GELU.supports_in_place());
+    }
+
+    #[test]
+    fn test_batch_norm_layer_has_buffers_besides_its_weights() {
+        let layer = BatchNorm::from_config(&BatchNormConfig::default());
+        assert!(layer.auto_weight_blobs());
+        assert_eq!(layer.exact_num_weight_blobs(), Some(2));
+
+        let buffer_names = layer.buffers().into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(buffer_names, vec!["running_mean".to_owned(), "running_variance".to_owned()]);
+    }
+
+    /// A network with a single BatchNorm layer for a batch of 2 with 2 channels,
+    /// which has run one forward pass in training mode on `[1, 3, 5, 7]`.
+    ///
+    /// The batch has the means `[3, 5]` and the unbiased variances `[8, 8]`,
+    /// so with a momentum of 0.5 the running averages are `[1.5, 2.5]` and `[4.5, 4.5]`.
+    fn trained_batch_norm_network(backend: Rc<Backend<MachLrnPackage>>) -> Layer {
+        let mut cfg = SequentialConfig::default();
+        cfg.add_input("data", &[2, 2]);
+        cfg.add_layer(LayerConfig::new("batch_norm", BatchNormConfig { momentum: 0.5f32, ..BatchNormConfig::default() }));
+        let mut network = Layer::from_config(backend, &LayerConfig::new("network", cfg));
+
+        network.set_mode(Mode::Train);
+        network.forward(&[tensor(&[2, 2], &[1f32, 3f32, 5f32, 7f32])]);
+        network
+    }
+
+    fn tensor(shape: &[usize], data: &[f32]) -> Arc<RwLock<SharedTensor<f32>>> {
+        let mut tensor = SharedTensor::<f32>::from(shape);
+        tensor.write_slice(data).unwrap();
+        Arc::new(RwLock::new(tensor))
+    }
+
+    fn assert_values(tensor: &Arc<RwLock<SharedTensor<f32>>>, expected: &[f32]) {
+        let tensor = tensor.read().unwrap();
+        let values = tensor.as_slice().unwrap();
+        assert_eq!(values.len(), expected.len());
+        for (value, expected_value) in values.iter().zip(expected) {
+            assert!((value - expected_value).abs() < 1e-4, "{:?} != {:?}", values, expected);
+        }
+    }
+
+    #[test]
+    fn test_batch_norm_running_statistics_are_saved_but_not_learnable() {
+        let backend = Rc::new(Backend::new::<Native<MachLrnPackage>>().unwrap());
+        let mut network = trained_batch_norm_network(backend.clone());
+
+        let path = env::temp_dir().join("leaf_batch_norm_running_statistics.capnp");
+        network.save(&path).unwrap();
+        let loaded = Layer::load(backend, &path).unwrap();
+
+        let buffers = loaded.buffers();
+        let buffer_names = buffers.iter().map(|&(ref name, _)| name.clone()).collect::<Vec<_>>();
+        assert_eq!(buffer_names, vec!["batch_norm-running_mean".to_owned(), "batch_norm-running_variance".to_owned()]);
+        assert_values(&buffers[0].1, &[1.5f32, 2.5f32]);
+        assert_values(&buffers[1].1, &[4.5f32, 4.5f32]);
+
+        let weights = loaded.learnable_weights_data();
+        assert_eq!(weights.len(), 2);
+        for &(ref name, ref buffer) in &buffers {
+            assert!(!weights.iter().any(|weight| Arc::ptr_eq(weight, buffer)));
+            assert!(!loaded.learnable_weights_names().contains(name));
+        }
+    }
+
+    #[test]
+    fn test_batch_norm_uses_running_statistics_in_eval_mode() {
+        let backend = Rc::new(Backend::new::<Native<MachLrnPackage>>().unwrap());
+        let mut network = trained_batch_norm_network(backend);
+
+        // with the running averages: (x - [1.5, 2.5]) / sqrt(4.5)
+        // (the batch statistics would give [-1, -1, 1, 1])
+        network.set_mode(Mode::Eval);
+        let output = network.forward(&[tensor(&[2, 2], &[1.5f32, 2.5f32, 6f32, 7f32])]);
+        assert_values(&output[0], &[0f32, 0f32, 2.1213203f32, 2.1213203f32]);
+    }
+
+    #[test]
+    fn test_multi_head_attention_layer_names_its_projections() {
+        let layer = MultiHeadAttention::from_config(&MultiHeadAttentionConfig::new(4));
+        assert!(layer.auto_weight_blobs());
+        assert_eq!(layer.exact_num_weight_blobs(), Some(8));
+        assert_eq!(layer.weight_name(0), Some("query_weight".to_owned()));
+        assert_eq!(layer.weight_name(7), Some("output_bias".to_owned()));
+        assert!(!LayerType::MultiHeadAttention(MultiHeadAttentionConfig::new(4)).supports_in_place());
+    }
+
+    #[test]
+    fn test_configured_filler_overrides_the_default_initialization_of_a_layer() {
+        let backend = Rc::new(Backend::new::<Native<MachLrnPackage>>().unwrap());
+        let mut cfg = SequentialConfig::default();
+        cfg.add_input("data", &[1, 3]);
+        let mut linear = LayerConfig::new("linear", LinearConfig { output_size: 2 });
+        linear.params.push(WeightConfig { filler: Some(FillerType::Constant { value: 0.5f32 }), ..WeightConfig::default() });
+        cfg.add_layer(linear);
+
+        let network = Layer::from_config(backend, &LayerConfig::new("network", cfg));
+        let weights = network.learnable_weights_data();
+        assert_eq!(weights[0].read().unwrap().as_slice().unwrap(), &[0.5f32; 6][..]);
+    }
+
+    #[test]
+    fn test_shared_weight_is_only_initialized_by_its_owner() {
+        let backend = Rc::new(Backend::new::<Native<MachLrnPackage>>().unwrap());
+        let mut cfg = SequentialConfig::default();
+        cfg.add_input("data", &[1, 2]);
+        let mut owner = LayerConfig::new("owner", LinearConfig { output_size: 2 });
+        owner.params.push(WeightConfig {
+            name: "shared".to_owned(),
+            filler: Some(FillerType::Constant { value: 0.5f32 }),
+            ..WeightConfig::default()
+        });
+        cfg.add_layer(owner);
+        let mut sharer = LayerConfig::new("sharer", LinearConfig { output_size: 2 });
+        sharer.params.push(WeightConfig { name: "shared".to_owned(), ..WeightConfig::default() });
+        cfg.add_layer(sharer);
+
+        let network = Layer::from_config(backend, &LayerConfig::new("network", cfg));
+        let weights = network.learnable_weights_data();
+        assert_eq!(weights.len(), 1);
+        assert_eq!(weights[0].read().unwrap().as_slice().unwrap(), &[0.5f32; 4][..]);
+    }
+}